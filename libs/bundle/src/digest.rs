@@ -0,0 +1,111 @@
+use std::io;
+
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio_stream::StreamExt;
+use tokio_tar::Archive;
+
+use crate::validate::{Validator, Violation};
+
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+
+/// What a single pass over a bundle found.
+#[derive(Debug)]
+pub struct Inspection {
+    pub digest: String,
+    /// Entries that must not be unpacked, in archive order.
+    pub violations: Vec<Violation>,
+}
+
+/// Hashes the paths and file contents of `archive` into the bundle digest.
+///
+/// Only entry names and contents count, so repacking the same folder with other
+/// timestamps or ownership gives the same digest.
+pub async fn content_digest<R: AsyncRead + Unpin>(archive: Archive<R>) -> io::Result<String> {
+    Ok(inspect(archive).await?.digest)
+}
+
+/// Computes the digest and validates every entry in one read of the archive.
+pub async fn inspect<R: AsyncRead + Unpin>(mut archive: Archive<R>) -> io::Result<Inspection> {
+    let mut hasher = Sha256::new();
+    let mut validator = Validator::default();
+    let mut violations = vec![];
+    let mut buf = vec![0; READ_BUFFER_SIZE];
+
+    let mut entries = archive.entries()?;
+    while let Some(file) = entries.next().await {
+        let mut entry = file?;
+        if let Some(violation) = validator.check(&entry)? {
+            violations.push(violation);
+        }
+
+        let path = entry.path()?.to_string_lossy().to_string();
+
+        if entry.header().entry_type().is_file() {
+            hasher.update(b"file:"); // prefix to differentiate files/folders
+            hasher.update(path.as_bytes());
+            loop {
+                let n = entry.read(&mut buf).await?;
+                if n == 0 {
+                    break;
+                }
+                hasher.update(&buf[..n]);
+            }
+        } else if entry.header().entry_type().is_dir() {
+            hasher.update(b"dir:"); // prefix for directories
+            hasher.update(path.as_bytes());
+        }
+    }
+
+    Ok(Inspection {
+        digest: format!("{:x}", hasher.finalize()),
+        violations,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio_tar::{Builder, Header};
+
+    async fn archive(files: &[(&str, &[u8], u64)]) -> Vec<u8> {
+        let mut builder = Builder::new(vec![]);
+        for (path, content, mtime) in files {
+            let mut header = Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o755);
+            header.set_mtime(*mtime);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, path, *content)
+                .await
+                .unwrap();
+        }
+        builder.into_inner().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_digest_ignores_metadata() {
+        let a = archive(&[("bootstrap", b"echo hi", 1)]).await;
+        let b = archive(&[("bootstrap", b"echo hi", 2)]).await;
+
+        assert_eq!(
+            content_digest(Archive::new(&a[..])).await.unwrap(),
+            content_digest(Archive::new(&b[..])).await.unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_digest_covers_paths_and_contents() {
+        let base = archive(&[("bootstrap", b"echo hi", 1)]).await;
+        let content = archive(&[("bootstrap", b"echo ho", 1)]).await;
+        let path = archive(&[("run", b"echo hi", 1)]).await;
+
+        let base = content_digest(Archive::new(&base[..])).await.unwrap();
+        assert_ne!(
+            base,
+            content_digest(Archive::new(&content[..])).await.unwrap()
+        );
+        assert_ne!(base, content_digest(Archive::new(&path[..])).await.unwrap());
+    }
+}
@@ -0,0 +1,8 @@
+//! Helpers shared by everything that handles function bundles, the tar archives
+//! pushed to the registry and unpacked by the workers.
+
+mod digest;
+mod validate;
+
+pub use digest::{Inspection, content_digest, inspect};
+pub use validate::Violation;
@@ -0,0 +1,264 @@
+use std::{
+    collections::HashSet,
+    ffi::OsString,
+    fmt, io,
+    path::{Component, Path, PathBuf},
+};
+
+use tokio::io::AsyncRead;
+use tokio_tar::{Entry, EntryType};
+
+/// An archive entry that could write outside the bundle or create something other
+/// than plain files, folders and links.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    pub path: String,
+    pub reason: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.reason)
+    }
+}
+
+/// Checks entries one by one, remembering symlinks so later entries can't be
+/// written or resolved through them.
+///
+/// Link targets are resolved lexically, which only matches the filesystem when no
+/// symlink is on the way. So targets going through a symlink are rejected, and so are
+/// symlinks placed where an earlier target already went through.
+#[derive(Default)]
+pub(crate) struct Validator {
+    symlinks: HashSet<PathBuf>,
+    traversed: HashSet<PathBuf>,
+}
+
+impl Validator {
+    pub(crate) fn check<R: AsyncRead + Unpin>(
+        &mut self,
+        entry: &Entry<R>,
+    ) -> io::Result<Option<Violation>> {
+        let raw_path = entry.path()?;
+        let display = raw_path.to_string_lossy().to_string();
+        let violation = |reason: &str| {
+            Ok(Some(Violation {
+                path: display.clone(),
+                reason: reason.to_string(),
+            }))
+        };
+
+        let path = match normalize(&raw_path) {
+            Ok(path) => path,
+            Err(reason) => return violation(reason),
+        };
+
+        if path
+            .ancestors()
+            .skip(1)
+            .any(|ancestor| self.symlinks.contains(ancestor))
+        {
+            return violation("path goes through a symlink");
+        }
+
+        let entry_type = entry.header().entry_type();
+        match entry_type {
+            EntryType::Regular | EntryType::Directory | EntryType::Continuous => Ok(None),
+            EntryType::Symlink | EntryType::Link => {
+                let Some(target) = entry.link_name()? else {
+                    return violation("link without a target");
+                };
+                // Symlinks resolve from their own folder, hard links from the archive root.
+                let base = match entry_type {
+                    EntryType::Symlink => path.parent().unwrap_or(Path::new("")),
+                    _ => Path::new(""),
+                };
+                let Some(resolved) = resolve(base, &target) else {
+                    return violation(match entry_type {
+                        EntryType::Symlink => "symlink points outside the bundle",
+                        _ => "hard link points outside the bundle",
+                    });
+                };
+                // A hard link to a symlink is a copy of it, resolving from its new place.
+                let through_symlink = resolved
+                    .traversed
+                    .iter()
+                    .any(|dir| self.symlinks.contains(dir))
+                    || (entry_type == EntryType::Link && self.symlinks.contains(&resolved.path));
+                if through_symlink {
+                    return violation("link target goes through a symlink");
+                }
+                if entry_type == EntryType::Symlink {
+                    if self.traversed.contains(&path) {
+                        return violation("symlink is on the way of an earlier link target");
+                    }
+                    self.symlinks.insert(path);
+                }
+                self.traversed.extend(resolved.traversed);
+                Ok(None)
+            }
+            EntryType::Char | EntryType::Block => violation("device node"),
+            EntryType::Fifo => violation("fifo"),
+            _ => violation("unsupported entry type"),
+        }
+    }
+}
+
+/// Entry path relative to the bundle root, with `.` components dropped.
+fn normalize(path: &Path) -> Result<PathBuf, &'static str> {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => normalized.push(part),
+            Component::CurDir => {}
+            Component::ParentDir => return Err("parent directory component"),
+            Component::RootDir | Component::Prefix(_) => return Err("absolute path"),
+        }
+    }
+    Ok(normalized)
+}
+
+struct Resolved {
+    path: PathBuf,
+    /// Folders the lookup descends into, each of which must not be a symlink.
+    traversed: Vec<PathBuf>,
+}
+
+/// Resolves `target` against `base` without touching the filesystem, `None` when it
+/// leaves the bundle.
+fn resolve(base: &Path, target: &Path) -> Option<Resolved> {
+    let mut parts: Vec<OsString> = base.iter().map(OsString::from).collect();
+    let mut traversed = Vec::new();
+    let mut components = target.components().peekable();
+    while let Some(component) = components.next() {
+        match component {
+            Component::Normal(part) => {
+                parts.push(part.to_os_string());
+                if components.peek().is_some() {
+                    traversed.push(parts.iter().collect());
+                }
+            }
+            Component::CurDir => {}
+            Component::ParentDir => {
+                parts.pop()?;
+            }
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(Resolved {
+        path: parts.iter().collect(),
+        traversed,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inspect;
+    use tokio_tar::{Archive, Builder, Header};
+
+    enum Spec {
+        File(&'static str),
+        Dir(&'static str),
+        Symlink(&'static str, &'static str),
+        HardLink(&'static str, &'static str),
+        Device(&'static str),
+    }
+
+    async fn violations(specs: &[Spec]) -> Vec<String> {
+        let mut builder = Builder::new(vec![]);
+        for spec in specs {
+            let mut header = Header::new_gnu();
+            header.set_mode(0o755);
+            let (path, entry_type, link) = match spec {
+                Spec::File(p) => (*p, EntryType::Regular, None),
+                Spec::Dir(p) => (*p, EntryType::Directory, None),
+                Spec::Symlink(p, t) => (*p, EntryType::Symlink, Some(*t)),
+                Spec::HardLink(p, t) => (*p, EntryType::Link, Some(*t)),
+                Spec::Device(p) => (*p, EntryType::Char, None),
+            };
+            header.set_entry_type(entry_type);
+            header.set_size(0);
+            // Raw names so the builder doesn't reject the hostile ones itself.
+            let name = &mut header.as_old_mut().name;
+            name[..path.len()].copy_from_slice(path.as_bytes());
+            if let Some(link) = link {
+                header.set_link_name(link).unwrap();
+            }
+            header.set_cksum();
+            builder.append(&header, &[][..]).await.unwrap();
+        }
+        let data = builder.into_inner().await.unwrap();
+
+        inspect(Archive::new(&data[..]))
+            .await
+            .unwrap()
+            .violations
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_accepts_regular_bundle() {
+        let found = violations(&[
+            Spec::Dir("./"),
+            Spec::File("./bootstrap"),
+            Spec::Dir("./lib"),
+            Spec::Symlink("./lib/current", "../bootstrap"),
+            Spec::HardLink("./copy", "./bootstrap"),
+        ])
+        .await;
+        assert!(found.is_empty(), "{:?}", found);
+    }
+
+    #[tokio::test]
+    async fn test_rejects_escaping_entries() {
+        let found = violations(&[
+            Spec::File("/etc/passwd"),
+            Spec::File("./../outside"),
+            Spec::Device("./null"),
+            Spec::Symlink("./etc", "/etc"),
+            Spec::Symlink("./up", "../.."),
+            Spec::HardLink("./shadow", "../etc/shadow"),
+            Spec::Symlink("./dir", "lib"),
+            Spec::File("./dir/through"),
+        ])
+        .await;
+        assert_eq!(
+            found,
+            vec![
+                "/etc/passwd: absolute path",
+                "./../outside: parent directory component",
+                "./null: device node",
+                "./etc: symlink points outside the bundle",
+                "./up: symlink points outside the bundle",
+                "./shadow: hard link points outside the bundle",
+                "./dir/through: path goes through a symlink",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rejects_link_targets_through_symlinks() {
+        let found = violations(&[
+            Spec::Dir("./sub"),
+            Spec::Symlink("./sub/s", ".."),
+            Spec::Symlink("./a", "sub/s/../.."),
+            Spec::HardLink("./b", "sub/s"),
+            // The same chain with the links in the other order.
+            Spec::Symlink("./c", "other/t/../.."),
+            Spec::Dir("./other"),
+            Spec::Symlink("./other/t", ".."),
+        ])
+        .await;
+        assert_eq!(
+            found,
+            vec![
+                "./a: link target goes through a symlink",
+                "./b: link target goes through a symlink",
+                "./other/t: symlink is on the way of an earlier link target",
+            ]
+        );
+    }
+}
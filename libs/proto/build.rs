@@ -1,37 +1,87 @@
 use std::{
     error::Error,
-    fs,
     path::{Path, PathBuf},
 };
 
+const PROTOS: &[(&str, &str)] = &[
+    ("controlplane", "controlplane.proto"),
+    ("registry", "registry.proto"),
+    ("worker", "worker.proto"),
+    ("function", "action.proto"),
+];
+
 fn main() -> Result<(), Box<dyn Error>> {
-    let out_dir = std::env::var("OUT_DIR")?;
-    let api_dir = Path::new(&out_dir).join("api");
-
-    tonic_prost_build::configure().compile_protos(
-        &[
-            get_proto_file("controlplane", "controlplane.proto", &api_dir)?,
-            get_proto_file("registry", "registry.proto", &api_dir)?,
-            get_proto_file("worker", "worker.proto", &api_dir)?,
-            get_proto_file("function", "action.proto", &api_dir)?,
-        ],
-        &[api_dir],
-    )?;
+    let proto_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("proto");
+    println!("cargo:rerun-if-changed={}", proto_dir.display());
+
+    #[cfg(feature = "sync-protos")]
+    sync::check_upstream(&proto_dir)?;
+
+    let protos = PROTOS
+        .iter()
+        .map(|(service, proto)| get_proto_file(service, proto, &proto_dir))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let includes: Vec<PathBuf> = protos
+        .iter()
+        .filter_map(|p| p.parent().map(Path::to_path_buf))
+        .collect();
+
+    tonic_prost_build::configure().compile_protos(&protos, &includes)?;
 
     Ok(())
 }
 
-fn get_proto_file(service: &str, proto: &str, api_dir: &Path) -> Result<PathBuf, Box<dyn Error>> {
-    fs::create_dir_all(api_dir)?;
-
-    let proto_path = api_dir.join(proto);
-    let proto_url = format!(
-        "https://raw.githubusercontent.com/ow1lab/NoctiForge-Proto/refs/heads/main/{}/v0/{}",
-        service, proto
-    );
+fn get_proto_file(service: &str, proto: &str, proto_dir: &Path) -> Result<PathBuf, Box<dyn Error>> {
+    let proto_path = proto_dir.join(service).join("v0").join(proto);
 
-    let response = reqwest::blocking::get(proto_url)?.text()?;
-    fs::write(&proto_path, response)?;
+    if !proto_path.exists() {
+        return Err(format!("missing vendored proto file: {}", proto_path.display()).into());
+    }
 
     Ok(proto_path)
 }
+
+#[cfg(feature = "sync-protos")]
+mod sync {
+    use std::{error::Error, fs, path::Path};
+
+    use super::PROTOS;
+
+    const UPSTREAM: &str =
+        "https://raw.githubusercontent.com/ow1lab/NoctiForge-Proto/refs/heads/main";
+
+    /// Fetches every proto from upstream and overwrites the vendored copy. The build fails if
+    /// anything changed so the drift gets reviewed and committed instead of silently compiled.
+    pub fn check_upstream(proto_dir: &Path) -> Result<(), Box<dyn Error>> {
+        let mut drifted = vec![];
+
+        for (service, proto) in PROTOS {
+            let proto_path = proto_dir.join(service).join("v0").join(proto);
+            let proto_url = format!("{}/{}/v0/{}", UPSTREAM, service, proto);
+
+            let upstream = reqwest::blocking::get(proto_url)?
+                .error_for_status()?
+                .text()?;
+            let vendored = fs::read_to_string(&proto_path).unwrap_or_default();
+
+            if upstream != vendored {
+                if let Some(parent) = proto_path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::write(&proto_path, upstream)?;
+                drifted.push(proto_path.display().to_string());
+            }
+        }
+
+        if !drifted.is_empty() {
+            return Err(format!(
+                "vendored protos drifted from upstream and have been re-synced, review and commit: {}",
+                drifted.join(", ")
+            )
+            .into());
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,106 @@
+use std::{fmt, net::IpAddr, str::FromStr};
+
+/// One `NetworkConfig.egress_allowlist` entry, an address or network with an optional
+/// port. Parsed by the control plane when the config is set and by workers when they
+/// build the instance's firewall, so both agree on what is valid.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EgressRule {
+    pub addr: IpAddr,
+    pub prefix: u8,
+    pub port: Option<u16>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidEgressRule(pub String);
+
+impl fmt::Display for InvalidEgressRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Invalid egress allowlist entry, expected address[/prefix][:port]: {}",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for InvalidEgressRule {}
+
+impl FromStr for EgressRule {
+    type Err = InvalidEgressRule;
+
+    /// Parses `address[/prefix][:port]`, IPv6 addresses need brackets when a port is given.
+    fn from_str(entry: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidEgressRule(entry.to_string());
+
+        let (network, port) = match entry.strip_prefix('[') {
+            Some(rest) => {
+                let (network, rest) = rest.split_once(']').ok_or_else(invalid)?;
+                let port = match rest {
+                    "" => None,
+                    rest => Some(rest.strip_prefix(':').ok_or_else(invalid)?),
+                };
+                (network, port)
+            }
+            // A bare IPv6 address has more than one colon and no port.
+            None if entry.matches(':').count() > 1 => (entry, None),
+            None => match entry.split_once(':') {
+                Some((network, port)) => (network, Some(port)),
+                None => (entry, None),
+            },
+        };
+
+        let (addr, prefix) = match network.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (network, None),
+        };
+        let addr: IpAddr = addr.parse().map_err(|_| invalid())?;
+        let max_prefix = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .parse()
+                .ok()
+                .filter(|p| *p <= max_prefix)
+                .ok_or_else(invalid)?,
+            None => max_prefix,
+        };
+        let port = port
+            .map(|port| port.parse().ok().filter(|p| *p > 0).ok_or_else(invalid))
+            .transpose()?;
+
+        Ok(EgressRule { addr, prefix, port })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(addr: &str, prefix: u8, port: Option<u16>) -> EgressRule {
+        EgressRule {
+            addr: addr.parse().unwrap(),
+            prefix,
+            port,
+        }
+    }
+
+    #[test]
+    fn test_parse_rule() {
+        assert_eq!("1.2.3.4".parse(), Ok(rule("1.2.3.4", 32, None)));
+        assert_eq!("10.0.0.0/8:443".parse(), Ok(rule("10.0.0.0", 8, Some(443))));
+        assert_eq!("2001:db8::/32".parse(), Ok(rule("2001:db8::", 32, None)));
+        assert_eq!(
+            "[2001:db8::1]:53".parse(),
+            Ok(rule("2001:db8::1", 128, Some(53)))
+        );
+
+        for invalid in [
+            "example.com",
+            "10.0.0.0/33",
+            "1.2.3.4:0",
+            "[::1]53",
+            "::1:x",
+        ] {
+            assert!(invalid.parse::<EgressRule>().is_err(), "{}", invalid);
+        }
+    }
+}
@@ -1,3 +1,7 @@
+pub mod egress;
+
+// Generated tonic code trips lints we don't control.
+#[allow(clippy::double_must_use)]
 pub mod api {
     pub mod action {
         tonic::include_proto!("noctiforge.action");
@@ -0,0 +1,148 @@
+use std::collections::HashMap;
+
+use opentelemetry::{
+    global,
+    propagation::{Extractor, Injector},
+};
+use tonic::{
+    Request, Status,
+    codegen::http::{self, HeaderMap},
+    metadata::{MetadataKey, MetadataMap, MetadataValue},
+    service::{Interceptor, interceptor::InterceptedService},
+    transport::{Channel, Endpoint, Error},
+};
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Adds the current span's W3C `traceparent` to outgoing requests, so the server
+/// continues the caller's trace.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TraceContext;
+
+impl Interceptor for TraceContext {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        let context = Span::current().context();
+        global::get_text_map_propagator(|propagator| {
+            propagator.inject_context(&context, &mut MetadataInjector(request.metadata_mut()))
+        });
+        Ok(request)
+    }
+}
+
+pub type TracedChannel = InterceptedService<Channel, TraceContext>;
+
+/// Connects like the generated clients' `connect`, with every request carrying the
+/// trace context. Takes `unix:` addresses as well.
+pub async fn connect(addr: String) -> Result<TracedChannel, Error> {
+    let channel = Endpoint::from_shared(addr)?.connect().await?;
+    Ok(InterceptedService::new(channel, TraceContext))
+}
+
+/// The span a server handles a request in, for tonic's `Server::trace_fn`. It continues
+/// the caller's trace when the request carries one.
+pub fn server_span(request: &http::Request<()>) -> Span {
+    let parent = global::get_text_map_propagator(|propagator| {
+        propagator.extract(&HeaderExtractor(request.headers()))
+    });
+    let span = tracing::info_span!(
+        "grpc_request",
+        otel.name = %request.uri().path(),
+        otel.kind = "server"
+    );
+    // Only fails when the span is disabled, and then there is nothing to continue.
+    let _ = span.set_parent(parent);
+    span
+}
+
+/// Adds the current trace context to `metadata` as `traceparent` and `tracestate`,
+/// for handlers to continue the trace in.
+pub fn inject(metadata: &mut HashMap<String, String>) {
+    let context = Span::current().context();
+    global::get_text_map_propagator(|propagator| propagator.inject_context(&context, metadata));
+}
+
+struct MetadataInjector<'a>(&'a mut MetadataMap);
+
+impl Injector for MetadataInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(key), Ok(value)) = (
+            MetadataKey::from_bytes(key.as_bytes()),
+            MetadataValue::try_from(value),
+        ) {
+            self.0.insert(key, value);
+        }
+    }
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|key| key.as_str()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use opentelemetry::trace::{TraceContextExt, TraceId, TracerProvider};
+    use opentelemetry_sdk::{propagation::TraceContextPropagator, trace::SdkTracerProvider};
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::*;
+
+    /// Runs `f` with spans recorded as OpenTelemetry spans, like the services do.
+    fn traced<T>(f: impl FnOnce() -> T) -> T {
+        global::set_text_map_propagator(TraceContextPropagator::new());
+        let provider = SdkTracerProvider::builder().build();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+        tracing::subscriber::with_default(subscriber, f)
+    }
+
+    fn trace_id(span: &Span) -> TraceId {
+        span.context().span().span_context().trace_id()
+    }
+
+    #[test]
+    fn test_server_span_continues_the_callers_trace() {
+        traced(|| {
+            let caller = tracing::info_span!("caller");
+            let request = caller
+                .in_scope(|| TraceContext.call(Request::new(())))
+                .unwrap();
+            assert!(request.metadata().contains_key("traceparent"));
+
+            let mut http_request = http::Request::new(());
+            *http_request.headers_mut() = request.metadata().clone().into_headers();
+            let server = server_span(&http_request);
+
+            assert_ne!(trace_id(&caller), TraceId::INVALID);
+            assert_eq!(trace_id(&server), trace_id(&caller));
+        });
+    }
+
+    #[test]
+    fn test_server_span_without_caller_starts_a_trace() {
+        traced(|| {
+            let server = server_span(&http::Request::new(()));
+            assert_ne!(trace_id(&server), TraceId::INVALID);
+        });
+    }
+
+    #[test]
+    fn test_inject_adds_traceparent_to_metadata() {
+        traced(|| {
+            let span = tracing::info_span!("invoke");
+            let mut metadata = HashMap::from([("routing-key".to_string(), "user".to_string())]);
+            span.in_scope(|| inject(&mut metadata));
+
+            let traceparent = &metadata["traceparent"];
+            assert!(traceparent.starts_with(&format!("00-{}-", trace_id(&span))));
+            assert_eq!(metadata["routing-key"], "user");
+        });
+    }
+}
@@ -0,0 +1,10 @@
+//! Observability shared by the services: Prometheus metrics served on each service's
+//! `METRICS_ADDR`, and traces that follow a request across the gRPC hops between them.
+
+mod context;
+mod prometheus;
+mod trace;
+
+pub use context::{TraceContext, TracedChannel, connect, inject, server_span};
+pub use prometheus::{BuildError, builder, serve_metrics};
+pub use trace::{ExporterBuildError, Tracing, init};
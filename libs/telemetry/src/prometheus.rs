@@ -0,0 +1,22 @@
+use std::net::SocketAddr;
+
+pub use metrics_exporter_prometheus::BuildError;
+use metrics_exporter_prometheus::PrometheusBuilder;
+
+/// Bucket bounds in seconds, every histogram the services record is a duration.
+const DURATION_BUCKETS: &[f64] = &[
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0,
+];
+
+/// The exporter configuration, without anything installed or listening yet.
+pub fn builder() -> PrometheusBuilder {
+    PrometheusBuilder::new()
+        .set_buckets(DURATION_BUCKETS)
+        .expect("duration buckets are not empty")
+}
+
+/// Installs the global metrics recorder and serves it over HTTP on `addr`, at `/metrics`
+/// or any other path. Must be called from within the Tokio runtime.
+pub fn serve_metrics(addr: SocketAddr) -> Result<(), BuildError> {
+    builder().with_http_listener(addr).install()
+}
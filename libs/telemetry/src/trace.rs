@@ -0,0 +1,138 @@
+use opentelemetry::{global, trace::TracerProvider};
+pub use opentelemetry_otlp::ExporterBuildError;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{Resource, propagation::TraceContextPropagator, trace::SdkTracerProvider};
+use tracing_subscriber::{EnvFilter, layer::SubscriberExt, util::SubscriberInitExt};
+
+/// The standard OpenTelemetry variable, spans are only exported when it is set.
+const OTLP_ENDPOINT: &str = "OTEL_EXPORTER_OTLP_ENDPOINT";
+
+/// Flushes the spans that were not exported yet when dropped, so it should live until
+/// the service exits.
+pub struct Tracing {
+    provider: SdkTracerProvider,
+}
+
+impl Drop for Tracing {
+    fn drop(&mut self) {
+        if let Err(err) = self.provider.shutdown() {
+            eprintln!("Failed to flush spans: {}", err);
+        }
+    }
+}
+
+/// Logs filtered by `RUST_LOG` (`info` by default) and records spans as OpenTelemetry
+/// traces, exported over OTLP/gRPC when `OTEL_EXPORTER_OTLP_ENDPOINT` is set.
+///
+/// Traces are continued across services through W3C `traceparent` headers either way.
+/// Must be called from within the Tokio runtime.
+pub fn init(service_name: &'static str) -> Result<Tracing, ExporterBuildError> {
+    let endpoint = std::env::var(OTLP_ENDPOINT)
+        .ok()
+        .filter(|endpoint| !endpoint.is_empty());
+    let provider = tracer_provider(service_name, endpoint)?;
+
+    global::set_text_map_propagator(TraceContextPropagator::new());
+    tracing_subscriber::registry()
+        .with(EnvFilter::try_from_default_env().unwrap_or_else(|_| "info".into()))
+        .with(tracing_subscriber::fmt::layer().with_target(false))
+        .with(tracing_opentelemetry::layer().with_tracer(provider.tracer(service_name)))
+        .init();
+
+    Ok(Tracing { provider })
+}
+
+/// Without an endpoint spans are still recorded, which keeps trace ids flowing to the
+/// services and handlers downstream.
+fn tracer_provider(
+    service_name: &'static str,
+    endpoint: Option<String>,
+) -> Result<SdkTracerProvider, ExporterBuildError> {
+    let mut builder = SdkTracerProvider::builder()
+        .with_resource(Resource::builder().with_service_name(service_name).build());
+    if let Some(endpoint) = endpoint {
+        let exporter = SpanExporter::builder()
+            .with_tonic()
+            .with_endpoint(endpoint)
+            .build()?;
+        builder = builder.with_batch_exporter(exporter);
+    }
+    Ok(builder.build())
+}
+
+#[cfg(test)]
+mod tests {
+    use opentelemetry::trace::{TraceContextExt, Tracer};
+    use opentelemetry_proto::tonic::{
+        collector::trace::v1::{
+            ExportTraceServiceRequest, ExportTraceServiceResponse,
+            trace_service_server::{TraceService, TraceServiceServer},
+        },
+        common::v1::any_value::Value,
+    };
+    use tokio::{net::TcpListener, sync::mpsc};
+    use tokio_stream::wrappers::TcpListenerStream;
+    use tonic::{Request, Response, Status, transport::Server};
+
+    use super::*;
+
+    /// Stands in for an OpenTelemetry collector, handing over what it receives.
+    struct Collector(mpsc::UnboundedSender<ExportTraceServiceRequest>);
+
+    #[tonic::async_trait]
+    impl TraceService for Collector {
+        async fn export(
+            &self,
+            request: Request<ExportTraceServiceRequest>,
+        ) -> Result<Response<ExportTraceServiceResponse>, Status> {
+            let _ = self.0.send(request.into_inner());
+            Ok(Response::new(ExportTraceServiceResponse::default()))
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_spans_are_exported_to_the_collector() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (sender, mut received) = mpsc::unbounded_channel();
+        tokio::spawn(
+            Server::builder()
+                .add_service(TraceServiceServer::new(Collector(sender)))
+                .serve_with_incoming(TcpListenerStream::new(listener)),
+        );
+
+        let provider = tracer_provider("test-service", Some(format!("http://{}", addr))).unwrap();
+        provider.tracer("test").in_span("exported", |_| {});
+        // The batch processor blocks until the export is done.
+        let flushing = provider.clone();
+        tokio::task::spawn_blocking(move || flushing.force_flush())
+            .await
+            .unwrap()
+            .unwrap();
+
+        let request = received.recv().await.unwrap();
+        let resource_spans = &request.resource_spans[0];
+        let service_name = resource_spans
+            .resource
+            .as_ref()
+            .unwrap()
+            .attributes
+            .iter()
+            .find(|attribute| attribute.key == "service.name")
+            .and_then(|attribute| attribute.value.as_ref()?.value.clone());
+        assert_eq!(
+            service_name,
+            Some(Value::StringValue("test-service".to_string()))
+        );
+        assert_eq!(resource_spans.scope_spans[0].spans[0].name, "exported");
+    }
+
+    #[test]
+    fn test_spans_are_recorded_without_an_endpoint() {
+        let provider = tracer_provider("test-service", None).unwrap();
+        let span_context = provider
+            .tracer("test")
+            .in_span("recorded", |cx| cx.span().span_context().clone());
+        assert!(span_context.is_valid());
+    }
+}
@@ -0,0 +1,73 @@
+use std::path::Path;
+
+use anyhow::{Result, bail};
+use tokio_tar::Builder;
+
+/// The worker starts every function by running this file from the bundle root.
+pub const BOOTSTRAP_FILE: &str = "bootstrap";
+
+pub fn is_function_dir(dir: &Path) -> bool {
+    dir.join(BOOTSTRAP_FILE).is_file()
+}
+
+/// Packs the contents of `dir` into an in-memory tar with paths relative to the bundle root.
+pub async fn pack_dir(dir: &Path) -> Result<Vec<u8>> {
+    if !is_function_dir(dir) {
+        bail!(
+            "{} is not a function folder: missing `{}`",
+            dir.display(),
+            BOOTSTRAP_FILE
+        );
+    }
+
+    let mut builder = Builder::new(Vec::new());
+    builder.append_dir_all(".", dir).await?;
+    Ok(builder.into_inner().await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use tempfile::TempDir;
+    use tokio::fs;
+    use tokio_stream::StreamExt;
+    use tokio_tar::Archive;
+
+    #[tokio::test]
+    async fn test_pack_dir_contains_relative_paths() {
+        let temp = TempDir::new().unwrap();
+        fs::write(temp.path().join(BOOTSTRAP_FILE), b"#!/bin/sh")
+            .await
+            .unwrap();
+        fs::create_dir_all(temp.path().join("lib")).await.unwrap();
+        fs::write(temp.path().join("lib/data.txt"), b"data")
+            .await
+            .unwrap();
+
+        let data = pack_dir(temp.path()).await.unwrap();
+
+        let mut archive = Archive::new(Cursor::new(data));
+        let mut entries = archive.entries().unwrap();
+        let mut paths = vec![];
+        while let Some(entry) = entries.next().await {
+            let entry = entry.unwrap();
+            let path = entry.path().unwrap().to_path_buf();
+            assert!(path.is_relative());
+            paths.push(path.to_string_lossy().trim_start_matches("./").to_string());
+        }
+
+        assert!(paths.contains(&BOOTSTRAP_FILE.to_string()));
+        assert!(paths.contains(&"lib/data.txt".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_pack_dir_requires_bootstrap() {
+        let temp = TempDir::new().unwrap();
+        fs::write(temp.path().join("main.rs"), b"fn main() {}")
+            .await
+            .unwrap();
+
+        assert!(pack_dir(temp.path()).await.is_err());
+    }
+}
@@ -0,0 +1,510 @@
+use anyhow::{Context, Result, bail};
+use clap::{Args, ValueEnum};
+use proto::api::controlplane::{
+    AliasRoute, DeleteFunctionRequest, FunctionConfig, GetFunctionConfigRequest,
+    ListFunctionsRequest, ListVersionsRequest, NetworkMode, RollbackRequest, SetAliasRequest,
+    SetFunctionConfigRequest, control_plane_service_client::ControlPlaneServiceClient,
+};
+use tonic::Request;
+
+use crate::config::ClientConfig;
+
+/// Prints every function known to the control plane, following all pages.
+pub async fn list(config: &ClientConfig, prefix: Option<String>) -> Result<()> {
+    let mut client = ControlPlaneServiceClient::connect(config.controlplane_addr.clone())
+        .await
+        .context("failed to connect to control plane")?;
+
+    let prefix = prefix.unwrap_or_default();
+    let mut page_token = String::new();
+    let mut functions = vec![];
+    loop {
+        let response = client
+            .list_functions(Request::new(ListFunctionsRequest {
+                prefix: prefix.clone(),
+                page_size: 0,
+                page_token,
+            }))
+            .await
+            .context("failed to list functions")?
+            .into_inner();
+
+        functions.extend(response.functions);
+        if response.next_page_token.is_empty() {
+            break;
+        }
+        page_token = response.next_page_token;
+    }
+
+    let width = functions
+        .iter()
+        .map(|f| f.name.len())
+        .max()
+        .unwrap_or(0)
+        .max("NAME".len());
+
+    println!("{:<width$}  VERSION  {:<64}  UPDATED", "NAME", "DIGEST");
+    for function in functions {
+        println!(
+            "{:<width$}  {:<7}  {:<64}  {}",
+            function.name, function.version, function.digest, function.updated_at
+        );
+    }
+
+    Ok(())
+}
+
+pub async fn delete(config: &ClientConfig, name: &str) -> Result<()> {
+    let mut client = ControlPlaneServiceClient::connect(config.controlplane_addr.clone())
+        .await
+        .context("failed to connect to control plane")?;
+
+    client
+        .delete_function(Request::new(DeleteFunctionRequest {
+            name: name.to_string(),
+        }))
+        .await
+        .context("failed to delete function")?;
+
+    println!("deleted {}", name);
+    Ok(())
+}
+
+pub async fn versions(config: &ClientConfig, name: &str) -> Result<()> {
+    let mut client = ControlPlaneServiceClient::connect(config.controlplane_addr.clone())
+        .await
+        .context("failed to connect to control plane")?;
+
+    let response = client
+        .list_versions(Request::new(ListVersionsRequest {
+            name: name.to_string(),
+        }))
+        .await
+        .context("failed to list versions")?
+        .into_inner();
+
+    println!("VERSION  {:<64}  CREATED", "DIGEST");
+    for version in response.versions {
+        println!(
+            "{:<7}  {:<64}  {}",
+            version.version, version.digest, version.created_at
+        );
+    }
+
+    Ok(())
+}
+
+pub async fn rollback(config: &ClientConfig, name: &str, version: u64) -> Result<()> {
+    let mut client = ControlPlaneServiceClient::connect(config.controlplane_addr.clone())
+        .await
+        .context("failed to connect to control plane")?;
+
+    client
+        .rollback(Request::new(RollbackRequest {
+            name: name.to_string(),
+            version,
+        }))
+        .await
+        .context("failed to rollback function")?;
+
+    println!("{} -> version {}", name, version);
+    Ok(())
+}
+
+/// Points `name:alias` at `targets`, each either `version` or `version=weight`.
+pub async fn alias(
+    config: &ClientConfig,
+    name: &str,
+    alias: &str,
+    targets: &[String],
+) -> Result<()> {
+    let routes = targets
+        .iter()
+        .map(|target| parse_route(target))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut client = ControlPlaneServiceClient::connect(config.controlplane_addr.clone())
+        .await
+        .context("failed to connect to control plane")?;
+
+    client
+        .set_alias(Request::new(SetAliasRequest {
+            name: name.to_string(),
+            alias: alias.to_string(),
+            version: 0,
+            routes: routes.clone(),
+        }))
+        .await
+        .context("failed to set alias")?;
+
+    let routes: Vec<String> = routes
+        .iter()
+        .map(|r| format!("{} ({})", r.version, r.weight))
+        .collect();
+    println!("{}:{} -> version {}", name, alias, routes.join(", "));
+    Ok(())
+}
+
+/// Settings to change on a function config, unset ones are left as they are.
+#[derive(Args)]
+pub struct ConfigChanges {
+    /// Instances kept running at all times, 0 to let the function scale to zero
+    #[arg(long)]
+    pub provisioned_concurrency: Option<u32>,
+    /// Longest a single invocation may run, 0 for the worker default
+    #[arg(long)]
+    pub timeout_ms: Option<u32>,
+    /// Memory limit of each instance, 0 for unlimited
+    #[arg(long)]
+    pub memory_mb: Option<u64>,
+    /// CPU quota of each instance, 1000 is one full core, 0 for unlimited
+    #[arg(long)]
+    pub cpu_millis: Option<u32>,
+    /// CPU weight against other functions, 0 for the default
+    #[arg(long)]
+    pub cpu_shares: Option<u64>,
+    /// Processes and threads each instance may run, 0 for unlimited
+    #[arg(long)]
+    pub pids_limit: Option<u32>,
+    /// Block IO weight between 10 and 1000, 0 for the default
+    #[arg(long)]
+    pub io_weight: Option<u32>,
+    /// Size of the writable `/tmp` of each instance, 0 for the worker default
+    #[arg(long)]
+    pub tmp_size_mb: Option<u32>,
+    /// Environment variable of each instance as `KEY=VALUE`, can be repeated
+    #[arg(long = "env", value_name = "KEY=VALUE", value_parser = parse_env_var)]
+    pub env: Vec<(String, String)>,
+    /// Environment variable to remove, can be repeated
+    #[arg(long = "unset-env", value_name = "KEY")]
+    pub unset_env: Vec<String>,
+    /// Network access of each instance
+    #[arg(long, value_enum)]
+    pub network: Option<Network>,
+    /// Destination the egress network mode may reach, `address[/prefix][:port]`,
+    /// replaces the current allowlist and can be repeated
+    #[arg(long = "egress-allow", value_name = "DESTINATION")]
+    pub egress_allowlist: Option<Vec<String>>,
+    /// Run without the seccomp syscall allowlist
+    #[arg(long)]
+    pub seccomp_unconfined: Option<bool>,
+    /// Syscall allowed on top of the default allowlist, replaces the current ones and
+    /// can be repeated
+    #[arg(long = "allow-syscall", value_name = "SYSCALL")]
+    pub extra_syscalls: Option<Vec<String>>,
+    /// Capability kept by the handler, e.g. `CAP_NET_BIND_SERVICE`, replaces the current
+    /// ones and can be repeated
+    #[arg(long = "keep-capability", value_name = "CAPABILITY")]
+    pub capabilities: Option<Vec<String>>,
+    /// Let the handler gain privileges through setuid binaries and file capabilities
+    #[arg(long)]
+    pub allow_new_privileges: Option<bool>,
+    /// Leave host paths under `/proc` unmasked and writable
+    #[arg(long)]
+    pub unmask_proc: Option<bool>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Network {
+    /// Share the worker's network
+    Host,
+    /// Loopback only
+    None,
+    /// Only the destinations on the egress allowlist
+    Egress,
+}
+
+impl From<Network> for NetworkMode {
+    fn from(network: Network) -> Self {
+        match network {
+            Network::Host => NetworkMode::Host,
+            Network::None => NetworkMode::None,
+            Network::Egress => NetworkMode::Egress,
+        }
+    }
+}
+
+impl ConfigChanges {
+    fn apply(&self, config: &mut FunctionConfig) {
+        fn set<T: Copy>(field: &mut T, value: Option<T>) {
+            if let Some(value) = value {
+                *field = value;
+            }
+        }
+
+        set(
+            &mut config.provisioned_concurrency,
+            self.provisioned_concurrency,
+        );
+        set(&mut config.timeout_ms, self.timeout_ms);
+        set(&mut config.tmp_size_mb, self.tmp_size_mb);
+        for key in &self.unset_env {
+            config.env.remove(key);
+        }
+        config.env.extend(self.env.iter().cloned());
+
+        if self.memory_mb.is_some()
+            || self.cpu_millis.is_some()
+            || self.cpu_shares.is_some()
+            || self.pids_limit.is_some()
+            || self.io_weight.is_some()
+        {
+            let limits = config.limits.get_or_insert_default();
+            set(&mut limits.memory_mb, self.memory_mb);
+            set(&mut limits.cpu_millis, self.cpu_millis);
+            set(&mut limits.cpu_shares, self.cpu_shares);
+            set(&mut limits.pids_limit, self.pids_limit);
+            set(&mut limits.io_weight, self.io_weight);
+        }
+
+        if self.network.is_some() || self.egress_allowlist.is_some() {
+            let network = config.network.get_or_insert_default();
+            if let Some(mode) = self.network {
+                network.set_mode(mode.into());
+            }
+            if let Some(allowlist) = &self.egress_allowlist {
+                network.egress_allowlist = allowlist.clone();
+            }
+        }
+
+        if self.seccomp_unconfined.is_some()
+            || self.extra_syscalls.is_some()
+            || self.capabilities.is_some()
+            || self.allow_new_privileges.is_some()
+            || self.unmask_proc.is_some()
+        {
+            let security = config.security.get_or_insert_default();
+            set(&mut security.seccomp_unconfined, self.seccomp_unconfined);
+            set(
+                &mut security.allow_new_privileges,
+                self.allow_new_privileges,
+            );
+            set(&mut security.unmask_proc, self.unmask_proc);
+            if let Some(syscalls) = &self.extra_syscalls {
+                security.extra_syscalls = syscalls.clone();
+            }
+            if let Some(capabilities) = &self.capabilities {
+                security.capabilities = capabilities.clone();
+            }
+        }
+    }
+}
+
+/// Prints the config of `name`, after applying `changes` when there are any.
+pub async fn config(config: &ClientConfig, name: &str, changes: &ConfigChanges) -> Result<()> {
+    let mut client = ControlPlaneServiceClient::connect(config.controlplane_addr.clone())
+        .await
+        .context("failed to connect to control plane")?;
+
+    let current = client
+        .get_function_config(Request::new(GetFunctionConfigRequest {
+            name: name.to_string(),
+        }))
+        .await
+        .context("failed to get function config")?
+        .into_inner()
+        .config
+        .unwrap_or_default();
+
+    let mut function_config = current.clone();
+    changes.apply(&mut function_config);
+    if function_config != current {
+        client
+            .set_function_config(Request::new(SetFunctionConfigRequest {
+                name: name.to_string(),
+                config: Some(function_config.clone()),
+            }))
+            .await
+            .context("failed to set function config")?;
+    }
+
+    print_config(&function_config);
+    Ok(())
+}
+
+fn print_config(config: &FunctionConfig) {
+    let or = |value: u64, unset: &str, unit: &str| match value {
+        0 => unset.to_string(),
+        value => format!("{}{}", value, unit),
+    };
+    let limits = config.limits.unwrap_or_default();
+    let network = config.network.clone().unwrap_or_default();
+    let security = config.security.clone().unwrap_or_default();
+    let list = |items: &[String]| match items {
+        [] => "none".to_string(),
+        items => items.join(", "),
+    };
+    let flag = |enabled: bool| if enabled { "yes" } else { "no" }.to_string();
+    let mut env = config
+        .env
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect::<Vec<_>>();
+    env.sort();
+
+    for (setting, value) in [
+        (
+            "provisioned concurrency",
+            config.provisioned_concurrency.to_string(),
+        ),
+        (
+            "timeout",
+            or(config.timeout_ms.into(), "worker default", "ms"),
+        ),
+        ("memory", or(limits.memory_mb, "unlimited", "MB")),
+        ("cpu", or(limits.cpu_millis.into(), "unlimited", "m")),
+        ("cpu shares", or(limits.cpu_shares, "default", "")),
+        ("pids", or(limits.pids_limit.into(), "unlimited", "")),
+        ("io weight", or(limits.io_weight.into(), "default", "")),
+        (
+            "tmp size",
+            or(config.tmp_size_mb.into(), "worker default", "MB"),
+        ),
+        (
+            "network",
+            match network.mode() {
+                NetworkMode::Host => "host",
+                NetworkMode::None => "none",
+                NetworkMode::Egress => "egress",
+            }
+            .to_string(),
+        ),
+        ("egress allowlist", list(&network.egress_allowlist)),
+        ("seccomp unconfined", flag(security.seccomp_unconfined)),
+        ("extra syscalls", list(&security.extra_syscalls)),
+        ("capabilities", list(&security.capabilities)),
+        ("allow new privileges", flag(security.allow_new_privileges)),
+        ("unmask proc", flag(security.unmask_proc)),
+        ("env", list(&env)),
+    ] {
+        println!("{:<25}{}", setting, value);
+    }
+}
+
+fn parse_env_var(var: &str) -> Result<(String, String)> {
+    match var.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => bail!(
+            "invalid environment variable `{}`, expected `KEY=VALUE`",
+            var
+        ),
+    }
+}
+
+fn parse_route(target: &str) -> Result<AliasRoute> {
+    let (version, weight) = target.split_once('=').unwrap_or((target, "1"));
+    match (version.parse(), weight.parse()) {
+        (Ok(version), Ok(weight)) => Ok(AliasRoute { version, weight }),
+        _ => bail!(
+            "invalid alias target `{}`, expected `version` or `version=weight`",
+            target
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[derive(Parser)]
+    struct Config {
+        #[command(flatten)]
+        changes: ConfigChanges,
+    }
+
+    fn changes(args: &[&str]) -> ConfigChanges {
+        Config::parse_from(["config"].iter().chain(args)).changes
+    }
+
+    #[test]
+    fn test_parse_route() {
+        let route = parse_route("3").unwrap();
+        assert_eq!((route.version, route.weight), (3, 1));
+
+        let route = parse_route("4=10").unwrap();
+        assert_eq!((route.version, route.weight), (4, 10));
+
+        assert!(parse_route("a").is_err());
+        assert!(parse_route("3=").is_err());
+    }
+
+    #[test]
+    fn test_config_changes_keep_unset_settings() {
+        let mut config = FunctionConfig {
+            provisioned_concurrency: 2,
+            timeout_ms: 500,
+            ..Default::default()
+        };
+
+        changes(&["--timeout-ms", "1000", "--memory-mb", "256"]).apply(&mut config);
+        assert_eq!(config.provisioned_concurrency, 2);
+        assert_eq!(config.timeout_ms, 1000);
+        assert_eq!(config.limits.unwrap().memory_mb, 256);
+
+        changes(&["--provisioned-concurrency", "0"]).apply(&mut config);
+        assert_eq!(config.provisioned_concurrency, 0);
+        assert_eq!(config.timeout_ms, 1000);
+        assert_eq!(config.limits.unwrap().memory_mb, 256);
+    }
+
+    #[test]
+    fn test_config_changes_network() {
+        let mut config = FunctionConfig::default();
+
+        changes(&[
+            "--network",
+            "egress",
+            "--egress-allow",
+            "10.0.0.0/8:443",
+            "--egress-allow",
+            "1.1.1.1",
+        ])
+        .apply(&mut config);
+        let network = config.network.clone().unwrap();
+        assert_eq!(network.mode(), NetworkMode::Egress);
+        assert_eq!(network.egress_allowlist, ["10.0.0.0/8:443", "1.1.1.1"]);
+
+        changes(&["--memory-mb", "64"]).apply(&mut config);
+        assert_eq!(config.network.unwrap().egress_allowlist.len(), 2);
+    }
+
+    #[test]
+    fn test_config_changes_env() {
+        let mut config = FunctionConfig::default();
+        changes(&["--env", "A=1", "--env", "B=x=y"]).apply(&mut config);
+        assert_eq!(config.env["A"], "1");
+        assert_eq!(config.env["B"], "x=y");
+
+        changes(&["--unset-env", "A", "--env", "C="]).apply(&mut config);
+        assert!(!config.env.contains_key("A"));
+        assert_eq!(config.env.len(), 2);
+
+        assert!(parse_env_var("=1").is_err());
+        assert!(parse_env_var("A").is_err());
+    }
+
+    #[test]
+    fn test_config_changes_security() {
+        let mut config = FunctionConfig::default();
+        changes(&[]).apply(&mut config);
+        assert_eq!(config, FunctionConfig::default());
+
+        changes(&[
+            "--keep-capability",
+            "CAP_NET_BIND_SERVICE",
+            "--allow-new-privileges",
+            "true",
+        ])
+        .apply(&mut config);
+        let security = config.security.clone().unwrap();
+        assert_eq!(security.capabilities, ["CAP_NET_BIND_SERVICE"]);
+        assert!(security.allow_new_privileges);
+        assert!(!security.seccomp_unconfined);
+
+        changes(&["--allow-new-privileges", "false"]).apply(&mut config);
+        let security = config.security.unwrap();
+        assert!(!security.allow_new_privileges);
+        assert_eq!(security.capabilities.len(), 1);
+    }
+}
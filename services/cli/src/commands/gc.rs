@@ -0,0 +1,34 @@
+use anyhow::{Context, Result};
+use proto::api::registry::{GarbageCollectRequest, registry_service_client::RegistryServiceClient};
+use tonic::Request;
+
+use crate::config::ClientConfig;
+
+/// Runs one registry garbage collection pass and prints what it swept and marked.
+pub async fn gc(config: &ClientConfig, dry_run: bool) -> Result<()> {
+    let mut client = RegistryServiceClient::connect(config.registry_addr.clone())
+        .await
+        .context("failed to connect to registry")?;
+
+    let response = client
+        .garbage_collect(Request::new(GarbageCollectRequest { dry_run }))
+        .await
+        .context("failed to garbage collect")?
+        .into_inner();
+
+    let swept = if dry_run { "would sweep" } else { "swept" };
+    for digest in &response.swept {
+        println!("{:<12}{}", swept, digest);
+    }
+    for digest in &response.marked {
+        println!("{:<12}{}", "marked", digest);
+    }
+    println!(
+        "{} {}, {} marked",
+        response.swept.len(),
+        swept,
+        response.marked.len()
+    );
+
+    Ok(())
+}
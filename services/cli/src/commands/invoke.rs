@@ -0,0 +1,63 @@
+use std::{collections::HashMap, io::Write, time::Duration};
+
+use anyhow::{Context, Result, bail};
+use proto::api::worker::{
+    ExecuteRequest, execute_response::Outcome, worker_service_client::WorkerServiceClient,
+};
+use tonic::Request;
+use tracing::instrument;
+
+use crate::config::ClientConfig;
+
+/// Metadata entry the worker uses to keep weighted aliases sticky per caller.
+const ROUTING_KEY_METADATA: &str = "routing-key";
+
+/// Runs `name` on the worker and writes the function output to stdout.
+#[instrument(skip(config, body))]
+pub async fn invoke(
+    config: &ClientConfig,
+    name: &str,
+    body: Vec<u8>,
+    routing_key: Option<String>,
+    timeout: Option<Duration>,
+) -> Result<()> {
+    let mut client = WorkerServiceClient::connect(config.worker_addr.clone())
+        .await
+        .context("failed to connect to worker")?;
+
+    let mut request = Request::new(ExecuteRequest {
+        action: name.to_string(),
+        body,
+        metadata: routing_key
+            .map(|key| HashMap::from([(ROUTING_KEY_METADATA.to_string(), key)]))
+            .unwrap_or_default(),
+    });
+    if let Some(timeout) = timeout {
+        request.set_timeout(timeout);
+    }
+
+    let response = client
+        .execute(request)
+        .await
+        .context("failed to invoke function")?
+        .into_inner();
+
+    match response.outcome {
+        Some(Outcome::Success(success)) => {
+            let mut stdout = std::io::stdout();
+            stdout.write_all(&success.body)?;
+            stdout.flush()?;
+            Ok(())
+        }
+        Some(Outcome::Problem(problem)) => {
+            bail!(
+                "function failed ({}): {}, see `noctiforge logs {} --invocation {}`",
+                problem.r#type,
+                problem.detail,
+                name,
+                response.invocation_id
+            )
+        }
+        None => bail!("worker returned an empty response"),
+    }
+}
@@ -0,0 +1,101 @@
+use anyhow::{Context, Result};
+use proto::api::worker::{
+    GetLogsRequest, LogEntry, LogStream, TailLogsRequest,
+    worker_service_client::WorkerServiceClient,
+};
+use tokio_stream::StreamExt;
+use tonic::Request;
+
+use crate::config::ClientConfig;
+
+/// Prints the newest `limit` lines `name` logged, following new ones with `follow`.
+pub async fn logs(
+    config: &ClientConfig,
+    name: &str,
+    limit: u32,
+    invocation_id: Option<String>,
+    follow: bool,
+) -> Result<()> {
+    let mut client = WorkerServiceClient::connect(config.worker_addr.clone())
+        .await
+        .context("failed to connect to worker")?;
+
+    if follow {
+        let mut entries = client
+            .tail_logs(Request::new(TailLogsRequest {
+                name: name.to_string(),
+                backlog: limit,
+            }))
+            .await
+            .context("failed to follow logs")?
+            .into_inner();
+
+        while let Some(entry) = entries.next().await {
+            let entry = entry.context("log stream failed")?;
+            if invocation_id
+                .as_ref()
+                .is_none_or(|id| *id == entry.invocation_id)
+            {
+                print_entry(&entry);
+            }
+        }
+        return Ok(());
+    }
+
+    let response = client
+        .get_logs(Request::new(GetLogsRequest {
+            name: name.to_string(),
+            limit,
+            invocation_id: invocation_id.unwrap_or_default(),
+        }))
+        .await
+        .context("failed to get logs")?
+        .into_inner();
+
+    for entry in &response.entries {
+        print_entry(entry);
+    }
+    Ok(())
+}
+
+/// Keeps the function's streams apart, stderr lines go to stderr.
+fn print_entry(entry: &LogEntry) {
+    let line = format!(
+        "{} {} {} {}",
+        format_time(entry.timestamp_ms),
+        entry.instance_id,
+        if entry.invocation_id.is_empty() {
+            "-"
+        } else {
+            &entry.invocation_id
+        },
+        entry.line
+    );
+    match entry.stream() {
+        LogStream::Stdout => println!("{}", line),
+        LogStream::Stderr => eprintln!("{}", line),
+    }
+}
+
+/// `HH:MM:SS.mmm` in UTC.
+fn format_time(timestamp_ms: i64) -> String {
+    let ms = timestamp_ms.rem_euclid(24 * 60 * 60 * 1000);
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        ms / 3_600_000,
+        ms / 60_000 % 60,
+        ms / 1000 % 60,
+        ms % 1000
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_time() {
+        assert_eq!(format_time(0), "00:00:00.000");
+        assert_eq!(format_time(1_760_706_245_123), "13:04:05.123");
+    }
+}
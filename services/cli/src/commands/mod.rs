@@ -0,0 +1,8 @@
+pub mod function;
+pub mod gc;
+pub mod invoke;
+pub mod logs;
+pub mod prewarm;
+pub mod push;
+pub mod secret;
+pub mod verify;
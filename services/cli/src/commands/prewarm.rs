@@ -0,0 +1,24 @@
+use anyhow::{Context, Result};
+use proto::api::worker::{PrewarmRequest, worker_service_client::WorkerServiceClient};
+use tonic::Request;
+
+use crate::config::ClientConfig;
+
+/// Starts `count` instances of `name` on the worker ahead of traffic.
+pub async fn prewarm(config: &ClientConfig, name: &str, count: u32) -> Result<()> {
+    let mut client = WorkerServiceClient::connect(config.worker_addr.clone())
+        .await
+        .context("failed to connect to worker")?;
+
+    let response = client
+        .prewarm(Request::new(PrewarmRequest {
+            name: name.to_string(),
+            count,
+        }))
+        .await
+        .context("failed to prewarm function")?
+        .into_inner();
+
+    println!("{} instances of {} running", response.running, name);
+    Ok(())
+}
@@ -0,0 +1,102 @@
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+use proto::api::{
+    controlplane::{
+        SetDigestToNameRequest, control_plane_service_client::ControlPlaneServiceClient,
+    },
+    registry::{RegistryPushRequest, registry_service_client::RegistryServiceClient},
+};
+use tonic::Request;
+use tracing::{debug, info, instrument};
+
+use crate::{
+    archive::{is_function_dir, pack_dir},
+    config::ClientConfig,
+};
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Pushes every direct sub folder of `project` that looks like a function.
+pub async fn push_all(config: &ClientConfig, project: &Path) -> Result<()> {
+    let mut folders = vec![];
+    let mut entries = tokio::fs::read_dir(project).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if path.is_dir() && is_function_dir(&path) {
+            folders.push(path);
+        }
+    }
+
+    if folders.is_empty() {
+        bail!("no function folders found in {}", project.display());
+    }
+
+    folders.sort();
+    for folder in folders {
+        push(config, &folder).await?;
+    }
+
+    Ok(())
+}
+
+/// Packs `folder`, uploads it to the registry and points the folder name at the new digest.
+#[instrument(skip(config), fields(folder = %folder.display()))]
+pub async fn push(config: &ClientConfig, folder: &Path) -> Result<()> {
+    let folder = folder
+        .canonicalize()
+        .with_context(|| format!("invalid function folder: {}", folder.display()))?;
+    let name = folder
+        .file_name()
+        .and_then(|n| n.to_str())
+        .context("function folder has no usable name")?
+        .to_string();
+
+    let data = pack_dir(&folder).await?;
+    debug!(name = %name, size_bytes = data.len(), "Packed function");
+
+    let digest = push_archive(config, data).await?;
+    let version = set_digest(config, &name, &digest).await?;
+
+    info!(name = %name, digest = %digest, version, "Function pushed");
+    println!("{}@{} -> {}", name, version, digest);
+    Ok(())
+}
+
+async fn push_archive(config: &ClientConfig, data: Vec<u8>) -> Result<String> {
+    let mut client = RegistryServiceClient::connect(config.registry_addr.clone())
+        .await
+        .context("failed to connect to registry")?;
+
+    let chunks: Vec<RegistryPushRequest> = data
+        .chunks(CHUNK_SIZE)
+        .map(|chunk| RegistryPushRequest {
+            data: chunk.to_vec(),
+        })
+        .collect();
+
+    let response = client
+        .push(Request::new(tokio_stream::iter(chunks)))
+        .await
+        .context("failed to push archive to registry")?
+        .into_inner();
+
+    Ok(response.digest)
+}
+
+async fn set_digest(config: &ClientConfig, name: &str, digest: &str) -> Result<u64> {
+    let mut client = ControlPlaneServiceClient::connect(config.controlplane_addr.clone())
+        .await
+        .context("failed to connect to control plane")?;
+
+    let response = client
+        .set_digest_to_name(Request::new(SetDigestToNameRequest {
+            key: name.to_string(),
+            digest: digest.to_string(),
+        }))
+        .await
+        .context("failed to record digest in control plane")?
+        .into_inner();
+
+    Ok(response.version)
+}
@@ -0,0 +1,83 @@
+use std::io::Read;
+
+use anyhow::{Context, Result};
+use proto::api::controlplane::{
+    DeleteSecretRequest, ListSecretsRequest, SetSecretRequest,
+    control_plane_service_client::ControlPlaneServiceClient,
+};
+use tonic::Request;
+
+use crate::config::ClientConfig;
+
+/// Stores `key` for `name`, reading the value from stdin when none is given so it
+/// stays out of the shell history.
+pub async fn set(
+    config: &ClientConfig,
+    name: &str,
+    key: &str,
+    value: Option<String>,
+) -> Result<()> {
+    let value = match value {
+        Some(value) => value,
+        None => {
+            let mut value = String::new();
+            std::io::stdin()
+                .read_to_string(&mut value)
+                .context("failed to read secret from stdin")?;
+            value.trim_end_matches(['\r', '\n']).to_string()
+        }
+    };
+
+    let mut client = ControlPlaneServiceClient::connect(config.controlplane_addr.clone())
+        .await
+        .context("failed to connect to control plane")?;
+
+    client
+        .set_secret(Request::new(SetSecretRequest {
+            name: name.to_string(),
+            key: key.to_string(),
+            value,
+        }))
+        .await
+        .context("failed to set secret")?;
+
+    println!("set {} of {}", key, name);
+    Ok(())
+}
+
+pub async fn delete(config: &ClientConfig, name: &str, key: &str) -> Result<()> {
+    let mut client = ControlPlaneServiceClient::connect(config.controlplane_addr.clone())
+        .await
+        .context("failed to connect to control plane")?;
+
+    client
+        .delete_secret(Request::new(DeleteSecretRequest {
+            name: name.to_string(),
+            key: key.to_string(),
+        }))
+        .await
+        .context("failed to delete secret")?;
+
+    println!("deleted {} of {}", key, name);
+    Ok(())
+}
+
+/// Prints the secret keys of `name`, values can't be read back.
+pub async fn list(config: &ClientConfig, name: &str) -> Result<()> {
+    let mut client = ControlPlaneServiceClient::connect(config.controlplane_addr.clone())
+        .await
+        .context("failed to connect to control plane")?;
+
+    let response = client
+        .list_secrets(Request::new(ListSecretsRequest {
+            name: name.to_string(),
+        }))
+        .await
+        .context("failed to list secrets")?
+        .into_inner();
+
+    for key in response.keys {
+        println!("{}", key);
+    }
+    Ok(())
+}
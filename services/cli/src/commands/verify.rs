@@ -0,0 +1,32 @@
+use anyhow::{Context, Result, bail};
+use proto::api::registry::{VerifyRequest, registry_service_client::RegistryServiceClient};
+use tonic::Request;
+
+use crate::config::ClientConfig;
+
+/// Asks the registry to re-hash the given digests, or every stored bundle when none are given.
+pub async fn verify(config: &ClientConfig, digests: Vec<String>) -> Result<()> {
+    let mut client = RegistryServiceClient::connect(config.registry_addr.clone())
+        .await
+        .context("failed to connect to registry")?;
+
+    let response = client
+        .verify(Request::new(VerifyRequest { digests }))
+        .await
+        .context("failed to verify registry")?
+        .into_inner();
+
+    for blob in &response.corrupted {
+        println!("{}: {}", blob.digest, blob.reason);
+    }
+    println!(
+        "{} checked, {} corrupted",
+        response.checked,
+        response.corrupted.len()
+    );
+
+    if !response.corrupted.is_empty() {
+        bail!("registry has corrupted bundles");
+    }
+    Ok(())
+}
@@ -0,0 +1,24 @@
+pub struct ClientConfig {
+    pub registry_addr: String,
+    pub controlplane_addr: String,
+    pub worker_addr: String,
+}
+
+impl ClientConfig {
+    pub fn from_env() -> Self {
+        let registry_addr =
+            std::env::var("REGISTRY_ADDR").unwrap_or_else(|_| "http://[::1]:50001".to_string());
+
+        let controlplane_addr =
+            std::env::var("CONTROLPLANE_ADDR").unwrap_or_else(|_| "http://[::1]:50002".to_string());
+
+        let worker_addr =
+            std::env::var("WORKER_ADDR").unwrap_or_else(|_| "http://[::1]:50003".to_string());
+
+        Self {
+            registry_addr,
+            controlplane_addr,
+            worker_addr,
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use std::{path::PathBuf, time::Duration};
+
+use anyhow::Result;
+use clap::{Parser, Subcommand};
+
+mod archive;
+mod commands;
+mod config;
+
+#[derive(Parser)]
+#[command(
+    name = "noctiforge",
+    version,
+    about = "Push and invoke NoctiForge functions"
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Build & push a single function folder, or `all` for every function in the project
+    Push { target: String },
+    /// Run a function (`name`, `name@version` or `name:alias`) with an optional body
+    Invoke {
+        name: String,
+        body: Option<String>,
+        /// Requests with the same key resolve weighted aliases to the same version
+        #[arg(long)]
+        routing_key: Option<String>,
+        /// Deadline for this call, overrides the function's own timeout
+        #[arg(long)]
+        timeout_ms: Option<u64>,
+    },
+    /// List all functions, optionally only those starting with a prefix
+    List { prefix: Option<String> },
+    /// Remove a function name from the control plane
+    Delete { name: String },
+    /// List every version pushed for a function
+    Versions { name: String },
+    /// Point a function back at a previous version
+    Rollback { name: String, version: u64 },
+    /// Point a named alias at one version, or split it like `3=90 4=10`
+    Alias {
+        name: String,
+        alias: String,
+        #[arg(required = true)]
+        targets: Vec<String>,
+    },
+    /// Show a function's config, or change the settings that are passed
+    Config {
+        name: String,
+        #[command(flatten)]
+        changes: commands::function::ConfigChanges,
+    },
+    /// Show what a function's instances wrote to stdout and stderr
+    Logs {
+        name: String,
+        /// Newest lines shown, also before following
+        #[arg(long, default_value_t = 100)]
+        limit: u32,
+        /// Only show lines of one invocation
+        #[arg(long)]
+        invocation: Option<String>,
+        /// Keep printing new lines as they are written
+        #[arg(short, long)]
+        follow: bool,
+    },
+    /// Start instances of a function on the worker ahead of traffic
+    Prewarm { name: String, count: u32 },
+    /// Manage a function's encrypted secrets, set as environment variables of its instances
+    Secret {
+        #[command(subcommand)]
+        command: SecretCommand,
+    },
+    /// Delete registry bundles no function references anymore
+    Gc {
+        /// Only report what would be deleted
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Check stored bundles still match their digests, all of them when none are given
+    Verify { digests: Vec<String> },
+}
+
+#[derive(Subcommand)]
+enum SecretCommand {
+    /// Set a secret, the value is read from stdin when not given
+    Set {
+        name: String,
+        key: String,
+        value: Option<String>,
+    },
+    /// Remove a secret
+    Delete { name: String, key: String },
+    /// List the keys of a function's secrets
+    List { name: String },
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    tracing_subscriber::fmt()
+        .with_target(false)
+        .with_env_filter(
+            tracing_subscriber::EnvFilter::try_from_default_env().unwrap_or_else(|_| "warn".into()),
+        )
+        .with_writer(std::io::stderr)
+        .init();
+
+    let cli = Cli::parse();
+    let config = config::ClientConfig::from_env();
+
+    match cli.command {
+        Command::Push { target } if target == "all" => {
+            commands::push::push_all(&config, &std::env::current_dir()?).await
+        }
+        Command::Push { target } => commands::push::push(&config, &PathBuf::from(target)).await,
+        Command::Invoke {
+            name,
+            body,
+            routing_key,
+            timeout_ms,
+        } => {
+            let body = body.unwrap_or_default().into_bytes();
+            let timeout = timeout_ms.map(Duration::from_millis);
+            commands::invoke::invoke(&config, &name, body, routing_key, timeout).await
+        }
+        Command::List { prefix } => commands::function::list(&config, prefix).await,
+        Command::Delete { name } => commands::function::delete(&config, &name).await,
+        Command::Versions { name } => commands::function::versions(&config, &name).await,
+        Command::Rollback { name, version } => {
+            commands::function::rollback(&config, &name, version).await
+        }
+        Command::Alias {
+            name,
+            alias,
+            targets,
+        } => commands::function::alias(&config, &name, &alias, &targets).await,
+        Command::Config { name, changes } => {
+            commands::function::config(&config, &name, &changes).await
+        }
+        Command::Logs {
+            name,
+            limit,
+            invocation,
+            follow,
+        } => commands::logs::logs(&config, &name, limit, invocation, follow).await,
+        Command::Prewarm { name, count } => commands::prewarm::prewarm(&config, &name, count).await,
+        Command::Secret { command } => match command {
+            SecretCommand::Set { name, key, value } => {
+                commands::secret::set(&config, &name, &key, value).await
+            }
+            SecretCommand::Delete { name, key } => {
+                commands::secret::delete(&config, &name, &key).await
+            }
+            SecretCommand::List { name } => commands::secret::list(&config, &name).await,
+        },
+        Command::Gc { dry_run } => commands::gc::gc(&config, dry_run).await,
+        Command::Verify { digests } => commands::verify::verify(&config, digests).await,
+    }
+}
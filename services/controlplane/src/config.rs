@@ -1,10 +1,17 @@
-use std::net::SocketAddr;
+use std::{net::SocketAddr, path::PathBuf};
 
 // TODO: share this location with registry
 pub const DB_PATH: &str = "/var/lib/noctiforge/controlplane/digests.db";
+/// Seals function secrets in the database, generated on first start.
+pub const SECRET_KEY_PATH: &str = "/var/lib/noctiforge/controlplane/secrets.key";
 
 pub struct ServerConfig {
     pub addr: SocketAddr,
+    /// Serves workers, with the decrypted secrets, so it should only be reachable by them.
+    pub internal_addr: SocketAddr,
+    /// Serves the Prometheus metrics.
+    pub metrics_addr: SocketAddr,
+    pub secret_key_path: PathBuf,
 }
 
 impl ServerConfig {
@@ -13,6 +20,22 @@ impl ServerConfig {
             .unwrap_or_else(|_| "[::1]:50002".to_string())
             .parse()
             .expect("Invalid server address");
-        Self { addr }
+        let internal_addr = std::env::var("INTERNAL_ADDR")
+            .unwrap_or_else(|_| "[::1]:50012".to_string())
+            .parse()
+            .expect("Invalid internal address");
+        let metrics_addr = std::env::var("METRICS_ADDR")
+            .unwrap_or_else(|_| "[::1]:9002".to_string())
+            .parse()
+            .expect("Invalid metrics address");
+        let secret_key_path = std::env::var("SECRET_KEY_PATH")
+            .unwrap_or_else(|_| SECRET_KEY_PATH.to_string())
+            .into();
+        Self {
+            addr,
+            internal_addr,
+            metrics_addr,
+            secret_key_path,
+        }
     }
 }
@@ -1,27 +1,48 @@
-use std::path::Path;
+use std::{path::Path, sync::Arc};
 
-use proto::api::controlplane::control_plane_service_server::ControlPlaneServiceServer;
+use proto::api::controlplane::{
+    control_plane_service_server::ControlPlaneServiceServer,
+    worker_control_plane_service_server::WorkerControlPlaneServiceServer,
+};
 use tonic::transport::Server;
 use tracing::info;
 
 mod config;
+mod metrics;
 mod server;
 mod services;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    tracing_subscriber::fmt().with_target(false).init();
+    let _tracing = telemetry::init("noctiforge-controlplane")?;
 
     let config = config::ServerConfig::from_env();
-    let control_plane = server::ControlPlane::new(Path::new(config::DB_PATH)).await?;
+    telemetry::serve_metrics(config.metrics_addr)?;
+    let secret_key = services::SecretKey::load_or_create(&config.secret_key_path)?;
+    let digest_service =
+        Arc::new(services::DigestService::new(Path::new(config::DB_PATH), secret_key).await?);
 
     info!("ControlPlaneService listening on {}", config.addr);
+    info!(
+        "WorkerControlPlaneService listening on {}",
+        config.internal_addr
+    );
     info!("Database at: {}", config::DB_PATH);
+    info!("Metrics listening on {}", config.metrics_addr);
 
-    Server::builder()
-        .add_service(ControlPlaneServiceServer::new(control_plane))
-        .serve(config.addr)
-        .await?;
+    let public = Server::builder()
+        .trace_fn(telemetry::server_span)
+        .add_service(ControlPlaneServiceServer::new(server::ControlPlane::new(
+            &digest_service,
+        )))
+        .serve(config.addr);
+    let internal = Server::builder()
+        .trace_fn(telemetry::server_span)
+        .add_service(WorkerControlPlaneServiceServer::new(
+            server::WorkerControlPlane::new(&digest_service),
+        ))
+        .serve(config.internal_addr);
+    tokio::try_join!(public, internal)?;
 
     Ok(())
 }
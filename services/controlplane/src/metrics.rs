@@ -0,0 +1,52 @@
+//! Metrics the control plane exports on `METRICS_ADDR`.
+
+use std::time::Instant;
+
+use metrics::{counter, histogram};
+
+const LOOKUPS: &str = "noctiforge_controlplane_lookups_total";
+const DB_DURATION: &str = "noctiforge_controlplane_db_duration_seconds";
+
+/// A name lookup, a miss when nothing matched the name, version or alias.
+pub fn lookup(hit: bool) {
+    let result = if hit { "hit" } else { "miss" };
+    counter!(LOOKUPS, "result" => result).increment(1);
+}
+
+/// Awaits `query`, recording how long `operation` spent on the database.
+pub async fn timed<F: Future>(operation: &'static str, query: F) -> F::Output {
+    let started = Instant::now();
+    let output = query.await;
+    histogram!(DB_DURATION, "operation" => operation).record(started.elapsed());
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookups_and_db_duration() {
+        let recorder = telemetry::builder().build_recorder();
+        let handle = recorder.handle();
+
+        metrics::with_local_recorder(&recorder, || {
+            lookup(true);
+            lookup(false);
+            lookup(false);
+            // Polled right away, `with_local_recorder` only covers this closure.
+            let output = tokio::runtime::Builder::new_current_thread()
+                .build()
+                .unwrap()
+                .block_on(timed("list_functions", async { 7 }));
+            assert_eq!(output, 7);
+        });
+
+        let rendered = handle.render();
+        assert!(rendered.contains(r#"noctiforge_controlplane_lookups_total{result="hit"} 1"#));
+        assert!(rendered.contains(r#"noctiforge_controlplane_lookups_total{result="miss"} 2"#));
+        assert!(rendered.contains(
+            r#"noctiforge_controlplane_db_duration_seconds_count{operation="list_functions"} 1"#
+        ));
+    }
+}
@@ -1,23 +1,30 @@
-use std::path::Path;
+use std::sync::Arc;
 
 use proto::api::controlplane::{
-    GetDigestByNameRequest, GetDigestByNameResponse, SetDigestToNameRequest,
-    SetDigestToNameResponse, control_plane_service_server::ControlPlaneService,
+    DeleteFunctionRequest, DeleteFunctionResponse, DeleteSecretRequest, DeleteSecretResponse,
+    GetDigestByNameRequest, GetDigestByNameResponse, GetFunctionConfigRequest,
+    GetFunctionConfigResponse, GetFunctionRequest, GetFunctionResponse, ListFunctionsRequest,
+    ListFunctionsResponse, ListReferencedDigestsRequest, ListReferencedDigestsResponse,
+    ListSecretsRequest, ListSecretsResponse, ListVersionsRequest, ListVersionsResponse,
+    RollbackRequest, RollbackResponse, SetAliasRequest, SetAliasResponse, SetDigestToNameRequest,
+    SetDigestToNameResponse, SetFunctionConfigRequest, SetFunctionConfigResponse, SetSecretRequest,
+    SetSecretResponse, control_plane_service_server::ControlPlaneService,
 };
-use tonic::{Request, Response, Status};
+use tonic::{Code, Request, Response, Status};
 use tracing::{debug, info, instrument};
 
-use crate::services::DigestService;
+use crate::{metrics, services::DigestService};
 
+/// Serves clients, without ever returning secret values.
 pub struct ControlPlane {
-    digest_service: DigestService,
+    digest_service: Arc<DigestService>,
 }
 
 impl ControlPlane {
-    pub async fn new(db_path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
-        Ok(Self {
-            digest_service: DigestService::new(db_path).await?,
-        })
+    pub fn new(digest_service: &Arc<DigestService>) -> Self {
+        Self {
+            digest_service: digest_service.clone(),
+        }
     }
 }
 
@@ -37,11 +44,24 @@ impl ControlPlaneService for ControlPlane {
             key = %req.key,
             "Received request to set digest"
         );
-        let result = self.digest_service.get_digest_by_name(&req.key).await;
+        let result = metrics::timed(
+            "get_digest_by_name",
+            self.digest_service
+                .get_digest_by_name(&req.key, &req.routing_key),
+        )
+        .await;
 
         match &result {
-            Ok(_) => info!(key = %req.key, "Successfully retrieved digest"),
-            Err(e) => debug!(key = %req.key, status = ?e.code(), "Failed to retrieve digest"),
+            Ok(_) => {
+                metrics::lookup(true);
+                info!(key = %req.key, "Successfully retrieved digest")
+            }
+            Err(e) => {
+                if e.code() == Code::NotFound {
+                    metrics::lookup(false);
+                }
+                debug!(key = %req.key, status = ?e.code(), "Failed to retrieve digest")
+            }
         }
 
         result
@@ -62,10 +82,12 @@ impl ControlPlaneService for ControlPlane {
             digest_length = req.digest.len(),
             "Received request to set digest"
         );
-        let result = self
-            .digest_service
-            .set_digest_by_name(&req.key, &req.digest)
-            .await;
+        let result = metrics::timed(
+            "set_digest_by_name",
+            self.digest_service
+                .set_digest_by_name(&req.key, &req.digest),
+        )
+        .await;
 
         match &result {
             Ok(_) => info!(key = %req.key, "Successfully set digest"),
@@ -74,4 +96,313 @@ impl ControlPlaneService for ControlPlane {
 
         result
     }
+
+    #[instrument(
+        name = "List functions",
+        skip(self, request),
+        fields(prefix = %request.get_ref().prefix, page_size = request.get_ref().page_size)
+    )]
+    async fn list_functions(
+        &self,
+        request: Request<ListFunctionsRequest>,
+    ) -> Result<Response<ListFunctionsResponse>, Status> {
+        let req = request.into_inner();
+        debug!(prefix = %req.prefix, "Received request to list functions");
+        let result = metrics::timed(
+            "list_functions",
+            self.digest_service
+                .list_functions(&req.prefix, req.page_size, &req.page_token),
+        )
+        .await;
+
+        match &result {
+            Ok(r) => info!(
+                count = r.get_ref().functions.len(),
+                "Successfully listed functions"
+            ),
+            Err(e) => debug!(status = ?e.code(), "Failed to list functions"),
+        }
+
+        result
+    }
+
+    #[instrument(
+        name = "Get function",
+        skip(self, request),
+        fields(name = %request.get_ref().name)
+    )]
+    async fn get_function(
+        &self,
+        request: Request<GetFunctionRequest>,
+    ) -> Result<Response<GetFunctionResponse>, Status> {
+        let req = request.into_inner();
+        debug!(name = %req.name, "Received request to get function");
+        let result =
+            metrics::timed("get_function", self.digest_service.get_function(&req.name)).await;
+
+        match &result {
+            Ok(_) => info!(name = %req.name, "Successfully retrieved function"),
+            Err(e) => debug!(name = %req.name, status = ?e.code(), "Failed to retrieve function"),
+        }
+
+        result
+    }
+
+    #[instrument(
+        name = "Delete function",
+        skip(self, request),
+        fields(name = %request.get_ref().name)
+    )]
+    async fn delete_function(
+        &self,
+        request: Request<DeleteFunctionRequest>,
+    ) -> Result<Response<DeleteFunctionResponse>, Status> {
+        let req = request.into_inner();
+        debug!(name = %req.name, "Received request to delete function");
+        let result = metrics::timed(
+            "delete_function",
+            self.digest_service.delete_function(&req.name),
+        )
+        .await;
+
+        match &result {
+            Ok(_) => info!(name = %req.name, "Successfully deleted function"),
+            Err(e) => debug!(name = %req.name, status = ?e.code(), "Failed to delete function"),
+        }
+
+        result
+    }
+
+    #[instrument(
+        name = "List versions",
+        skip(self, request),
+        fields(name = %request.get_ref().name)
+    )]
+    async fn list_versions(
+        &self,
+        request: Request<ListVersionsRequest>,
+    ) -> Result<Response<ListVersionsResponse>, Status> {
+        let req = request.into_inner();
+        debug!(name = %req.name, "Received request to list versions");
+        let result = metrics::timed(
+            "list_versions",
+            self.digest_service.list_versions(&req.name),
+        )
+        .await;
+
+        match &result {
+            Ok(r) => {
+                info!(name = %req.name, count = r.get_ref().versions.len(), "Successfully listed versions")
+            }
+            Err(e) => debug!(name = %req.name, status = ?e.code(), "Failed to list versions"),
+        }
+
+        result
+    }
+
+    #[instrument(
+        name = "Rollback",
+        skip(self, request),
+        fields(name = %request.get_ref().name, version = request.get_ref().version)
+    )]
+    async fn rollback(
+        &self,
+        request: Request<RollbackRequest>,
+    ) -> Result<Response<RollbackResponse>, Status> {
+        let req = request.into_inner();
+        debug!(name = %req.name, version = req.version, "Received request to rollback");
+        let result = metrics::timed(
+            "rollback",
+            self.digest_service.rollback(&req.name, req.version),
+        )
+        .await;
+
+        match &result {
+            Ok(_) => info!(name = %req.name, version = req.version, "Successfully rolled back"),
+            Err(e) => debug!(name = %req.name, status = ?e.code(), "Failed to rollback"),
+        }
+
+        result
+    }
+
+    #[instrument(
+        name = "Set alias",
+        skip(self, request),
+        fields(name = %request.get_ref().name, alias = %request.get_ref().alias)
+    )]
+    async fn set_alias(
+        &self,
+        request: Request<SetAliasRequest>,
+    ) -> Result<Response<SetAliasResponse>, Status> {
+        let req = request.into_inner();
+        debug!(name = %req.name, alias = %req.alias, version = req.version, "Received request to set alias");
+        let result = metrics::timed(
+            "set_alias",
+            self.digest_service
+                .set_alias(&req.name, &req.alias, req.version, &req.routes),
+        )
+        .await;
+
+        match &result {
+            Ok(_) => info!(name = %req.name, alias = %req.alias, "Successfully set alias"),
+            Err(e) => debug!(name = %req.name, status = ?e.code(), "Failed to set alias"),
+        }
+
+        result
+    }
+
+    #[instrument(name = "List referenced digests", skip(self, _request))]
+    async fn list_referenced_digests(
+        &self,
+        _request: Request<ListReferencedDigestsRequest>,
+    ) -> Result<Response<ListReferencedDigestsResponse>, Status> {
+        debug!("Received request to list referenced digests");
+        let result = metrics::timed(
+            "list_referenced_digests",
+            self.digest_service.list_referenced_digests(),
+        )
+        .await;
+
+        match &result {
+            Ok(r) => info!(
+                count = r.get_ref().digests.len(),
+                "Successfully listed referenced digests"
+            ),
+            Err(e) => debug!(status = ?e.code(), "Failed to list referenced digests"),
+        }
+
+        result
+    }
+
+    #[instrument(
+        name = "Get function config",
+        skip(self, request),
+        fields(name = %request.get_ref().name)
+    )]
+    async fn get_function_config(
+        &self,
+        request: Request<GetFunctionConfigRequest>,
+    ) -> Result<Response<GetFunctionConfigResponse>, Status> {
+        let req = request.into_inner();
+        debug!(name = %req.name, "Received request to get function config");
+        let result = metrics::timed(
+            "get_function_config",
+            self.digest_service.get_function_config(&req.name),
+        )
+        .await;
+
+        match &result {
+            Ok(_) => info!(name = %req.name, "Successfully retrieved function config"),
+            Err(e) => {
+                debug!(name = %req.name, status = ?e.code(), "Failed to retrieve function config")
+            }
+        }
+
+        result
+    }
+
+    #[instrument(
+        name = "Set function config",
+        skip(self, request),
+        fields(name = %request.get_ref().name)
+    )]
+    async fn set_function_config(
+        &self,
+        request: Request<SetFunctionConfigRequest>,
+    ) -> Result<Response<SetFunctionConfigResponse>, Status> {
+        let req = request.into_inner();
+        debug!(name = %req.name, "Received request to set function config");
+        let config = req
+            .config
+            .ok_or_else(|| Status::invalid_argument("Missing function config"))?;
+        let result = metrics::timed(
+            "set_function_config",
+            self.digest_service.set_function_config(&req.name, &config),
+        )
+        .await;
+
+        match &result {
+            Ok(_) => info!(name = %req.name, "Successfully set function config"),
+            Err(e) => debug!(name = %req.name, status = ?e.code(), "Failed to set function config"),
+        }
+
+        result
+    }
+
+    #[instrument(
+        name = "Set secret",
+        skip(self, request),
+        fields(name = %request.get_ref().name, key = %request.get_ref().key)
+    )]
+    async fn set_secret(
+        &self,
+        request: Request<SetSecretRequest>,
+    ) -> Result<Response<SetSecretResponse>, Status> {
+        let req = request.into_inner();
+        debug!(name = %req.name, key = %req.key, "Received request to set secret");
+        let result = metrics::timed(
+            "set_secret",
+            self.digest_service
+                .set_secret(&req.name, &req.key, &req.value),
+        )
+        .await;
+
+        match &result {
+            Ok(_) => info!(name = %req.name, key = %req.key, "Successfully set secret"),
+            Err(e) => debug!(name = %req.name, status = ?e.code(), "Failed to set secret"),
+        }
+
+        result
+    }
+
+    #[instrument(
+        name = "Delete secret",
+        skip(self, request),
+        fields(name = %request.get_ref().name, key = %request.get_ref().key)
+    )]
+    async fn delete_secret(
+        &self,
+        request: Request<DeleteSecretRequest>,
+    ) -> Result<Response<DeleteSecretResponse>, Status> {
+        let req = request.into_inner();
+        debug!(name = %req.name, key = %req.key, "Received request to delete secret");
+        let result = metrics::timed(
+            "delete_secret",
+            self.digest_service.delete_secret(&req.name, &req.key),
+        )
+        .await;
+
+        match &result {
+            Ok(_) => info!(name = %req.name, key = %req.key, "Successfully deleted secret"),
+            Err(e) => debug!(name = %req.name, status = ?e.code(), "Failed to delete secret"),
+        }
+
+        result
+    }
+
+    #[instrument(
+        name = "List secrets",
+        skip(self, request),
+        fields(name = %request.get_ref().name)
+    )]
+    async fn list_secrets(
+        &self,
+        request: Request<ListSecretsRequest>,
+    ) -> Result<Response<ListSecretsResponse>, Status> {
+        let req = request.into_inner();
+        debug!(name = %req.name, "Received request to list secrets");
+        let result =
+            metrics::timed("list_secrets", self.digest_service.list_secrets(&req.name)).await;
+
+        match &result {
+            Ok(r) => info!(
+                name = %req.name,
+                count = r.get_ref().keys.len(),
+                "Successfully listed secrets"
+            ),
+            Err(e) => debug!(name = %req.name, status = ?e.code(), "Failed to list secrets"),
+        }
+
+        result
+    }
 }
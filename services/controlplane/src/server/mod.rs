@@ -1,2 +1,4 @@
 mod controlplane;
+mod worker;
 pub use controlplane::ControlPlane;
+pub use worker::WorkerControlPlane;
@@ -0,0 +1,83 @@
+use std::sync::Arc;
+
+use proto::api::controlplane::{
+    ListProvisionedFunctionsRequest, ListProvisionedFunctionsResponse, ResolveFunctionRequest,
+    ResolveFunctionResponse, worker_control_plane_service_server::WorkerControlPlaneService,
+};
+use tonic::{Code, Request, Response, Status};
+use tracing::{debug, info, instrument};
+
+use crate::{metrics, services::DigestService};
+
+/// Serves workers on the internal address, these responses carry decrypted secrets.
+pub struct WorkerControlPlane {
+    digest_service: Arc<DigestService>,
+}
+
+impl WorkerControlPlane {
+    pub fn new(digest_service: &Arc<DigestService>) -> Self {
+        Self {
+            digest_service: digest_service.clone(),
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl WorkerControlPlaneService for WorkerControlPlane {
+    #[instrument(
+        name = "Resolve function",
+        skip(self, request),
+        fields(key = %request.get_ref().key)
+    )]
+    async fn resolve_function(
+        &self,
+        request: Request<ResolveFunctionRequest>,
+    ) -> Result<Response<ResolveFunctionResponse>, Status> {
+        let req = request.into_inner();
+        debug!(key = %req.key, "Received request to resolve function");
+        let result = metrics::timed(
+            "resolve_function",
+            self.digest_service
+                .resolve_function(&req.key, &req.routing_key),
+        )
+        .await;
+
+        match &result {
+            Ok(_) => {
+                metrics::lookup(true);
+                info!(key = %req.key, "Successfully resolved function")
+            }
+            Err(e) => {
+                if e.code() == Code::NotFound {
+                    metrics::lookup(false);
+                }
+                debug!(key = %req.key, status = ?e.code(), "Failed to resolve function")
+            }
+        }
+
+        result
+    }
+
+    #[instrument(name = "List provisioned functions", skip(self, _request))]
+    async fn list_provisioned_functions(
+        &self,
+        _request: Request<ListProvisionedFunctionsRequest>,
+    ) -> Result<Response<ListProvisionedFunctionsResponse>, Status> {
+        debug!("Received request to list provisioned functions");
+        let result = metrics::timed(
+            "list_provisioned_functions",
+            self.digest_service.list_provisioned_functions(),
+        )
+        .await;
+
+        match &result {
+            Ok(r) => info!(
+                count = r.get_ref().functions.len(),
+                "Successfully listed provisioned functions"
+            ),
+            Err(e) => debug!(status = ?e.code(), "Failed to list provisioned functions"),
+        }
+
+        result
+    }
+}
@@ -1,16 +1,36 @@
-use proto::api::controlplane::{GetDigestByNameResponse, SetDigestToNameResponse};
+use proto::api::controlplane::{
+    AliasRoute, DeleteFunctionResponse, Function, FunctionVersion, GetDigestByNameResponse,
+    GetFunctionResponse, ListFunctionsResponse, ListReferencedDigestsResponse,
+    ListVersionsResponse, RollbackResponse, SetAliasResponse, SetDigestToNameResponse,
+};
 use sqlx::{SqlitePool, sqlite::SqlitePoolOptions};
 use std::path::Path;
 use tonic::{Response, Status};
 use tracing::{debug, error, info, instrument, warn};
 
+use crate::services::{
+    SecretKey,
+    routing::{Route, pick_route},
+    selector::{Selector, validate_name},
+};
+
+const DEFAULT_PAGE_SIZE: u32 = 50;
+const MAX_PAGE_SIZE: u32 = 500;
+
+type FunctionRow = (String, String, i64, i64, i64);
+type VersionRow = (String, i64, String, i64);
+
 pub struct DigestService {
-    pool: SqlitePool,
+    pub(super) pool: SqlitePool,
+    pub(super) secret_key: SecretKey,
 }
 
 impl DigestService {
-    #[instrument(skip(db_path), fields(db_path = %db_path.display()))]
-    pub async fn new(db_path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+    #[instrument(skip(db_path, secret_key), fields(db_path = %db_path.display()))]
+    pub async fn new(
+        db_path: &Path,
+        secret_key: SecretKey,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
         info!("Initializing DigestService");
         let parent = db_path
             .parent()
@@ -32,47 +52,52 @@ impl DigestService {
             .await?;
         info!("Database connection established");
 
-        debug!("Creating digests table if not exists");
-        sqlx::query(
-            r#"
-            CREATE TABLE IF NOT EXISTS digests (
-                name TEXT PRIMARY KEY,
-                digest TEXT NOT NULL,
-                created_at INTEGER DEFAULT (strftime('%s', 'now')),
-                updated_at INTEGER DEFAULT (strftime('%s', 'now'))
-            )
-            "#,
-        )
-        .execute(&pool)
-        .await
-        .map_err(|e| {
-            error!(error = %e, "Failed to create digests table");
+        migrate(&pool).await.map_err(|e| {
+            error!(error = %e, "Failed to migrate database");
             e
         })?;
 
         info!("DigestService initialized successfully");
-        Ok(Self { pool })
+        Ok(Self { pool, secret_key })
     }
 
-    #[instrument(skip(self), fields(key = %key))]
+    #[instrument(skip(self, routing_key), fields(key = %key))]
     pub async fn get_digest_by_name(
         &self,
         key: &str,
+        routing_key: &str,
     ) -> Result<Response<GetDigestByNameResponse>, Status> {
         debug!("Fetching digest from database");
-        let result = sqlx::query_as::<_, (String,)>("SELECT digest FROM digests WHERE name = ?")
-            .bind(key)
-            .fetch_optional(&self.pool)
-            .await
-            .map_err(|e| {
-                error!(error = %e, "Database query failed");
-                Status::internal(format!("Database error: {}", e))
-            })?;
+        let selector = Selector::parse(key)?;
+        let name = selector.name();
+        let query = match selector {
+            Selector::Current(name) => sqlx::query_as::<_, (String, i64)>(
+                "SELECT digest, version FROM digests WHERE name = ?",
+            )
+            .bind(name),
+            Selector::Version(name, version) => sqlx::query_as(
+                "SELECT digest, version FROM function_versions WHERE name = ? AND version = ?",
+            )
+            .bind(name)
+            .bind(version),
+            Selector::Alias(name, alias) => {
+                return self.resolve_alias(key, name, alias, routing_key).await;
+            }
+        };
+
+        let result = query.fetch_optional(&self.pool).await.map_err(|e| {
+            error!(error = %e, "Database query failed");
+            Status::internal(format!("Database error: {}", e))
+        })?;
 
         match result {
-            Some((digest,)) => {
-                info!(digest_length = digest.len(), "Digest found");
-                Ok(Response::new(GetDigestByNameResponse { digest }))
+            Some((digest, version)) => {
+                info!(digest_length = digest.len(), version, "Digest found");
+                Ok(Response::new(GetDigestByNameResponse {
+                    digest,
+                    version: version as u64,
+                    config: Some(self.load_function_config(name).await?),
+                }))
             }
             None => {
                 warn!("Digest not found");
@@ -84,32 +109,841 @@ impl DigestService {
         }
     }
 
+    /// Picks one of the alias routes, sticky per routing key when one is given.
+    async fn resolve_alias(
+        &self,
+        key: &str,
+        name: &str,
+        alias: &str,
+        routing_key: &str,
+    ) -> Result<Response<GetDigestByNameResponse>, Status> {
+        let routes = sqlx::query_as::<_, (i64, String, i64)>(
+            r#"
+            SELECT v.version, v.digest, r.weight FROM function_alias_routes r
+            JOIN function_versions v ON v.name = r.name AND v.version = r.version
+            WHERE r.name = ? AND r.alias = ?
+            ORDER BY v.version
+            "#,
+        )
+        .bind(name)
+        .bind(alias)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(db_error)?
+        .into_iter()
+        .map(|(version, digest, weight)| Route {
+            version,
+            digest,
+            weight,
+        })
+        .collect::<Vec<_>>();
+
+        let routing_key = (!routing_key.is_empty()).then_some(routing_key);
+        match pick_route(key, &routes, routing_key) {
+            Some(route) => {
+                info!(
+                    version = route.version,
+                    routes = routes.len(),
+                    sticky = routing_key.is_some(),
+                    "Alias resolved"
+                );
+                Ok(Response::new(GetDigestByNameResponse {
+                    digest: route.digest.clone(),
+                    version: route.version as u64,
+                    config: Some(self.load_function_config(name).await?),
+                }))
+            }
+            None => {
+                warn!("Alias not found");
+                Err(Status::not_found(format!(
+                    "Digest not found for name: {}",
+                    key
+                )))
+            }
+        }
+    }
+
+    /// Points `key` at `digest`, recording a new immutable version when the digest changed.
     #[instrument(skip(self, digest), fields(key = %key, digest_length = digest.len()))]
     pub async fn set_digest_by_name(
         &self,
         key: &str,
         digest: &str,
     ) -> Result<Response<SetDigestToNameResponse>, Status> {
-        debug!("Upserting digest into database");
+        validate_name(key)?;
+
+        let mut tx = self.pool.begin().await.map_err(db_error)?;
+
+        let current = sqlx::query_as::<_, (String, i64)>(
+            "SELECT digest, version FROM digests WHERE name = ?",
+        )
+        .bind(key)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(db_error)?;
+
+        if let Some((current_digest, version)) = current
+            && current_digest == digest
+        {
+            info!(version, "Digest unchanged, keeping current version");
+            return Ok(Response::new(SetDigestToNameResponse {
+                success: true,
+                version: version as u64,
+            }));
+        }
+
+        debug!("Recording new function version");
+        let (version,) = sqlx::query_as::<_, (i64,)>(
+            r#"
+            INSERT INTO function_versions (name, version, digest)
+            SELECT ?1, COALESCE(MAX(version), 0) + 1, ?2 FROM function_versions WHERE name = ?1
+            RETURNING version
+            "#,
+        )
+        .bind(key)
+        .bind(digest)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(db_error)?;
+
+        debug!(version, "Upserting digest into database");
         sqlx::query(
             r#"
-            INSERT INTO digests (name, digest, updated_at)
-            VALUES (?, ?, strftime('%s', 'now'))
+            INSERT INTO digests (name, digest, version, updated_at)
+            VALUES (?, ?, ?, strftime('%s', 'now'))
             ON CONFLICT(name) DO UPDATE SET
                 digest = excluded.digest,
+                version = excluded.version,
                 updated_at = strftime('%s', 'now')
             "#,
         )
         .bind(key)
         .bind(digest)
-        .execute(&self.pool)
+        .bind(version)
+        .execute(&mut *tx)
         .await
         .map_err(|e| {
             error!(error = %e, "Database upsert failed");
             Status::internal(format!("Database error: {}", e))
         })?;
 
-        info!("Digest set successfully");
-        Ok(Response::new(SetDigestToNameResponse { success: true }))
+        tx.commit().await.map_err(db_error)?;
+
+        info!(version, "Digest set successfully");
+        Ok(Response::new(SetDigestToNameResponse {
+            success: true,
+            version: version as u64,
+        }))
+    }
+
+    #[instrument(skip(self), fields(prefix = %prefix, page_size, page_token = %page_token))]
+    pub async fn list_functions(
+        &self,
+        prefix: &str,
+        page_size: u32,
+        page_token: &str,
+    ) -> Result<Response<ListFunctionsResponse>, Status> {
+        let page_size = match page_size {
+            0 => DEFAULT_PAGE_SIZE,
+            size => size.min(MAX_PAGE_SIZE),
+        };
+        debug!(page_size, "Listing functions from database");
+
+        // Fetch one extra row to know if there is a next page.
+        let mut rows = sqlx::query_as::<_, FunctionRow>(
+            r#"
+            SELECT name, digest, created_at, updated_at, version FROM digests
+            WHERE name > ?1 AND substr(name, 1, length(?2)) = ?2
+            ORDER BY name
+            LIMIT ?3
+            "#,
+        )
+        .bind(page_token)
+        .bind(prefix)
+        .bind(page_size as i64 + 1)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            error!(error = %e, "Database query failed");
+            Status::internal(format!("Database error: {}", e))
+        })?;
+
+        let next_page_token = if rows.len() > page_size as usize {
+            rows.truncate(page_size as usize);
+            rows.last()
+                .map(|(name, ..)| name.clone())
+                .unwrap_or_default()
+        } else {
+            String::new()
+        };
+
+        info!(count = rows.len(), "Functions listed");
+        Ok(Response::new(ListFunctionsResponse {
+            functions: rows.into_iter().map(to_function).collect(),
+            next_page_token,
+        }))
+    }
+
+    #[instrument(skip(self), fields(name = %name))]
+    pub async fn get_function(&self, name: &str) -> Result<Response<GetFunctionResponse>, Status> {
+        debug!("Fetching function from database");
+        let result = sqlx::query_as::<_, FunctionRow>(
+            "SELECT name, digest, created_at, updated_at, version FROM digests WHERE name = ?",
+        )
+        .bind(name)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| {
+            error!(error = %e, "Database query failed");
+            Status::internal(format!("Database error: {}", e))
+        })?;
+
+        match result {
+            Some(row) => Ok(Response::new(GetFunctionResponse {
+                function: Some(to_function(row)),
+            })),
+            None => {
+                warn!("Function not found");
+                Err(Status::not_found(format!("Function not found: {}", name)))
+            }
+        }
+    }
+
+    #[instrument(skip(self), fields(name = %name))]
+    pub async fn delete_function(
+        &self,
+        name: &str,
+    ) -> Result<Response<DeleteFunctionResponse>, Status> {
+        debug!("Deleting function from database");
+        let mut tx = self.pool.begin().await.map_err(db_error)?;
+
+        let result = sqlx::query("DELETE FROM digests WHERE name = ?")
+            .bind(name)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| {
+                error!(error = %e, "Database delete failed");
+                Status::internal(format!("Database error: {}", e))
+            })?;
+
+        for table in [
+            "function_versions",
+            "function_alias_routes",
+            "function_configs",
+            "function_secrets",
+        ] {
+            sqlx::query(&format!("DELETE FROM {} WHERE name = ?", table))
+                .bind(name)
+                .execute(&mut *tx)
+                .await
+                .map_err(db_error)?;
+        }
+
+        tx.commit().await.map_err(db_error)?;
+
+        if result.rows_affected() == 0 {
+            warn!("Function not found");
+            return Err(Status::not_found(format!("Function not found: {}", name)));
+        }
+
+        info!("Function deleted successfully");
+        Ok(Response::new(DeleteFunctionResponse { success: true }))
+    }
+
+    #[instrument(skip(self), fields(name = %name))]
+    pub async fn list_versions(
+        &self,
+        name: &str,
+    ) -> Result<Response<ListVersionsResponse>, Status> {
+        debug!("Listing function versions");
+        let rows = sqlx::query_as::<_, VersionRow>(
+            r#"
+            SELECT name, version, digest, created_at FROM function_versions
+            WHERE name = ?
+            ORDER BY version DESC
+            "#,
+        )
+        .bind(name)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(db_error)?;
+
+        if rows.is_empty() {
+            warn!("Function not found");
+            return Err(Status::not_found(format!("Function not found: {}", name)));
+        }
+
+        info!(count = rows.len(), "Versions listed");
+        Ok(Response::new(ListVersionsResponse {
+            versions: rows.into_iter().map(to_version).collect(),
+        }))
+    }
+
+    /// Points `name` back at an existing version without recording a new one.
+    #[instrument(skip(self), fields(name = %name, version))]
+    pub async fn rollback(
+        &self,
+        name: &str,
+        version: u64,
+    ) -> Result<Response<RollbackResponse>, Status> {
+        let mut tx = self.pool.begin().await.map_err(db_error)?;
+        let row = self.find_version(&mut tx, name, version).await?;
+
+        debug!("Pointing function at previous version");
+        sqlx::query(
+            r#"
+            UPDATE digests SET digest = ?, version = ?, updated_at = strftime('%s', 'now')
+            WHERE name = ?
+            "#,
+        )
+        .bind(&row.2)
+        .bind(row.1)
+        .bind(name)
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+
+        tx.commit().await.map_err(db_error)?;
+
+        info!("Rolled back successfully");
+        Ok(Response::new(RollbackResponse {
+            version: Some(to_version(row)),
+        }))
+    }
+
+    /// Points `alias` at a single `version`, or splits it between weighted `routes`.
+    #[instrument(skip(self, routes), fields(name = %name, alias = %alias, version, routes = routes.len()))]
+    pub async fn set_alias(
+        &self,
+        name: &str,
+        alias: &str,
+        version: u64,
+        routes: &[AliasRoute],
+    ) -> Result<Response<SetAliasResponse>, Status> {
+        validate_name(alias)?;
+
+        let routes = match (version, routes) {
+            (0, []) => return Err(Status::invalid_argument("alias needs a version or routes")),
+            (version, []) => vec![AliasRoute { version, weight: 1 }],
+            (0, routes) => routes.to_vec(),
+            _ => {
+                return Err(Status::invalid_argument(
+                    "alias takes either a version or routes, not both",
+                ));
+            }
+        };
+
+        let mut tx = self.pool.begin().await.map_err(db_error)?;
+
+        let mut resolved: Vec<(i64, u32)> = vec![];
+        for route in &routes {
+            if route.weight == 0 {
+                return Err(Status::invalid_argument(format!(
+                    "route to version {} needs a positive weight",
+                    route.version
+                )));
+            }
+            let (_, version, ..) = self.find_version(&mut tx, name, route.version).await?;
+            if resolved.iter().any(|(v, _)| *v == version) {
+                return Err(Status::invalid_argument(format!(
+                    "version {} is routed more than once",
+                    version
+                )));
+            }
+            resolved.push((version, route.weight));
+        }
+
+        debug!("Replacing alias routes in database");
+        sqlx::query("DELETE FROM function_alias_routes WHERE name = ? AND alias = ?")
+            .bind(name)
+            .bind(alias)
+            .execute(&mut *tx)
+            .await
+            .map_err(db_error)?;
+
+        for (version, weight) in resolved {
+            sqlx::query(
+                r#"
+                INSERT INTO function_alias_routes (name, alias, version, weight)
+                VALUES (?, ?, ?, ?)
+                "#,
+            )
+            .bind(name)
+            .bind(alias)
+            .bind(version)
+            .bind(weight as i64)
+            .execute(&mut *tx)
+            .await
+            .map_err(db_error)?;
+        }
+
+        tx.commit().await.map_err(db_error)?;
+
+        info!("Alias set successfully");
+        Ok(Response::new(SetAliasResponse { success: true }))
+    }
+
+    /// Every digest a function name, version or alias can still resolve to.
+    /// Aliases only point at recorded versions, so `function_versions` covers them.
+    #[instrument(skip(self))]
+    pub async fn list_referenced_digests(
+        &self,
+    ) -> Result<Response<ListReferencedDigestsResponse>, Status> {
+        debug!("Listing referenced digests");
+        let digests = sqlx::query_scalar::<_, String>(
+            r#"
+            SELECT digest FROM digests
+            UNION
+            SELECT digest FROM function_versions
+            ORDER BY digest
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(db_error)?;
+
+        info!(count = digests.len(), "Referenced digests listed");
+        Ok(Response::new(ListReferencedDigestsResponse { digests }))
+    }
+
+    async fn find_version(
+        &self,
+        tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+        name: &str,
+        version: u64,
+    ) -> Result<VersionRow, Status> {
+        let version = i64::try_from(version)
+            .map_err(|_| Status::invalid_argument(format!("invalid version: {}", version)))?;
+
+        sqlx::query_as::<_, VersionRow>(
+            r#"
+            SELECT name, version, digest, created_at FROM function_versions
+            WHERE name = ? AND version = ?
+            "#,
+        )
+        .bind(name)
+        .bind(version)
+        .fetch_optional(&mut **tx)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| {
+            warn!("Version not found");
+            Status::not_found(format!("Version not found: {}@{}", name, version))
+        })
+    }
+}
+
+fn to_function((name, digest, created_at, updated_at, version): FunctionRow) -> Function {
+    Function {
+        name,
+        digest,
+        created_at,
+        updated_at,
+        version: version as u64,
+    }
+}
+
+fn to_version((name, version, digest, created_at): VersionRow) -> FunctionVersion {
+    FunctionVersion {
+        name,
+        version: version as u64,
+        digest,
+        created_at,
+    }
+}
+
+pub(super) fn db_error(e: sqlx::Error) -> Status {
+    error!(error = %e, "Database operation failed");
+    Status::internal(format!("Database error: {}", e))
+}
+
+async fn migrate(pool: &SqlitePool) -> Result<(), sqlx::Error> {
+    debug!("Creating tables if not exists");
+    for statement in [
+        r#"
+        CREATE TABLE IF NOT EXISTS digests (
+            name TEXT PRIMARY KEY,
+            digest TEXT NOT NULL,
+            created_at INTEGER DEFAULT (strftime('%s', 'now')),
+            updated_at INTEGER DEFAULT (strftime('%s', 'now'))
+        )
+        "#,
+        r#"
+        CREATE TABLE IF NOT EXISTS function_versions (
+            name TEXT NOT NULL,
+            version INTEGER NOT NULL,
+            digest TEXT NOT NULL,
+            created_at INTEGER DEFAULT (strftime('%s', 'now')),
+            PRIMARY KEY (name, version)
+        )
+        "#,
+        r#"
+        CREATE TABLE IF NOT EXISTS function_alias_routes (
+            name TEXT NOT NULL,
+            alias TEXT NOT NULL,
+            version INTEGER NOT NULL,
+            weight INTEGER NOT NULL,
+            updated_at INTEGER DEFAULT (strftime('%s', 'now')),
+            PRIMARY KEY (name, alias, version)
+        )
+        "#,
+        r#"
+        CREATE TABLE IF NOT EXISTS function_configs (
+            name TEXT PRIMARY KEY,
+            config BLOB NOT NULL,
+            updated_at INTEGER DEFAULT (strftime('%s', 'now'))
+        )
+        "#,
+        r#"
+        CREATE TABLE IF NOT EXISTS function_secrets (
+            name TEXT NOT NULL,
+            key TEXT NOT NULL,
+            nonce BLOB NOT NULL,
+            value BLOB NOT NULL,
+            updated_at INTEGER DEFAULT (strftime('%s', 'now')),
+            PRIMARY KEY (name, key)
+        )
+        "#,
+    ] {
+        sqlx::query(statement).execute(pool).await?;
+    }
+
+    let (has_version,) = sqlx::query_as::<_, (bool,)>(
+        "SELECT COUNT(*) > 0 FROM pragma_table_info('digests') WHERE name = 'version'",
+    )
+    .fetch_one(pool)
+    .await?;
+
+    if !has_version {
+        info!("Migrating digests table to versioned functions");
+        let mut tx = pool.begin().await?;
+        for statement in [
+            "ALTER TABLE digests ADD COLUMN version INTEGER NOT NULL DEFAULT 1",
+            r#"
+            INSERT OR IGNORE INTO function_versions (name, version, digest, created_at)
+            SELECT name, 1, digest, updated_at FROM digests
+            "#,
+        ] {
+            sqlx::query(statement).execute(&mut *tx).await?;
+        }
+        tx.commit().await?;
+    }
+
+    let (has_single_aliases,) = sqlx::query_as::<_, (bool,)>(
+        "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = 'function_aliases'",
+    )
+    .fetch_one(pool)
+    .await?;
+
+    if has_single_aliases {
+        info!("Migrating function aliases to weighted routes");
+        let mut tx = pool.begin().await?;
+        for statement in [
+            r#"
+            INSERT OR IGNORE INTO function_alias_routes (name, alias, version, weight, updated_at)
+            SELECT name, alias, version, 1, updated_at FROM function_aliases
+            "#,
+            "DROP TABLE function_aliases",
+        ] {
+            sqlx::query(statement).execute(&mut *tx).await?;
+        }
+        tx.commit().await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    async fn new_service(temp: &TempDir) -> DigestService {
+        let secret_key = SecretKey::load_or_create(&temp.path().join("secrets.key")).unwrap();
+        DigestService::new(&temp.path().join("digests.db"), secret_key)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_list_functions_pages_in_name_order() {
+        let temp = TempDir::new().unwrap();
+        let service = new_service(&temp).await;
+        for name in ["c", "a", "b"] {
+            service.set_digest_by_name(name, "digest").await.unwrap();
+        }
+
+        let first = service
+            .list_functions("", 2, "")
+            .await
+            .unwrap()
+            .into_inner();
+        let names: Vec<_> = first.functions.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, vec!["a", "b"]);
+        assert_eq!(first.next_page_token, "b");
+
+        let second = service
+            .list_functions("", 2, &first.next_page_token)
+            .await
+            .unwrap()
+            .into_inner();
+        let names: Vec<_> = second.functions.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, vec!["c"]);
+        assert!(second.next_page_token.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_list_functions_filters_by_prefix() {
+        let temp = TempDir::new().unwrap();
+        let service = new_service(&temp).await;
+        for name in ["checkout", "Checkout-admin", "check_in", "cart"] {
+            service.set_digest_by_name(name, "digest").await.unwrap();
+        }
+
+        let resp = service
+            .list_functions("check", 0, "")
+            .await
+            .unwrap()
+            .into_inner();
+        let names: Vec<_> = resp.functions.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, vec!["check_in", "checkout"]);
+    }
+
+    #[tokio::test]
+    async fn test_get_function_returns_digest_and_timestamps() {
+        let temp = TempDir::new().unwrap();
+        let service = new_service(&temp).await;
+        service.set_digest_by_name("hello", "abc").await.unwrap();
+
+        let function = service
+            .get_function("hello")
+            .await
+            .unwrap()
+            .into_inner()
+            .function
+            .unwrap();
+        assert_eq!(function.digest, "abc");
+        assert!(function.created_at > 0);
+        assert!(function.updated_at >= function.created_at);
+    }
+
+    #[tokio::test]
+    async fn test_delete_function() {
+        let temp = TempDir::new().unwrap();
+        let service = new_service(&temp).await;
+        service.set_digest_by_name("hello", "abc").await.unwrap();
+
+        service.delete_function("hello").await.unwrap();
+
+        let err = service.get_function("hello").await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::NotFound);
+        let err = service.delete_function("hello").await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::NotFound);
+    }
+
+    #[tokio::test]
+    async fn test_list_referenced_digests_includes_old_versions() {
+        let temp = TempDir::new().unwrap();
+        let service = new_service(&temp).await;
+        service.set_digest_by_name("hello", "abc").await.unwrap();
+        service.set_digest_by_name("hello", "def").await.unwrap();
+        service.set_digest_by_name("other", "abc").await.unwrap();
+        service.set_digest_by_name("gone", "xyz").await.unwrap();
+        service.delete_function("gone").await.unwrap();
+
+        let digests = service
+            .list_referenced_digests()
+            .await
+            .unwrap()
+            .into_inner()
+            .digests;
+        assert_eq!(digests, vec!["abc", "def"]);
+    }
+
+    #[tokio::test]
+    async fn test_set_digest_records_versions() {
+        let temp = TempDir::new().unwrap();
+        let service = new_service(&temp).await;
+
+        let v1 = service.set_digest_by_name("hello", "a").await.unwrap();
+        let same = service.set_digest_by_name("hello", "a").await.unwrap();
+        let v2 = service.set_digest_by_name("hello", "b").await.unwrap();
+        assert_eq!(v1.get_ref().version, 1);
+        assert_eq!(same.get_ref().version, 1);
+        assert_eq!(v2.get_ref().version, 2);
+
+        let versions = service
+            .list_versions("hello")
+            .await
+            .unwrap()
+            .into_inner()
+            .versions;
+        let digests: Vec<_> = versions
+            .iter()
+            .map(|v| (v.version, v.digest.as_str()))
+            .collect();
+        assert_eq!(digests, vec![(2, "b"), (1, "a")]);
+    }
+
+    #[tokio::test]
+    async fn test_rollback_points_name_at_previous_version() {
+        let temp = TempDir::new().unwrap();
+        let service = new_service(&temp).await;
+        service.set_digest_by_name("hello", "a").await.unwrap();
+        service.set_digest_by_name("hello", "b").await.unwrap();
+
+        service.rollback("hello", 1).await.unwrap();
+
+        let current = service.get_digest_by_name("hello", "").await.unwrap();
+        assert_eq!(current.get_ref().digest, "a");
+        assert_eq!(current.get_ref().version, 1);
+
+        let err = service.rollback("hello", 3).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::NotFound);
+
+        // The next push still gets a fresh version number.
+        let v3 = service.set_digest_by_name("hello", "c").await.unwrap();
+        assert_eq!(v3.get_ref().version, 3);
+    }
+
+    #[tokio::test]
+    async fn test_get_digest_by_selector() {
+        let temp = TempDir::new().unwrap();
+        let service = new_service(&temp).await;
+        service.set_digest_by_name("hello", "a").await.unwrap();
+        service.set_digest_by_name("hello", "b").await.unwrap();
+        service.set_alias("hello", "stable", 1, &[]).await.unwrap();
+
+        let digest = |key: &'static str| {
+            let service = &service;
+            async move {
+                service
+                    .get_digest_by_name(key, "")
+                    .await
+                    .map(|r| r.into_inner().digest)
+            }
+        };
+
+        assert_eq!(digest("hello").await.unwrap(), "b");
+        assert_eq!(digest("hello@1").await.unwrap(), "a");
+        assert_eq!(digest("hello:stable").await.unwrap(), "a");
+        assert_eq!(
+            digest("hello:live").await.unwrap_err().code(),
+            tonic::Code::NotFound
+        );
+        assert_eq!(
+            digest("hello@x").await.unwrap_err().code(),
+            tonic::Code::InvalidArgument
+        );
+    }
+
+    #[tokio::test]
+    async fn test_migrates_unversioned_database() {
+        let temp = TempDir::new().unwrap();
+        let db_path = temp.path().join("digests.db");
+        let pool = SqlitePoolOptions::new()
+            .connect(&format!("sqlite://{}?mode=rwc", db_path.display()))
+            .await
+            .unwrap();
+        sqlx::query(
+            r#"
+            CREATE TABLE digests (
+                name TEXT PRIMARY KEY,
+                digest TEXT NOT NULL,
+                created_at INTEGER DEFAULT (strftime('%s', 'now')),
+                updated_at INTEGER DEFAULT (strftime('%s', 'now'))
+            )
+            "#,
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        sqlx::query("INSERT INTO digests (name, digest) VALUES ('hello', 'a')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        pool.close().await;
+
+        let secret_key = SecretKey::load_or_create(&temp.path().join("secrets.key")).unwrap();
+        let service = DigestService::new(&db_path, secret_key).await.unwrap();
+
+        assert_eq!(
+            service
+                .get_digest_by_name("hello@1", "")
+                .await
+                .unwrap()
+                .get_ref()
+                .digest,
+            "a"
+        );
+        let v2 = service.set_digest_by_name("hello", "b").await.unwrap();
+        assert_eq!(v2.get_ref().version, 2);
+    }
+
+    #[tokio::test]
+    async fn test_weighted_alias_is_sticky_per_routing_key() {
+        let temp = TempDir::new().unwrap();
+        let service = new_service(&temp).await;
+        service.set_digest_by_name("checkout", "a").await.unwrap();
+        service.set_digest_by_name("checkout", "b").await.unwrap();
+        let routes = [
+            AliasRoute {
+                version: 1,
+                weight: 90,
+            },
+            AliasRoute {
+                version: 2,
+                weight: 10,
+            },
+        ];
+        service
+            .set_alias("checkout", "live", 0, &routes)
+            .await
+            .unwrap();
+
+        let mut seen = std::collections::HashSet::new();
+        for i in 0..200 {
+            let key = format!("user-{}", i);
+            let first = service
+                .get_digest_by_name("checkout:live", &key)
+                .await
+                .unwrap()
+                .into_inner();
+            let again = service
+                .get_digest_by_name("checkout:live", &key)
+                .await
+                .unwrap()
+                .into_inner();
+            assert_eq!(first.digest, again.digest);
+            seen.insert(first.digest);
+        }
+        assert_eq!(seen.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_set_alias_rejects_invalid_routes() {
+        let temp = TempDir::new().unwrap();
+        let service = new_service(&temp).await;
+        service.set_digest_by_name("checkout", "a").await.unwrap();
+
+        let route = |version, weight| AliasRoute { version, weight };
+        let cases: [(u64, Vec<AliasRoute>, tonic::Code); 5] = [
+            (0, vec![], tonic::Code::InvalidArgument),
+            (1, vec![route(1, 1)], tonic::Code::InvalidArgument),
+            (0, vec![route(1, 0)], tonic::Code::InvalidArgument),
+            (
+                0,
+                vec![route(1, 1), route(1, 1)],
+                tonic::Code::InvalidArgument,
+            ),
+            (0, vec![route(2, 1)], tonic::Code::NotFound),
+        ];
+        for (version, routes, code) in cases {
+            let err = service
+                .set_alias("checkout", "live", version, &routes)
+                .await
+                .unwrap_err();
+            assert_eq!(err.code(), code);
+        }
     }
 }
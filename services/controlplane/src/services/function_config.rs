@@ -0,0 +1,512 @@
+use std::str::FromStr;
+
+use oci_spec::runtime::Capability;
+use prost::Message;
+use proto::{
+    api::controlplane::{
+        FunctionConfig, GetFunctionConfigResponse, ListProvisionedFunctionsResponse, NetworkMode,
+        ProvisionedFunction, SetFunctionConfigResponse,
+    },
+    egress::EgressRule,
+};
+use tonic::{Response, Status};
+use tracing::{debug, error, info, instrument, warn};
+
+use crate::services::{DigestService, digest_service::db_error};
+
+/// Capabilities that would let a handler bypass its egress allowlist.
+const EGRESS_FORBIDDEN_CAPABILITIES: [&str; 2] = ["CAP_NET_ADMIN", "CAP_NET_RAW"];
+
+/// 1 TiB, far above any machine a worker runs on while the limit in bytes still fits
+/// the cgroup's `i64`.
+const MAX_MEMORY_MB: u64 = 1024 * 1024;
+
+/// Function configs are stored as encoded `FunctionConfig` messages, so new settings
+/// don't need a schema migration and unset ones read back as their defaults.
+impl DigestService {
+    #[instrument(skip(self), fields(name = %name))]
+    pub async fn get_function_config(
+        &self,
+        name: &str,
+    ) -> Result<Response<GetFunctionConfigResponse>, Status> {
+        debug!("Fetching function config from database");
+        let row = sqlx::query_as::<_, (Option<Vec<u8>>,)>(
+            r#"
+            SELECT c.config FROM digests d
+            LEFT JOIN function_configs c ON c.name = d.name
+            WHERE d.name = ?
+            "#,
+        )
+        .bind(name)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(db_error)?;
+
+        let Some((config,)) = row else {
+            warn!("Function not found");
+            return Err(Status::not_found(format!("Function not found: {}", name)));
+        };
+
+        Ok(Response::new(GetFunctionConfigResponse {
+            config: Some(decode_config(config.as_deref())?),
+        }))
+    }
+
+    /// Config of `name`, the defaults when none was ever set.
+    pub(super) async fn load_function_config(&self, name: &str) -> Result<FunctionConfig, Status> {
+        let row =
+            sqlx::query_as::<_, (Vec<u8>,)>("SELECT config FROM function_configs WHERE name = ?")
+                .bind(name)
+                .fetch_optional(&self.pool)
+                .await
+                .map_err(db_error)?;
+
+        decode_config(row.as_ref().map(|(config,)| config.as_slice()))
+    }
+
+    /// Replaces the config of an existing function.
+    #[instrument(skip(self, config), fields(name = %name))]
+    pub async fn set_function_config(
+        &self,
+        name: &str,
+        config: &FunctionConfig,
+    ) -> Result<Response<SetFunctionConfigResponse>, Status> {
+        validate_config(config)?;
+
+        let mut tx = self.pool.begin().await.map_err(db_error)?;
+
+        let exists = sqlx::query_as::<_, (i64,)>("SELECT 1 FROM digests WHERE name = ?")
+            .bind(name)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(db_error)?;
+        if exists.is_none() {
+            warn!("Function not found");
+            return Err(Status::not_found(format!("Function not found: {}", name)));
+        }
+
+        debug!("Upserting function config into database");
+        sqlx::query(
+            r#"
+            INSERT INTO function_configs (name, config, updated_at)
+            VALUES (?, ?, strftime('%s', 'now'))
+            ON CONFLICT(name) DO UPDATE SET
+                config = excluded.config,
+                updated_at = strftime('%s', 'now')
+            "#,
+        )
+        .bind(name)
+        .bind(config.encode_to_vec())
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+
+        tx.commit().await.map_err(db_error)?;
+
+        info!(
+            provisioned_concurrency = config.provisioned_concurrency,
+            "Function config set successfully"
+        );
+        Ok(Response::new(SetFunctionConfigResponse { success: true }))
+    }
+
+    /// Functions with provisioned concurrency, resolved to their current digest.
+    #[instrument(skip(self))]
+    pub async fn list_provisioned_functions(
+        &self,
+    ) -> Result<Response<ListProvisionedFunctionsResponse>, Status> {
+        debug!("Listing provisioned functions");
+        let rows = sqlx::query_as::<_, (String, String, Vec<u8>)>(
+            r#"
+            SELECT d.name, d.digest, c.config FROM function_configs c
+            JOIN digests d ON d.name = c.name
+            ORDER BY d.name
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(db_error)?;
+
+        let mut functions = vec![];
+        for (name, digest, config) in rows {
+            let config = decode_config(Some(&config))?;
+            if config.provisioned_concurrency > 0 {
+                functions.push(ProvisionedFunction {
+                    secrets: self.load_secrets(&name).await?,
+                    name,
+                    digest,
+                    provisioned_concurrency: config.provisioned_concurrency,
+                    config: Some(config),
+                });
+            }
+        }
+
+        info!(count = functions.len(), "Provisioned functions listed");
+        Ok(Response::new(ListProvisionedFunctionsResponse {
+            functions,
+        }))
+    }
+}
+
+fn validate_config(config: &FunctionConfig) -> Result<(), Status> {
+    for (key, value) in &config.env {
+        validate_env_var(key, value)?;
+    }
+    if let Some(limits) = &config.limits
+        && limits.memory_mb > MAX_MEMORY_MB
+    {
+        return Err(Status::invalid_argument(format!(
+            "memory limit must be at most {} MB: {}",
+            MAX_MEMORY_MB, limits.memory_mb
+        )));
+    }
+    if let Some(limits) = &config.limits
+        && limits.io_weight != 0
+        && !(10..=1000).contains(&limits.io_weight)
+    {
+        return Err(Status::invalid_argument(format!(
+            "io weight must be between 10 and 1000: {}",
+            limits.io_weight
+        )));
+    }
+    if let Some(network) = &config.network {
+        if network.mode() != NetworkMode::Egress && !network.egress_allowlist.is_empty() {
+            return Err(Status::invalid_argument(
+                "egress allowlist needs the egress network mode",
+            ));
+        }
+        // Workers would otherwise fail every cold start of the function.
+        for entry in &network.egress_allowlist {
+            entry
+                .parse::<EgressRule>()
+                .map_err(|err| Status::invalid_argument(err.to_string()))?;
+        }
+    }
+    if let Some(security) = &config.security {
+        // Workers skip syscalls their kernel doesn't know, so only the shape is checked.
+        if let Some(syscall) = security.extra_syscalls.iter().find(|syscall| {
+            syscall.is_empty()
+                || !syscall
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+        }) {
+            return Err(Status::invalid_argument(format!(
+                "Invalid syscall name: {}",
+                syscall
+            )));
+        }
+        // Checked against the names workers know, which would fail every cold start.
+        if let Some(capability) = security.capabilities.iter().find(|capability| {
+            !capability
+                .strip_prefix("CAP_")
+                .is_some_and(|name| Capability::from_str(name).is_ok())
+        }) {
+            return Err(Status::invalid_argument(format!(
+                "Invalid capability, expected e.g. CAP_NET_BIND_SERVICE: {}",
+                capability
+            )));
+        }
+
+        // The egress allowlist is a firewall inside the instance's own network
+        // namespace, which these would let the handler change or get around.
+        let egress = config
+            .network
+            .as_ref()
+            .is_some_and(|network| network.mode() == NetworkMode::Egress);
+        if egress {
+            if let Some(capability) = security
+                .capabilities
+                .iter()
+                .find(|capability| EGRESS_FORBIDDEN_CAPABILITIES.contains(&capability.as_str()))
+            {
+                return Err(Status::invalid_argument(format!(
+                    "{} can't be kept with the egress network mode",
+                    capability
+                )));
+            }
+            if security.seccomp_unconfined {
+                return Err(Status::invalid_argument(
+                    "seccomp can't be unconfined with the egress network mode",
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Environment variable names like `DATABASE_URL`, values can't contain NUL bytes.
+pub(super) fn validate_env_var(key: &str, value: &str) -> Result<(), Status> {
+    let valid_key = key
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid_key {
+        return Err(Status::invalid_argument(format!(
+            "Invalid environment variable name: {}",
+            key
+        )));
+    }
+    if value.contains('\0') {
+        return Err(Status::invalid_argument(format!(
+            "Value of {} must not contain NUL bytes",
+            key
+        )));
+    }
+    Ok(())
+}
+
+fn decode_config(config: Option<&[u8]>) -> Result<FunctionConfig, Status> {
+    match config {
+        Some(bytes) => FunctionConfig::decode(bytes).map_err(|e| {
+            error!(error = %e, "Stored function config is corrupted");
+            Status::internal(format!("Corrupted function config: {}", e))
+        }),
+        None => Ok(FunctionConfig::default()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::SecretKey;
+    use proto::api::controlplane::{NetworkConfig, ResourceLimits, SecurityConfig};
+    use tempfile::TempDir;
+
+    async fn new_service(temp: &TempDir) -> DigestService {
+        let secret_key = SecretKey::load_or_create(&temp.path().join("secrets.key")).unwrap();
+        DigestService::new(&temp.path().join("digests.db"), secret_key)
+            .await
+            .unwrap()
+    }
+
+    fn provisioned(count: u32) -> FunctionConfig {
+        FunctionConfig {
+            provisioned_concurrency: count,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_function_config_defaults_and_round_trips() {
+        let temp = TempDir::new().unwrap();
+        let service = new_service(&temp).await;
+        service.set_digest_by_name("hello", "abc").await.unwrap();
+
+        let config = service.get_function_config("hello").await.unwrap();
+        assert_eq!(config.into_inner().config, Some(FunctionConfig::default()));
+
+        service
+            .set_function_config("hello", &provisioned(2))
+            .await
+            .unwrap();
+        let config = service.get_function_config("hello").await.unwrap();
+        assert_eq!(config.into_inner().config, Some(provisioned(2)));
+
+        let digest = service.get_digest_by_name("hello@1", "").await.unwrap();
+        assert_eq!(digest.into_inner().config, Some(provisioned(2)));
+
+        let err = service
+            .set_function_config("missing", &provisioned(1))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::NotFound);
+    }
+
+    #[tokio::test]
+    async fn test_set_function_config_rejects_invalid_io_weight() {
+        let temp = TempDir::new().unwrap();
+        let service = new_service(&temp).await;
+        service.set_digest_by_name("hello", "abc").await.unwrap();
+
+        let config = |io_weight| FunctionConfig {
+            limits: Some(ResourceLimits {
+                memory_mb: 128,
+                io_weight,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let err = service
+            .set_function_config("hello", &config(5))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::InvalidArgument);
+
+        service
+            .set_function_config("hello", &config(100))
+            .await
+            .unwrap();
+        let stored = service.get_function_config("hello").await.unwrap();
+        assert_eq!(stored.into_inner().config, Some(config(100)));
+    }
+
+    #[test]
+    fn test_validate_config_bounds_memory() {
+        let config = |memory_mb| FunctionConfig {
+            limits: Some(ResourceLimits {
+                memory_mb,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        assert!(validate_config(&config(MAX_MEMORY_MB)).is_ok());
+        let err = validate_config(&config(u64::MAX)).unwrap_err();
+        assert_eq!(err.code(), tonic::Code::InvalidArgument);
+    }
+
+    #[tokio::test]
+    async fn test_set_function_config_requires_egress_mode_for_allowlist() {
+        let temp = TempDir::new().unwrap();
+        let service = new_service(&temp).await;
+        service.set_digest_by_name("hello", "abc").await.unwrap();
+
+        let config = |mode: NetworkMode| FunctionConfig {
+            network: Some(NetworkConfig {
+                mode: mode.into(),
+                egress_allowlist: vec!["10.0.0.0/8:443".to_string()],
+            }),
+            ..Default::default()
+        };
+
+        let err = service
+            .set_function_config("hello", &config(NetworkMode::None))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::InvalidArgument);
+
+        service
+            .set_function_config("hello", &config(NetworkMode::Egress))
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn test_validate_config_parses_egress_allowlist() {
+        let config = |entry: &str| FunctionConfig {
+            network: Some(NetworkConfig {
+                mode: NetworkMode::Egress.into(),
+                egress_allowlist: vec!["10.0.0.0/8:443".to_string(), entry.to_string()],
+            }),
+            ..Default::default()
+        };
+
+        assert!(validate_config(&config("[2001:db8::1]:53")).is_ok());
+        for entry in ["example.com", "10.0.0.0/33", "1.2.3.4:0"] {
+            let err = validate_config(&config(entry)).unwrap_err();
+            assert_eq!(err.code(), tonic::Code::InvalidArgument);
+        }
+    }
+
+    #[test]
+    fn test_validate_config_checks_security_names() {
+        let config = |syscall: &str, capability: &str| FunctionConfig {
+            security: Some(SecurityConfig {
+                extra_syscalls: vec![syscall.to_string()],
+                capabilities: vec![capability.to_string()],
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        assert!(validate_config(&config("ptrace", "CAP_NET_BIND_SERVICE")).is_ok());
+        for (syscall, capability) in [
+            ("", "CAP_SYS_PTRACE"),
+            ("Ptrace", "CAP_SYS_PTRACE"),
+            ("ptrace", "SYS_PTRACE"),
+            ("ptrace", "CAP_"),
+            ("ptrace", "cap_sys_ptrace"),
+            ("ptrace", "CAP_MAKE_COFFEE"),
+        ] {
+            let err = validate_config(&config(syscall, capability)).unwrap_err();
+            assert_eq!(err.code(), tonic::Code::InvalidArgument);
+        }
+    }
+
+    #[test]
+    fn test_validate_config_keeps_egress_mode_enforceable() {
+        let config = |mode: NetworkMode, capability: &str, seccomp_unconfined| FunctionConfig {
+            network: Some(NetworkConfig {
+                mode: mode.into(),
+                ..Default::default()
+            }),
+            security: Some(SecurityConfig {
+                seccomp_unconfined,
+                capabilities: vec![capability.to_string()],
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        assert!(validate_config(&config(NetworkMode::Host, "CAP_NET_ADMIN", true)).is_ok());
+        assert!(
+            validate_config(&config(NetworkMode::Egress, "CAP_NET_BIND_SERVICE", false)).is_ok()
+        );
+        for (capability, seccomp_unconfined) in [
+            ("CAP_NET_ADMIN", false),
+            ("CAP_NET_RAW", false),
+            ("CAP_NET_BIND_SERVICE", true),
+        ] {
+            let err = validate_config(&config(NetworkMode::Egress, capability, seccomp_unconfined))
+                .unwrap_err();
+            assert_eq!(err.code(), tonic::Code::InvalidArgument);
+        }
+    }
+
+    #[test]
+    fn test_validate_config_checks_env() {
+        let config = |key: &str, value: &str| FunctionConfig {
+            env: [(key.to_string(), value.to_string())].into(),
+            ..Default::default()
+        };
+
+        assert!(validate_config(&config("LOG_LEVEL", "debug")).is_ok());
+        assert!(validate_config(&config("_X1", "")).is_ok());
+        for (key, value) in [("", "v"), ("9LIVES", "v"), ("A-B", "v"), ("A", "a\0b")] {
+            let err = validate_config(&config(key, value)).unwrap_err();
+            assert_eq!(err.code(), tonic::Code::InvalidArgument);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_list_provisioned_functions_uses_current_digest() {
+        let temp = TempDir::new().unwrap();
+        let service = new_service(&temp).await;
+        for name in ["warm", "cold", "deleted"] {
+            service.set_digest_by_name(name, "v1").await.unwrap();
+        }
+        service
+            .set_function_config("warm", &provisioned(3))
+            .await
+            .unwrap();
+        service
+            .set_function_config("cold", &provisioned(0))
+            .await
+            .unwrap();
+        service
+            .set_function_config("deleted", &provisioned(1))
+            .await
+            .unwrap();
+        service.delete_function("deleted").await.unwrap();
+        service.set_digest_by_name("warm", "v2").await.unwrap();
+
+        let functions = service
+            .list_provisioned_functions()
+            .await
+            .unwrap()
+            .into_inner()
+            .functions;
+        assert_eq!(
+            functions,
+            vec![ProvisionedFunction {
+                name: "warm".to_string(),
+                digest: "v2".to_string(),
+                provisioned_concurrency: 3,
+                config: Some(provisioned(3)),
+                secrets: Default::default(),
+            }]
+        );
+    }
+}
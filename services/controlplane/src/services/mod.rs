@@ -1,2 +1,7 @@
 mod digest_service;
+mod function_config;
+mod routing;
+mod secrets;
+mod selector;
 pub use digest_service::DigestService;
+pub use secrets::SecretKey;
@@ -0,0 +1,97 @@
+use sha2::{Digest, Sha256};
+
+/// One weighted target of an alias.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Route {
+    pub version: i64,
+    pub digest: String,
+    pub weight: i64,
+}
+
+/// Picks a route proportionally to its weight. Requests sharing a routing key always land on
+/// the same route as long as the alias doesn't change, without a key the pick is random.
+pub fn pick_route<'a>(
+    alias: &str,
+    routes: &'a [Route],
+    routing_key: Option<&str>,
+) -> Option<&'a Route> {
+    let total: i64 = routes.iter().map(|r| r.weight).sum();
+    if total <= 0 {
+        return None;
+    }
+
+    let point = match routing_key {
+        Some(key) => (hash_key(alias, key) % total as u64) as i64,
+        None => rand::random_range(0..total),
+    };
+
+    let mut upper = 0;
+    routes.iter().find(|route| {
+        upper += route.weight;
+        point < upper
+    })
+}
+
+fn hash_key(alias: &str, key: &str) -> u64 {
+    let mut hasher = Sha256::new();
+    hasher.update(alias.as_bytes());
+    hasher.update(b":");
+    hasher.update(key.as_bytes());
+    let hash = hasher.finalize();
+    u64::from_be_bytes(hash[..8].try_into().expect("sha256 is 32 bytes"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn routes(weights: &[i64]) -> Vec<Route> {
+        weights
+            .iter()
+            .enumerate()
+            .map(|(i, weight)| Route {
+                version: i as i64 + 1,
+                digest: format!("digest{}", i + 1),
+                weight: *weight,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_pick_route_is_deterministic_for_routing_key() {
+        let routes = routes(&[90, 10]);
+        let first = pick_route("checkout:live", &routes, Some("user-42")).unwrap();
+        for _ in 0..100 {
+            let again = pick_route("checkout:live", &routes, Some("user-42")).unwrap();
+            assert_eq!(first, again);
+        }
+    }
+
+    #[test]
+    fn test_pick_route_follows_weights() {
+        let routes = routes(&[90, 10]);
+        let canary = (0..10_000)
+            .filter(|i| {
+                let key = format!("user-{}", i);
+                pick_route("checkout:live", &routes, Some(&key))
+                    .unwrap()
+                    .version
+                    == 2
+            })
+            .count();
+        assert!(
+            (800..1200).contains(&canary),
+            "canary got {} requests",
+            canary
+        );
+    }
+
+    #[test]
+    fn test_pick_route_skips_zero_weights() {
+        let routes = routes(&[0, 5]);
+        for _ in 0..100 {
+            assert_eq!(pick_route("a", &routes, None).unwrap().version, 2);
+        }
+        assert!(pick_route("a", &[], None).is_none());
+    }
+}
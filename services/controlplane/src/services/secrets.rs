@@ -0,0 +1,348 @@
+use std::{collections::HashMap, fs, io::Write, os::unix::fs::OpenOptionsExt, path::Path};
+
+use proto::api::controlplane::{
+    DeleteSecretResponse, ListSecretsResponse, ResolveFunctionResponse, SetSecretResponse,
+};
+use ring::{
+    aead::{Aad, CHACHA20_POLY1305, LessSafeKey, NONCE_LEN, Nonce, UnboundKey},
+    rand::{SecureRandom, SystemRandom},
+};
+use tonic::{Response, Status};
+use tracing::{debug, error, info, instrument, warn};
+
+use crate::services::{
+    DigestService, digest_service::db_error, function_config::validate_env_var, selector::Selector,
+};
+
+const KEY_LEN: usize = 32;
+
+/// Key sealing function secrets at rest, the database only ever sees ciphertext.
+pub struct SecretKey {
+    key: LessSafeKey,
+    rng: SystemRandom,
+}
+
+impl SecretKey {
+    /// Reads the key at `path`, generating a new one on first start.
+    #[instrument(fields(path = %path.display()))]
+    pub fn load_or_create(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let rng = SystemRandom::new();
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                info!("Generating secret key");
+                let mut bytes = vec![0; KEY_LEN];
+                rng.fill(&mut bytes)
+                    .map_err(|_| "Failed to generate secret key")?;
+                fs::OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .mode(0o600)
+                    .open(path)?
+                    .write_all(&bytes)?;
+                bytes
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let key = UnboundKey::new(&CHACHA20_POLY1305, &bytes)
+            .map_err(|_| format!("Secret key must be {} bytes: {}", KEY_LEN, path.display()))?;
+        Ok(Self {
+            key: LessSafeKey::new(key),
+            rng,
+        })
+    }
+
+    /// Encrypts `value`, bound to its function and key so rows can't be swapped around.
+    fn seal(&self, name: &str, key: &str, value: &str) -> Result<(Vec<u8>, Vec<u8>), Status> {
+        let mut nonce = [0; NONCE_LEN];
+        self.rng
+            .fill(&mut nonce)
+            .map_err(|_| Status::internal("Failed to generate nonce"))?;
+
+        let mut sealed = value.as_bytes().to_vec();
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                aad(name, key),
+                &mut sealed,
+            )
+            .map_err(|_| Status::internal("Failed to encrypt secret"))?;
+        Ok((nonce.to_vec(), sealed))
+    }
+
+    fn open(
+        &self,
+        name: &str,
+        key: &str,
+        nonce: &[u8],
+        mut sealed: Vec<u8>,
+    ) -> Result<String, Status> {
+        let corrupted = || {
+            error!(key, "Stored secret can't be decrypted");
+            Status::internal(format!("Corrupted secret: {}", key))
+        };
+
+        let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| corrupted())?;
+        let value = self
+            .key
+            .open_in_place(nonce, aad(name, key), &mut sealed)
+            .map_err(|_| corrupted())?;
+        String::from_utf8(value.to_vec()).map_err(|_| corrupted())
+    }
+}
+
+// Keys can't contain '/', so this is unambiguous.
+fn aad(name: &str, key: &str) -> Aad<String> {
+    Aad::from(format!("{}/{}", name, key))
+}
+
+/// Secret values are never logged, only their keys.
+impl DigestService {
+    #[instrument(skip(self, value), fields(name = %name, key = %key))]
+    pub async fn set_secret(
+        &self,
+        name: &str,
+        key: &str,
+        value: &str,
+    ) -> Result<Response<SetSecretResponse>, Status> {
+        validate_env_var(key, value)?;
+        let (nonce, sealed) = self.secret_key.seal(name, key, value)?;
+
+        let mut tx = self.pool.begin().await.map_err(db_error)?;
+        self.require_function(&mut tx, name).await?;
+
+        debug!("Upserting secret into database");
+        sqlx::query(
+            r#"
+            INSERT INTO function_secrets (name, key, nonce, value, updated_at)
+            VALUES (?, ?, ?, ?, strftime('%s', 'now'))
+            ON CONFLICT(name, key) DO UPDATE SET
+                nonce = excluded.nonce,
+                value = excluded.value,
+                updated_at = strftime('%s', 'now')
+            "#,
+        )
+        .bind(name)
+        .bind(key)
+        .bind(nonce)
+        .bind(sealed)
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+
+        tx.commit().await.map_err(db_error)?;
+
+        info!("Secret set successfully");
+        Ok(Response::new(SetSecretResponse { success: true }))
+    }
+
+    #[instrument(skip(self), fields(name = %name, key = %key))]
+    pub async fn delete_secret(
+        &self,
+        name: &str,
+        key: &str,
+    ) -> Result<Response<DeleteSecretResponse>, Status> {
+        debug!("Deleting secret from database");
+        let result = sqlx::query("DELETE FROM function_secrets WHERE name = ? AND key = ?")
+            .bind(name)
+            .bind(key)
+            .execute(&self.pool)
+            .await
+            .map_err(db_error)?;
+
+        if result.rows_affected() == 0 {
+            warn!("Secret not found");
+            return Err(Status::not_found(format!(
+                "Secret not found: {} of {}",
+                key, name
+            )));
+        }
+
+        info!("Secret deleted successfully");
+        Ok(Response::new(DeleteSecretResponse { success: true }))
+    }
+
+    #[instrument(skip(self), fields(name = %name))]
+    pub async fn list_secrets(&self, name: &str) -> Result<Response<ListSecretsResponse>, Status> {
+        debug!("Listing secrets");
+        let mut tx = self.pool.begin().await.map_err(db_error)?;
+        self.require_function(&mut tx, name).await?;
+
+        let keys = sqlx::query_as::<_, (String,)>(
+            "SELECT key FROM function_secrets WHERE name = ? ORDER BY key",
+        )
+        .bind(name)
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(db_error)?
+        .into_iter()
+        .map(|(key,)| key)
+        .collect::<Vec<_>>();
+
+        info!(count = keys.len(), "Secrets listed");
+        Ok(Response::new(ListSecretsResponse { keys }))
+    }
+
+    /// Resolves `key` like `get_digest_by_name` with the decrypted secrets added, only
+    /// served to workers.
+    #[instrument(skip(self, routing_key), fields(key = %key))]
+    pub async fn resolve_function(
+        &self,
+        key: &str,
+        routing_key: &str,
+    ) -> Result<Response<ResolveFunctionResponse>, Status> {
+        let name = Selector::parse(key)?.name();
+        let resolved = self
+            .get_digest_by_name(key, routing_key)
+            .await?
+            .into_inner();
+        Ok(Response::new(ResolveFunctionResponse {
+            name: name.to_string(),
+            digest: resolved.digest,
+            version: resolved.version,
+            config: resolved.config,
+            secrets: self.load_secrets(name).await?,
+        }))
+    }
+
+    /// Decrypted secrets of `name`, only handed to workers.
+    pub(super) async fn load_secrets(&self, name: &str) -> Result<HashMap<String, String>, Status> {
+        let rows = sqlx::query_as::<_, (String, Vec<u8>, Vec<u8>)>(
+            "SELECT key, nonce, value FROM function_secrets WHERE name = ?",
+        )
+        .bind(name)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(db_error)?;
+
+        rows.into_iter()
+            .map(|(key, nonce, sealed)| {
+                let value = self.secret_key.open(name, &key, &nonce, sealed)?;
+                Ok((key, value))
+            })
+            .collect()
+    }
+
+    async fn require_function(
+        &self,
+        tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+        name: &str,
+    ) -> Result<(), Status> {
+        let exists = sqlx::query_as::<_, (i64,)>("SELECT 1 FROM digests WHERE name = ?")
+            .bind(name)
+            .fetch_optional(&mut **tx)
+            .await
+            .map_err(db_error)?;
+        if exists.is_none() {
+            warn!("Function not found");
+            return Err(Status::not_found(format!("Function not found: {}", name)));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    async fn new_service(temp: &TempDir) -> DigestService {
+        let secret_key = SecretKey::load_or_create(&temp.path().join("secrets.key")).unwrap();
+        DigestService::new(&temp.path().join("digests.db"), secret_key)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_secrets_round_trip_sealed() {
+        let temp = TempDir::new().unwrap();
+        let service = new_service(&temp).await;
+        service.set_digest_by_name("hello", "abc").await.unwrap();
+
+        service
+            .set_secret("hello", "DATABASE_URL", "postgres://secret")
+            .await
+            .unwrap();
+        service.set_secret("hello", "API_KEY", "one").await.unwrap();
+        service.set_secret("hello", "API_KEY", "two").await.unwrap();
+
+        let keys = service
+            .list_secrets("hello")
+            .await
+            .unwrap()
+            .into_inner()
+            .keys;
+        assert_eq!(keys, ["API_KEY", "DATABASE_URL"]);
+
+        let (stored,) = sqlx::query_as::<_, (Vec<u8>,)>(
+            "SELECT value FROM function_secrets WHERE key = 'DATABASE_URL'",
+        )
+        .fetch_one(&service.pool)
+        .await
+        .unwrap();
+        assert!(!stored.windows(6).any(|w| w == b"secret"));
+
+        let resolved = service
+            .resolve_function("hello@1", "")
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(resolved.name, "hello");
+        assert_eq!(resolved.secrets["API_KEY"], "two");
+        assert_eq!(resolved.secrets["DATABASE_URL"], "postgres://secret");
+
+        service.delete_secret("hello", "API_KEY").await.unwrap();
+        let err = service.delete_secret("hello", "API_KEY").await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::NotFound);
+    }
+
+    #[tokio::test]
+    async fn test_secrets_survive_restart_with_same_key() {
+        let temp = TempDir::new().unwrap();
+        {
+            let service = new_service(&temp).await;
+            service.set_digest_by_name("hello", "abc").await.unwrap();
+            service.set_secret("hello", "TOKEN", "t0k3n").await.unwrap();
+        }
+
+        let service = new_service(&temp).await;
+        let secrets = service.load_secrets("hello").await.unwrap();
+        assert_eq!(secrets["TOKEN"], "t0k3n");
+    }
+
+    #[tokio::test]
+    async fn test_secret_rejects_invalid_key_and_missing_function() {
+        let temp = TempDir::new().unwrap();
+        let service = new_service(&temp).await;
+        service.set_digest_by_name("hello", "abc").await.unwrap();
+
+        for key in ["", "1ABC", "A=B", "A/B"] {
+            let err = service.set_secret("hello", key, "v").await.unwrap_err();
+            assert_eq!(err.code(), tonic::Code::InvalidArgument, "{}", key);
+        }
+
+        let err = service.set_secret("missing", "KEY", "v").await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::NotFound);
+    }
+
+    #[test]
+    fn test_sealed_secret_is_bound_to_its_key() {
+        let temp = TempDir::new().unwrap();
+        let secret_key = SecretKey::load_or_create(&temp.path().join("secrets.key")).unwrap();
+
+        let (nonce, sealed) = secret_key.seal("hello", "A", "value").unwrap();
+        assert_eq!(
+            secret_key
+                .open("hello", "A", &nonce, sealed.clone())
+                .unwrap(),
+            "value"
+        );
+        assert!(
+            secret_key
+                .open("hello", "B", &nonce, sealed.clone())
+                .is_err()
+        );
+        assert!(secret_key.open("other", "A", &nonce, sealed).is_err());
+    }
+}
@@ -0,0 +1,85 @@
+use tonic::Status;
+
+/// What a caller asked for when resolving a function: `name`, `name@version` or `name:alias`.
+#[derive(Debug, PartialEq)]
+pub enum Selector<'a> {
+    Current(&'a str),
+    Version(&'a str, i64),
+    Alias(&'a str, &'a str),
+}
+
+impl<'a> Selector<'a> {
+    pub fn parse(key: &'a str) -> Result<Self, Status> {
+        if let Some((name, version)) = key.split_once('@') {
+            let version = version
+                .parse::<i64>()
+                .ok()
+                .filter(|v| *v > 0)
+                .ok_or_else(|| Status::invalid_argument(format!("invalid version in: {}", key)))?;
+            return Ok(Self::Version(validate_name(name)?, version));
+        }
+
+        if let Some((name, alias)) = key.split_once(':') {
+            return Ok(Self::Alias(validate_name(name)?, validate_name(alias)?));
+        }
+
+        Ok(Self::Current(validate_name(key)?))
+    }
+
+    pub fn name(&self) -> &'a str {
+        match self {
+            Self::Current(name) | Self::Version(name, _) | Self::Alias(name, _) => name,
+        }
+    }
+}
+
+/// Function and alias names can't contain the selector separators.
+pub fn validate_name(name: &str) -> Result<&str, Status> {
+    if name.is_empty() {
+        return Err(Status::invalid_argument("name must not be empty"));
+    }
+    if name.contains(['@', ':']) {
+        return Err(Status::invalid_argument(format!(
+            "name must not contain '@' or ':': {}",
+            name
+        )));
+    }
+    Ok(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_selectors() {
+        assert_eq!(
+            Selector::parse("hello").unwrap(),
+            Selector::Current("hello")
+        );
+        assert_eq!(
+            Selector::parse("hello@3").unwrap(),
+            Selector::Version("hello", 3)
+        );
+        assert_eq!(
+            Selector::parse("hello:live").unwrap(),
+            Selector::Alias("hello", "live")
+        );
+    }
+
+    #[test]
+    fn test_parse_rejects_invalid_selectors() {
+        for key in [
+            "",
+            "hello@",
+            "hello@0",
+            "hello@abc",
+            "hello:",
+            ":live",
+            "a:b:c",
+            "a@1:b",
+        ] {
+            assert!(Selector::parse(key).is_err(), "{} should be rejected", key);
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use std::{net::SocketAddr, path::PathBuf, time::Duration};
+
+use crate::path::get_registry_dir_path;
+
+pub enum StorageConfig {
+    Filesystem {
+        root: PathBuf,
+    },
+    S3 {
+        bucket: String,
+        endpoint: Option<String>,
+        region: Option<String>,
+    },
+}
+
+const DEFAULT_MAX_BUNDLE_SIZE: u64 = 4 * 1024 * 1024 * 1024;
+
+pub struct GcConfig {
+    /// How often the periodic collection runs, `None` disables it.
+    pub interval: Option<Duration>,
+    /// How long a blob stays unreferenced before it may be swept.
+    pub grace_period: Duration,
+}
+
+pub struct ServerConfig {
+    pub addr: SocketAddr,
+    /// Serves the Prometheus metrics.
+    pub metrics_addr: SocketAddr,
+    pub storage: StorageConfig,
+    /// Pushes larger than this many bytes are rejected.
+    pub max_bundle_size: u64,
+    /// Where incoming pushes are written before they reach the store.
+    pub spool_dir: PathBuf,
+    pub controlplane_addr: String,
+    pub gc: GcConfig,
+}
+
+impl ServerConfig {
+    pub fn from_env() -> Self {
+        let addr = std::env::var("SERVER_ADDR")
+            .unwrap_or_else(|_| "[::1]:50001".to_string())
+            .parse()
+            .expect("Invalid server address");
+        let metrics_addr = std::env::var("METRICS_ADDR")
+            .unwrap_or_else(|_| "[::1]:9001".to_string())
+            .parse()
+            .expect("Invalid metrics address");
+
+        let storage = match std::env::var("REGISTRY_STORAGE").as_deref() {
+            Ok("s3") => StorageConfig::S3 {
+                bucket: std::env::var("REGISTRY_S3_BUCKET").expect("Missing REGISTRY_S3_BUCKET"),
+                endpoint: std::env::var("REGISTRY_S3_ENDPOINT").ok(),
+                region: std::env::var("REGISTRY_S3_REGION").ok(),
+            },
+            Ok("fs") | Err(_) => StorageConfig::Filesystem {
+                root: std::env::var("REGISTRY_FS_ROOT")
+                    .map(PathBuf::from)
+                    .unwrap_or_else(|_| get_registry_dir_path()),
+            },
+            Ok(other) => panic!("Invalid REGISTRY_STORAGE: {}", other),
+        };
+
+        let max_bundle_size = std::env::var("REGISTRY_MAX_BUNDLE_SIZE")
+            .map(|v| v.parse().expect("Invalid REGISTRY_MAX_BUNDLE_SIZE"))
+            .unwrap_or(DEFAULT_MAX_BUNDLE_SIZE);
+
+        // Spooling inside the filesystem store lets finished pushes be renamed into place.
+        let spool_dir = std::env::var("REGISTRY_SPOOL_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| match &storage {
+                StorageConfig::Filesystem { root } => root.clone(),
+                StorageConfig::S3 { .. } => std::env::temp_dir(),
+            });
+
+        let controlplane_addr =
+            std::env::var("CONTROLPLANE_ADDR").unwrap_or_else(|_| "http://[::1]:50002".to_string());
+
+        let interval = std::env::var("REGISTRY_GC_INTERVAL")
+            .map(|v| v.parse::<u64>().expect("Invalid REGISTRY_GC_INTERVAL"))
+            .unwrap_or(600);
+        let grace_period = std::env::var("REGISTRY_GC_GRACE_PERIOD")
+            .map(|v| v.parse::<u64>().expect("Invalid REGISTRY_GC_GRACE_PERIOD"))
+            .unwrap_or(3600);
+
+        Self {
+            addr,
+            metrics_addr,
+            storage,
+            max_bundle_size,
+            spool_dir,
+            controlplane_addr,
+            gc: GcConfig {
+                interval: (interval > 0).then(|| Duration::from_secs(interval)),
+                grace_period: Duration::from_secs(grace_period),
+            },
+        }
+    }
+}
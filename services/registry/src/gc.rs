@@ -0,0 +1,303 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io,
+    sync::Arc,
+    time::Duration,
+};
+
+use proto::api::controlplane::{
+    ListReferencedDigestsRequest, control_plane_service_client::ControlPlaneServiceClient,
+};
+use tokio::{
+    sync::Mutex,
+    time::{Instant, sleep},
+};
+use tonic::{Request, Status};
+use tracing::{debug, error, info, instrument, warn};
+
+use crate::store::BlobStore;
+
+/// Source of truth for which digests must be kept.
+#[allow(clippy::double_must_use)]
+#[tonic::async_trait]
+pub trait DigestReferences: Send + Sync {
+    async fn referenced_digests(&self) -> Result<HashSet<String>, Status>;
+}
+
+pub struct ControlPlaneReferences {
+    addr: String,
+}
+
+impl ControlPlaneReferences {
+    pub fn new(addr: String) -> Self {
+        Self { addr }
+    }
+}
+
+#[tonic::async_trait]
+impl DigestReferences for ControlPlaneReferences {
+    async fn referenced_digests(&self) -> Result<HashSet<String>, Status> {
+        let mut client = telemetry::connect(self.addr.clone())
+            .await
+            .map(ControlPlaneServiceClient::new)
+            .map_err(|err| {
+                warn!(addr = %self.addr, error = %err, "Failed to connect to control plane");
+                Status::unavailable(format!("failed to connect to control plane: {}", err))
+            })?;
+
+        let response = client
+            .list_referenced_digests(Request::new(ListReferencedDigestsRequest {}))
+            .await?
+            .into_inner();
+
+        Ok(response.digests.into_iter().collect())
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct GcReport {
+    pub swept: Vec<String>,
+    pub marked: Vec<String>,
+}
+
+/// Mark and sweep collection of blobs no function can resolve to anymore.
+///
+/// An unreferenced blob is marked the first time a run sees it and only swept by a
+/// later run once `grace_period` has passed, which leaves time for a fresh push to
+/// be registered with the control plane. Marks live in memory, so a restart only
+/// delays sweeping.
+pub struct GarbageCollector {
+    store: Arc<dyn BlobStore>,
+    references: Arc<dyn DigestReferences>,
+    grace_period: Duration,
+    marks: Mutex<HashMap<String, Instant>>,
+}
+
+impl GarbageCollector {
+    pub fn new(
+        store: Arc<dyn BlobStore>,
+        references: Arc<dyn DigestReferences>,
+        grace_period: Duration,
+    ) -> Self {
+        Self {
+            store,
+            references,
+            grace_period,
+            marks: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Runs `collect` every `interval` until the process exits.
+    pub fn start(self: &Arc<Self>, interval: Duration) {
+        info!(interval = ?interval, grace_period = ?self.grace_period, "Starting garbage collector");
+        let gc = self.clone();
+        tokio::spawn(async move {
+            loop {
+                sleep(interval).await;
+                if let Err(err) = gc.collect(false).await {
+                    error!(error = %err, "Garbage collection failed");
+                }
+            }
+        });
+    }
+
+    /// Drops the mark of `digest`, so a freshly pushed blob gets the whole grace period
+    /// to be registered again. Waits for a running pass, which may still sweep it.
+    pub async fn unmark(&self, digest: &str) {
+        if self.marks.lock().await.remove(digest).is_some() {
+            debug!(digest = %digest, "Unmarked pushed blob");
+        }
+    }
+
+    /// One mark and sweep pass, `dry_run` reports without deleting or marking.
+    #[instrument(name = "Garbage collect", skip(self))]
+    pub async fn collect(&self, dry_run: bool) -> Result<GcReport, Status> {
+        // Holding the marks for the whole pass keeps runs from overlapping.
+        let mut marks = self.marks.lock().await;
+
+        let blobs = self.store.list().await.map_err(|err| {
+            error!(error = %err, "Failed to list blob store");
+            Status::internal(format!("failed to list blobs: {:?}", err))
+        })?;
+        let referenced = self.references.referenced_digests().await.map_err(|err| {
+            error!(error = %err, "Failed to fetch referenced digests");
+            err
+        })?;
+
+        let unreferenced: Vec<String> = blobs
+            .into_iter()
+            .filter(|digest| !referenced.contains(digest))
+            .collect();
+        debug!(
+            referenced = referenced.len(),
+            unreferenced = unreferenced.len(),
+            "Computed unreferenced blobs"
+        );
+
+        let now = Instant::now();
+        let mut report = GcReport::default();
+        let mut next_marks = HashMap::new();
+
+        for digest in unreferenced {
+            let marked_at = marks.get(&digest).copied();
+            let expired = marked_at.is_some_and(|at| now - at >= self.grace_period);
+
+            if !expired {
+                next_marks.insert(digest.clone(), marked_at.unwrap_or(now));
+                report.marked.push(digest);
+                continue;
+            }
+
+            if dry_run {
+                report.swept.push(digest);
+                continue;
+            }
+
+            match self.store.delete(&digest).await {
+                Ok(()) => {
+                    info!(digest = %digest, "Swept unreferenced blob");
+                    report.swept.push(digest);
+                }
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                    report.swept.push(digest);
+                }
+                Err(err) => {
+                    warn!(digest = %digest, error = %err, "Failed to sweep blob, will retry");
+                    next_marks.insert(digest, marked_at.unwrap_or(now));
+                }
+            }
+        }
+
+        if !dry_run {
+            // Referenced or vanished blobs drop their marks.
+            *marks = next_marks;
+        }
+
+        info!(
+            dry_run = dry_run,
+            swept = report.swept.len(),
+            marked = report.marked.len(),
+            "Garbage collection finished"
+        );
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::FsBlobStore;
+    use std::sync::Mutex as StdMutex;
+    use tempfile::TempDir;
+
+    struct StaticReferences(StdMutex<HashSet<String>>);
+
+    impl StaticReferences {
+        fn new(digests: &[&str]) -> Arc<Self> {
+            Arc::new(Self(StdMutex::new(
+                digests.iter().map(|d| d.to_string()).collect(),
+            )))
+        }
+    }
+
+    #[tonic::async_trait]
+    impl DigestReferences for StaticReferences {
+        async fn referenced_digests(&self) -> Result<HashSet<String>, Status> {
+            Ok(self.0.lock().unwrap().clone())
+        }
+    }
+
+    async fn store_with(temp: &TempDir, keys: &[&str]) -> Arc<FsBlobStore> {
+        let store = Arc::new(FsBlobStore::new(temp.path().to_path_buf()));
+        for key in keys {
+            store.put(key, Box::pin(&b"data"[..])).await.unwrap();
+        }
+        store
+    }
+
+    #[tokio::test]
+    async fn test_gc_marks_then_sweeps_unreferenced() {
+        let temp = TempDir::new().unwrap();
+        let store = store_with(&temp, &["beef", "dead"]).await;
+        let gc = GarbageCollector::new(
+            store.clone(),
+            StaticReferences::new(&["beef"]),
+            Duration::ZERO,
+        );
+
+        let first = gc.collect(false).await.unwrap();
+        assert_eq!(first.marked, vec!["dead"]);
+        assert!(first.swept.is_empty());
+        assert!(store.exists("dead").await.unwrap());
+
+        let second = gc.collect(false).await.unwrap();
+        assert_eq!(second.swept, vec!["dead"]);
+        assert!(!store.exists("dead").await.unwrap());
+        assert!(store.exists("beef").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_gc_waits_for_grace_period() {
+        let temp = TempDir::new().unwrap();
+        let store = store_with(&temp, &["dead"]).await;
+        let gc = GarbageCollector::new(
+            store.clone(),
+            StaticReferences::new(&[]),
+            Duration::from_secs(3600),
+        );
+
+        gc.collect(false).await.unwrap();
+        let report = gc.collect(false).await.unwrap();
+        assert_eq!(report.marked, vec!["dead"]);
+        assert!(report.swept.is_empty());
+        assert!(store.exists("dead").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_gc_dry_run_keeps_blobs_and_marks() {
+        let temp = TempDir::new().unwrap();
+        let store = store_with(&temp, &["dead"]).await;
+        let gc = GarbageCollector::new(store.clone(), StaticReferences::new(&[]), Duration::ZERO);
+
+        // A dry run does not mark, so the real run after it only marks.
+        assert_eq!(gc.collect(true).await.unwrap().marked, vec!["dead"]);
+        assert_eq!(gc.collect(false).await.unwrap().marked, vec!["dead"]);
+
+        let report = gc.collect(true).await.unwrap();
+        assert_eq!(report.swept, vec!["dead"]);
+        assert!(store.exists("dead").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_gc_unmarks_blobs_referenced_again() {
+        let temp = TempDir::new().unwrap();
+        let store = store_with(&temp, &["fade"]).await;
+        let references = StaticReferences::new(&[]);
+        let gc = GarbageCollector::new(store.clone(), references.clone(), Duration::ZERO);
+
+        gc.collect(false).await.unwrap();
+        references.0.lock().unwrap().insert("fade".to_string());
+        assert_eq!(gc.collect(false).await.unwrap(), GcReport::default());
+
+        // Unreferenced again starts a new grace period instead of sweeping at once.
+        references.0.lock().unwrap().clear();
+        assert_eq!(gc.collect(false).await.unwrap().marked, vec!["fade"]);
+        assert!(store.exists("fade").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_gc_unmark_restarts_grace_period() {
+        let temp = TempDir::new().unwrap();
+        let store = store_with(&temp, &["dead"]).await;
+        let gc = GarbageCollector::new(store.clone(), StaticReferences::new(&[]), Duration::ZERO);
+
+        gc.collect(false).await.unwrap();
+        gc.unmark("dead").await;
+
+        // Pushed again, so the next run marks it anew instead of sweeping it.
+        let report = gc.collect(false).await.unwrap();
+        assert_eq!(report.marked, vec!["dead"]);
+        assert!(report.swept.is_empty());
+        assert!(store.exists("dead").await.unwrap());
+    }
+}
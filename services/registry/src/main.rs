@@ -1,22 +1,61 @@
+use std::sync::Arc;
+
 use proto::api::registry::registry_service_server::RegistryServiceServer;
 use tonic::transport::Server;
 use tracing::info;
 
+mod config;
+mod gc;
+mod metrics;
 mod path;
 mod registry;
+mod store;
+mod verify;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    tracing_subscriber::fmt().with_target(false).init();
+    let _tracing = telemetry::init("noctiforge-registry")?;
+
+    let config = config::ServerConfig::from_env();
+    let store = store::from_config(&config.storage)?;
+
+    // `registry fsck` checks the store offline instead of serving.
+    if std::env::args().nth(1).as_deref() == Some("fsck") {
+        let report = verify::verify(store.as_ref(), &[]).await?;
+        for blob in &report.corrupted {
+            println!("{}: {}", blob.digest, blob.reason);
+        }
+        println!(
+            "{} checked, {} corrupted",
+            report.checked,
+            report.corrupted.len()
+        );
+        std::process::exit(if report.corrupted.is_empty() { 0 } else { 1 });
+    }
+
+    telemetry::serve_metrics(config.metrics_addr)?;
+    info!("Metrics listening on {}", config.metrics_addr);
+
+    let gc = Arc::new(gc::GarbageCollector::new(
+        store.clone(),
+        Arc::new(gc::ControlPlaneReferences::new(
+            config.controlplane_addr.clone(),
+        )),
+        config.gc.grace_period,
+    ));
+    if let Some(interval) = config.gc.interval {
+        gc.start(interval);
+    }
 
-    let addr = "[::1]:50001".parse().unwrap();
-    let file_engine = registry::LocalBackend::default();
+    let registry =
+        registry::Registry::new(store, config.max_bundle_size, config.spool_dir.clone(), gc);
 
-    info!("RegistryServiceServer listening on {}", addr);
+    info!("RegistryServiceServer listening on {}", config.addr);
 
     Server::builder()
-        .add_service(RegistryServiceServer::new(file_engine))
-        .serve(addr)
+        .trace_fn(telemetry::server_span)
+        .add_service(RegistryServiceServer::new(registry))
+        .serve(config.addr)
         .await?;
 
     Ok(())
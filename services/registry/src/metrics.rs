@@ -0,0 +1,67 @@
+//! Metrics the registry exports on `METRICS_ADDR`.
+
+use std::time::{Duration, Instant};
+
+use metrics::{counter, histogram};
+
+const PUSH_BYTES: &str = "noctiforge_registry_push_bytes_total";
+const PUSH_DURATION: &str = "noctiforge_registry_push_duration_seconds";
+const PULL_BYTES: &str = "noctiforge_registry_pull_bytes_total";
+const PULL_DURATION: &str = "noctiforge_registry_pull_duration_seconds";
+
+/// Bytes received by a push that made it into the spool.
+pub fn pushed(bytes: u64) {
+    counter!(PUSH_BYTES).increment(bytes);
+}
+
+pub fn push_duration(elapsed: Duration) {
+    histogram!(PUSH_DURATION).record(elapsed);
+}
+
+pub fn pulled(bytes: usize) {
+    counter!(PULL_BYTES).increment(bytes as u64);
+}
+
+/// Records how long a pull streamed once its response stream is dropped, whether it
+/// ran to the end or the caller went away.
+pub struct PullTimer {
+    started: Instant,
+}
+
+impl PullTimer {
+    pub fn start() -> Self {
+        Self {
+            started: Instant::now(),
+        }
+    }
+}
+
+impl Drop for PullTimer {
+    fn drop(&mut self) {
+        histogram!(PULL_DURATION).record(self.started.elapsed());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pull_duration_is_recorded_on_drop() {
+        let recorder = telemetry::builder().build_recorder();
+        let handle = recorder.handle();
+
+        metrics::with_local_recorder(&recorder, || {
+            let timer = PullTimer::start();
+            pulled(64);
+            pulled(36);
+            drop(timer);
+            pushed(10);
+        });
+
+        let rendered = handle.render();
+        assert!(rendered.contains("noctiforge_registry_pull_bytes_total 100"));
+        assert!(rendered.contains("noctiforge_registry_pull_duration_seconds_count 1"));
+        assert!(rendered.contains("noctiforge_registry_push_bytes_total 10"));
+    }
+}
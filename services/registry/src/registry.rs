@@ -1,86 +1,122 @@
-use std::{io::Cursor, pin::Pin};
+use std::{
+    io,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::Arc,
+    time::Instant,
+};
 
 use proto::api::registry::{
-    RegistryPullRequest, RegistryPullResponse, RegistryPushRequest, RegistryPushResponse,
+    CorruptedBlob, GarbageCollectRequest, GarbageCollectResponse, RegistryPullRequest,
+    RegistryPullResponse, RegistryPushRequest, RegistryPushResponse, VerifyRequest, VerifyResponse,
     registry_service_server::RegistryService,
 };
-use sha2::{Digest, Sha256};
 use tokio::{
-    fs::{read, write},
-    io::AsyncReadExt,
+    fs::{self, File},
+    io::AsyncWriteExt,
 };
 use tokio_stream::{Stream, StreamExt};
 use tokio_tar::Archive;
+use tokio_util::io::ReaderStream;
 use tonic::{Request, Response, Result, Status, Streaming};
 use tracing::{debug, error, info, instrument, warn};
+use uuid::Uuid;
 
-use crate::path::get_registry_path;
+use crate::{
+    gc::GarbageCollector,
+    metrics::{self, PullTimer},
+    store::BlobStore,
+    verify,
+};
 
 const CHUNK_SIZE: usize = 64 * 1024;
+const SPOOL_PREFIX: &str = ".spool-";
 
-#[derive(Default)]
-pub struct LocalBackend {}
+pub struct Registry {
+    store: Arc<dyn BlobStore>,
+    max_bundle_size: u64,
+    spool_dir: PathBuf,
+    gc: Arc<GarbageCollector>,
+}
 
-#[tonic::async_trait]
-impl RegistryService for LocalBackend {
-    type PullStream =
-        Pin<Box<dyn Stream<Item = Result<RegistryPullResponse, Status>> + Send + 'static>>;
+impl Registry {
+    pub fn new(
+        store: Arc<dyn BlobStore>,
+        max_bundle_size: u64,
+        spool_dir: PathBuf,
+        gc: Arc<GarbageCollector>,
+    ) -> Self {
+        Self {
+            store,
+            max_bundle_size,
+            spool_dir,
+            gc,
+        }
+    }
 
-    #[instrument(
-        name = "Registry pull",
-        skip(self, request),
-        fields(digest = %request.get_ref().digest)
-    )]
-    async fn pull(
+    /// Spools the push and moves it into the store unless the digest is already there,
+    /// either way restarting its garbage collection grace period.
+    async fn store_push(
         &self,
-        request: Request<RegistryPullRequest>,
-    ) -> Result<Response<Self::PullStream>, Status> {
-        let req = request.into_inner();
-        let request_path = get_registry_path(&req.digest);
+        request_stream: Streaming<RegistryPushRequest>,
+        spool_path: &Path,
+    ) -> Result<String> {
+        let (digest, size_bytes) = self.spool(request_stream, spool_path).await?;
+        metrics::pushed(size_bytes);
 
-        debug!(path = %request_path.display(), "Reading tar from registry path");
+        info!(digest = %digest, "Computed digest successfully");
 
-        let data = read(&request_path).await.map_err(|err| {
-            error!(
-                path = %request_path.display(),
-                error = %err,
-                "Failed to read from registry path"
-            );
-            Status::internal(format!("failed to read store path: {:?}", err))
+        // Unmarking waits out a running collection, so the existence check below sees
+        // whether that pass swept the blob and it has to be written again.
+        self.gc.unmark(&digest).await;
+
+        let exists = self.store.exists(&digest).await.map_err(|err| {
+            error!(digest = %digest, error = %err, "Failed to check blob store");
+            Status::internal(format!("failed to check blob store: {:?}", err))
         })?;
-        let data_size = data.len();
-        let chunk_count = data_size.div_ceil(CHUNK_SIZE);
 
-        info!(
-            digest = %req.digest,
-            size_bytes = data_size,
-            chunk_count = chunk_count,
-            chunk_size = CHUNK_SIZE,
-            "Successfully read tar, streaming chunks"
-        );
+        if exists {
+            info!(digest = %digest, "Digest already exists in registry, skipping write");
+        } else {
+            debug!(
+                digest = %digest,
+                size_bytes = size_bytes,
+                "Writing tar to blob store"
+            );
 
-        let stream = tokio_stream::iter(
-            data.chunks(CHUNK_SIZE)
-                .map(|chunk| {
-                    Ok(RegistryPullResponse {
-                        data: chunk.to_vec(),
-                    })
-                })
-                .collect::<Vec<_>>(),
-        );
+            self.store
+                .put_file(&digest, spool_path)
+                .await
+                .map_err(|err| {
+                    error!(digest = %digest, error = %err, "Failed to write to blob store");
+                    Status::internal(format!("failed to write blob: {:?}", err))
+                })?;
 
-        Ok(Response::new(Box::pin(stream)))
+            info!(digest = %digest, "Successfully written to registry");
+        }
+
+        Ok(digest)
     }
 
-    #[instrument(name = "Registry push", skip(self, request))]
-    async fn push(
+    /// Writes the push stream to `spool_path` while hashing the archive on the fly.
+    /// Returns the digest and the number of bytes received.
+    async fn spool(
         &self,
-        request: Request<Streaming<RegistryPushRequest>>,
-    ) -> Result<Response<RegistryPushResponse>, Status> {
-        debug!("Starting to receive push stream");
+        mut request_stream: Streaming<RegistryPushRequest>,
+        spool_path: &Path,
+    ) -> Result<(String, u64)> {
+        let mut file = File::create(spool_path).await.map_err(|err| {
+            error!(error = %err, "Failed to create spool file");
+            Status::internal(format!("failed to create spool file: {:?}", err))
+        })?;
+
+        // The digest task reads the archive through a bounded pipe, so hashing keeps
+        // pace with the upload instead of needing the whole bundle up front.
+        let (mut pipe, reader) = tokio::io::duplex(CHUNK_SIZE);
+        let digest_task = tokio::spawn(bundle::inspect(Archive::new(reader)));
+        let mut hashing = true;
 
-        let mut request_data: Vec<u8> = vec![];
-        let mut request_stream = request.into_inner();
+        let mut total_bytes: u64 = 0;
         let mut chunk_count = 0;
 
         while let Some(request) = request_stream.next().await {
@@ -90,104 +126,394 @@ impl RegistryService for LocalBackend {
             })?;
 
             chunk_count += 1;
-            request_data.extend_from_slice(&request.data);
+            total_bytes += request.data.len() as u64;
+
+            if total_bytes > self.max_bundle_size {
+                warn!(
+                    total_bytes = total_bytes,
+                    max_bundle_size = self.max_bundle_size,
+                    "Bundle exceeds maximum size"
+                );
+                return Err(Status::resource_exhausted(format!(
+                    "bundle exceeds maximum size of {} bytes",
+                    self.max_bundle_size
+                )));
+            }
+
+            file.write_all(&request.data).await.map_err(|err| {
+                error!(error = %err, "Failed to write spool file");
+                Status::internal(format!("failed to write spool file: {:?}", err))
+            })?;
+
+            // The archive reader stops at the end-of-archive marker, anything after it
+            // is padding that does not contribute to the digest.
+            if hashing && pipe.write_all(&request.data).await.is_err() {
+                hashing = false;
+            }
 
             if chunk_count % 10 == 0 {
                 debug!(
                     chunks_received = chunk_count,
-                    total_bytes = request_data.len(),
+                    total_bytes = total_bytes,
                     "Receiving data..."
                 );
             }
         }
+        drop(pipe);
 
         info!(
             total_chunks = chunk_count,
-            total_bytes = request_data.len(),
+            total_bytes = total_bytes,
             "Completed receiving all chunks"
         );
 
-        if request_data.is_empty() {
+        if total_bytes == 0 {
             warn!("Received empty data");
             return Err(Status::invalid_argument("missing `data` field"));
         }
 
-        debug!("Validating tar archive");
-        let cursor = Cursor::new(&request_data);
-        let mut archive = Archive::new(cursor);
-        if let Err(err) = archive.entries() {
-            error!(error = %err, "Invalid tar archive received");
+        file.flush().await?;
+        file.sync_all().await?;
+
+        let inspection = digest_task
+            .await
+            .map_err(|err| Status::internal(format!("digest task failed: {:?}", err)))?
+            .map_err(|err| {
+                error!(error = %err, "Invalid tar archive received");
+                Status::invalid_argument(format!("invalid tar archive: {}", err))
+            })?;
+
+        if !inspection.violations.is_empty() {
+            let offenders = inspection
+                .violations
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ");
+            warn!(offenders = %offenders, "Rejected archive with unsafe entries");
             return Err(Status::invalid_argument(format!(
-                "invalid tar archive: {}",
-                err
+                "unsafe entries in archive: {}",
+                offenders
             )));
         }
 
-        debug!("Computing digest");
-        let digest = get_digest(archive).await.map_err(|err| {
-            error!(error = %err, "Failed to compute digest");
-            err
+        Ok((inspection.digest, total_bytes))
+    }
+}
+
+#[tonic::async_trait]
+impl RegistryService for Registry {
+    type PullStream =
+        Pin<Box<dyn Stream<Item = Result<RegistryPullResponse, Status>> + Send + 'static>>;
+
+    #[instrument(
+        name = "Registry pull",
+        skip(self, request),
+        fields(digest = %request.get_ref().digest)
+    )]
+    async fn pull(
+        &self,
+        request: Request<RegistryPullRequest>,
+    ) -> Result<Response<Self::PullStream>, Status> {
+        let req = request.into_inner();
+
+        debug!(digest = %req.digest, "Opening tar in blob store");
+
+        let reader = self.store.get(&req.digest).await.map_err(|err| {
+            error!(digest = %req.digest, error = %err, "Failed to read from blob store");
+            match err.kind() {
+                io::ErrorKind::NotFound => {
+                    Status::not_found(format!("digest not found: {}", req.digest))
+                }
+                io::ErrorKind::InvalidInput => Status::invalid_argument(err.to_string()),
+                _ => Status::internal(format!("failed to read blob: {:?}", err)),
+            }
         })?;
 
-        info!(digest = %digest, "Computed digest successfully");
+        info!(
+            digest = %req.digest,
+            chunk_size = CHUNK_SIZE,
+            "Streaming tar chunks"
+        );
 
-        let request_path = get_registry_path(&digest);
+        let digest = req.digest;
+        let timer = PullTimer::start();
+        let stream = ReaderStream::with_capacity(reader, CHUNK_SIZE).map(move |chunk| {
+            // Owned by the stream, so the pull is timed until it is dropped.
+            let _ = &timer;
+            chunk
+                .map(|data| {
+                    metrics::pulled(data.len());
+                    RegistryPullResponse {
+                        data: data.to_vec(),
+                    }
+                })
+                .map_err(|err| {
+                    error!(digest = %digest, error = %err, "Failed to stream blob");
+                    Status::internal(format!("failed to read blob: {:?}", err))
+                })
+        });
 
-        if request_path.exists() {
-            info!(
-                digest = %digest,
-                path = %request_path.display(),
-                "Digest already exists in registry, skipping write"
-            );
-        } else {
-            debug!(
-                digest = %digest,
-                path = %request_path.display(),
-                size_bytes = request_data.len(),
-                "Writing tar to registry"
-            );
+        Ok(Response::new(Box::pin(stream)))
+    }
 
-            write(&request_path, &request_data).await.map_err(|err| {
-                error!(
-                    path = %request_path.display(),
-                    error = %err,
-                    "Failed to write to registry path"
-                );
-                Status::internal(format!("failed to write store path: {:?}", err))
-            })?;
+    #[instrument(name = "Registry push", skip(self, request))]
+    async fn push(
+        &self,
+        request: Request<Streaming<RegistryPushRequest>>,
+    ) -> Result<Response<RegistryPushResponse>, Status> {
+        debug!("Starting to receive push stream");
+        let started = Instant::now();
 
-            info!(
-                digest = %digest,
-                path = %request_path.display(),
-                "Successfully written to registry"
-            );
-        }
+        let spool_path = self
+            .spool_dir
+            .join(format!("{}{}", SPOOL_PREFIX, Uuid::new_v4()));
+        let result = self.store_push(request.into_inner(), &spool_path).await;
 
-        Ok(Response::new(RegistryPushResponse { digest }))
+        // Already gone when the store renamed it into place.
+        let _ = fs::remove_file(&spool_path).await;
+        metrics::push_duration(started.elapsed());
+
+        result.map(|digest| Response::new(RegistryPushResponse { digest }))
+    }
+
+    #[instrument(
+        name = "Registry garbage collect",
+        skip(self, request),
+        fields(dry_run = request.get_ref().dry_run)
+    )]
+    async fn garbage_collect(
+        &self,
+        request: Request<GarbageCollectRequest>,
+    ) -> Result<Response<GarbageCollectResponse>, Status> {
+        let req = request.into_inner();
+        debug!(dry_run = req.dry_run, "Received request to garbage collect");
+
+        let report = self.gc.collect(req.dry_run).await?;
+
+        Ok(Response::new(GarbageCollectResponse {
+            swept: report.swept,
+            marked: report.marked,
+        }))
+    }
+
+    #[instrument(
+        name = "Registry verify",
+        skip(self, request),
+        fields(requested = request.get_ref().digests.len())
+    )]
+    async fn verify(
+        &self,
+        request: Request<VerifyRequest>,
+    ) -> Result<Response<VerifyResponse>, Status> {
+        let req = request.into_inner();
+        debug!(
+            requested = req.digests.len(),
+            "Received request to verify blobs"
+        );
+
+        let report = verify::verify(self.store.as_ref(), &req.digests)
+            .await
+            .map_err(|err| {
+                error!(error = %err, "Failed to verify blob store");
+                Status::internal(format!("failed to verify blobs: {:?}", err))
+            })?;
+
+        Ok(Response::new(VerifyResponse {
+            checked: report.checked,
+            corrupted: report
+                .corrupted
+                .into_iter()
+                .map(|c| CorruptedBlob {
+                    digest: c.digest,
+                    reason: c.reason,
+                })
+                .collect(),
+        }))
     }
 }
 
-async fn get_digest<T: std::marker::Unpin + tokio::io::AsyncRead>(
-    mut archive: Archive<T>,
-) -> Result<String> {
-    let mut hasher = Sha256::new();
-
-    let mut entries = archive.entries()?;
-    while let Some(file) = entries.next().await {
-        let mut entry = file?;
-        let path = entry.path()?.to_string_lossy().to_string();
-
-        if entry.header().entry_type().is_file() {
-            let mut buf = Vec::new();
-            entry.read_to_end(&mut buf).await?;
-            hasher.update(b"file:"); // prefix to differentiate files/folders
-            hasher.update(path.as_bytes());
-            hasher.update(&buf);
-        } else if entry.header().entry_type().is_dir() {
-            hasher.update(b"dir:"); // prefix for directories
-            hasher.update(path.as_bytes());
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{gc::ControlPlaneReferences, store::FsBlobStore};
+    use proto::api::registry::{
+        registry_service_client::RegistryServiceClient,
+        registry_service_server::RegistryServiceServer,
+    };
+    use std::time::Duration;
+    use tempfile::TempDir;
+    use tokio::net::TcpListener;
+    use tokio_stream::wrappers::TcpListenerStream;
+    use tonic::{Code, transport::Server};
+
+    async fn start(
+        root: PathBuf,
+        max_bundle_size: u64,
+    ) -> RegistryServiceClient<tonic::transport::Channel> {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let store = Arc::new(FsBlobStore::new(root.clone()));
+        // Collection is covered in `gc`, this address is never dialed.
+        let gc = Arc::new(GarbageCollector::new(
+            store.clone(),
+            Arc::new(ControlPlaneReferences::new("http://[::1]:1".to_string())),
+            Duration::ZERO,
+        ));
+        let registry = Registry::new(store, max_bundle_size, root, gc);
+        tokio::spawn(
+            Server::builder()
+                .add_service(RegistryServiceServer::new(registry))
+                .serve_with_incoming(TcpListenerStream::new(listener)),
+        );
+        RegistryServiceClient::connect(format!("http://{}", addr))
+            .await
+            .unwrap()
+    }
+
+    async fn archive(content: &[u8]) -> Vec<u8> {
+        let mut builder = tokio_tar::Builder::new(vec![]);
+        let mut header = tokio_tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o755);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "bootstrap", content)
+            .await
+            .unwrap();
+        builder.into_inner().await.unwrap()
+    }
+
+    fn chunks(data: &[u8]) -> impl Stream<Item = RegistryPushRequest> + use<> {
+        tokio_stream::iter(
+            data.chunks(1024)
+                .map(|chunk| RegistryPushRequest {
+                    data: chunk.to_vec(),
+                })
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_push_then_pull_roundtrip() {
+        let temp = TempDir::new().unwrap();
+        let mut client = start(temp.path().to_path_buf(), 1024 * 1024).await;
+        let data = archive(&vec![7; 200 * 1024]).await;
+
+        let digest = client
+            .push(chunks(&data))
+            .await
+            .unwrap()
+            .into_inner()
+            .digest;
+        assert_eq!(
+            digest,
+            bundle::content_digest(Archive::new(&data[..]))
+                .await
+                .unwrap()
+        );
+
+        // Same content pushed again lands on the same blob.
+        let again = client
+            .push(chunks(&data))
+            .await
+            .unwrap()
+            .into_inner()
+            .digest;
+        assert_eq!(again, digest);
+
+        let mut stream = client
+            .pull(RegistryPullRequest {
+                digest: digest.clone(),
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        let mut pulled = vec![];
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.unwrap();
+            assert!(chunk.data.len() <= CHUNK_SIZE);
+            pulled.extend_from_slice(&chunk.data);
+        }
+        assert_eq!(pulled, data);
+
+        // Only the blob remains, no spool files are left behind.
+        let mut names = vec![];
+        let mut entries = fs::read_dir(temp.path()).await.unwrap();
+        while let Some(entry) = entries.next_entry().await.unwrap() {
+            names.push(entry.file_name().to_string_lossy().to_string());
         }
+        assert_eq!(names, vec![format!("{}.tar", digest)]);
+    }
+
+    #[tokio::test]
+    async fn test_push_rejects_oversized_bundle() {
+        let temp = TempDir::new().unwrap();
+        let mut client = start(temp.path().to_path_buf(), 4 * 1024).await;
+        let data = archive(&vec![7; 16 * 1024]).await;
+
+        let err = client.push(chunks(&data)).await.unwrap_err();
+        assert_eq!(err.code(), Code::ResourceExhausted);
+
+        let mut entries = fs::read_dir(temp.path()).await.unwrap();
+        assert!(entries.next_entry().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_push_rejects_invalid_archive() {
+        let temp = TempDir::new().unwrap();
+        let mut client = start(temp.path().to_path_buf(), 1024 * 1024).await;
+
+        let err = client.push(chunks(&[1; 2048])).await.unwrap_err();
+        assert_eq!(err.code(), Code::InvalidArgument);
     }
 
-    Ok(format!("{:x}", hasher.finalize()))
+    #[tokio::test]
+    async fn test_push_rejects_unsafe_entries() {
+        let temp = TempDir::new().unwrap();
+        let mut client = start(temp.path().to_path_buf(), 1024 * 1024).await;
+
+        let mut builder = tokio_tar::Builder::new(vec![]);
+        let mut header = tokio_tar::Header::new_gnu();
+        header.set_entry_type(tokio_tar::EntryType::Symlink);
+        header.set_size(0);
+        header.set_link_name("/etc/passwd").unwrap();
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "bootstrap", &[][..])
+            .await
+            .unwrap();
+        let data = builder.into_inner().await.unwrap();
+
+        let err = client.push(chunks(&data)).await.unwrap_err();
+        assert_eq!(err.code(), Code::InvalidArgument);
+        assert_eq!(
+            err.message(),
+            "unsafe entries in archive: bootstrap: symlink points outside the bundle"
+        );
+        let mut entries = fs::read_dir(temp.path()).await.unwrap();
+        assert!(entries.next_entry().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_pull_missing_digest() {
+        let temp = TempDir::new().unwrap();
+        let mut client = start(temp.path().to_path_buf(), 1024).await;
+
+        let err = client
+            .pull(RegistryPullRequest {
+                digest: "ffff".to_string(),
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), Code::NotFound);
+
+        let err = client
+            .pull(RegistryPullRequest {
+                digest: "../digests.db".to_string(),
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), Code::InvalidArgument);
+    }
 }
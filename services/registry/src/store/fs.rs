@@ -0,0 +1,182 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use tokio::{
+    fs::{self, File},
+    io::AsyncWriteExt,
+};
+use tracing::debug;
+use uuid::Uuid;
+
+use super::{BlobReader, BlobStore, validate_key};
+
+const BLOB_EXTENSION: &str = "tar";
+const TEMP_PREFIX: &str = ".tmp-";
+
+/// Stores every blob as `<root>/<key>.tar`.
+pub struct FsBlobStore {
+    root: PathBuf,
+}
+
+impl FsBlobStore {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    fn blob_path(&self, key: &str) -> io::Result<PathBuf> {
+        validate_key(key)?;
+        Ok(self.root.join(format!("{}.{}", key, BLOB_EXTENSION)))
+    }
+}
+
+#[tonic::async_trait]
+impl BlobStore for FsBlobStore {
+    async fn put(&self, key: &str, mut reader: BlobReader) -> io::Result<u64> {
+        let blob_path = self.blob_path(key)?;
+        // Write next to the destination and rename so readers never see a partial blob.
+        let temp_path = self.root.join(format!("{}{}", TEMP_PREFIX, Uuid::new_v4()));
+        let result = async {
+            let mut file = File::create(&temp_path).await?;
+            let size = tokio::io::copy(&mut reader, &mut file).await?;
+            file.flush().await?;
+            file.sync_all().await?;
+            fs::rename(&temp_path, &blob_path).await?;
+            Ok(size)
+        }
+        .await;
+
+        match &result {
+            Ok(_) => debug!(key = %key, "Stored blob on filesystem"),
+            Err(_) => {
+                let _ = fs::remove_file(&temp_path).await;
+            }
+        }
+        result
+    }
+
+    async fn put_file(&self, key: &str, path: &Path) -> io::Result<u64> {
+        let blob_path = self.blob_path(key)?;
+        let size = fs::metadata(path).await?.len();
+        match fs::rename(path, &blob_path).await {
+            Ok(()) => {
+                debug!(key = %key, "Moved blob into filesystem store");
+                Ok(size)
+            }
+            Err(err) if err.kind() == io::ErrorKind::CrossesDevices => {
+                let file = File::open(path).await?;
+                self.put(key, Box::pin(file)).await
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn get(&self, key: &str) -> io::Result<BlobReader> {
+        let file = File::open(self.blob_path(key)?).await?;
+        Ok(Box::pin(file))
+    }
+
+    async fn exists(&self, key: &str) -> io::Result<bool> {
+        fs::try_exists(self.blob_path(key)?).await
+    }
+
+    async fn delete(&self, key: &str) -> io::Result<()> {
+        fs::remove_file(self.blob_path(key)?).await
+    }
+
+    async fn list(&self) -> io::Result<Vec<String>> {
+        let mut keys = vec![];
+        let mut entries = fs::read_dir(&self.root).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().and_then(|e| e.to_str()) != Some(BLOB_EXTENSION) {
+                continue;
+            }
+            if let Some(key) = path.file_stem().and_then(|s| s.to_str())
+                && validate_key(key).is_ok()
+            {
+                keys.push(key.to_string());
+            }
+        }
+        keys.sort();
+        Ok(keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+    use tokio::io::AsyncReadExt;
+
+    async fn read_all(mut reader: BlobReader) -> Vec<u8> {
+        let mut buf = vec![];
+        reader.read_to_end(&mut buf).await.unwrap();
+        buf
+    }
+
+    #[tokio::test]
+    async fn test_fs_store_roundtrip() {
+        let temp = TempDir::new().unwrap();
+        let store = FsBlobStore::new(temp.path().to_path_buf());
+
+        let size = store.put("abc", Box::pin(&b"hello"[..])).await.unwrap();
+        assert_eq!(size, 5);
+        assert!(temp.path().join("abc.tar").exists());
+        assert!(store.exists("abc").await.unwrap());
+        assert_eq!(read_all(store.get("abc").await.unwrap()).await, b"hello");
+        assert_eq!(store.list().await.unwrap(), vec!["abc".to_string()]);
+
+        store.delete("abc").await.unwrap();
+        assert!(!store.exists("abc").await.unwrap());
+        let err = store.get("abc").await.err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[tokio::test]
+    async fn test_fs_store_put_file_moves_file() {
+        let temp = TempDir::new().unwrap();
+        let store = FsBlobStore::new(temp.path().to_path_buf());
+        let spooled = temp.path().join(".spool-1");
+        fs::write(&spooled, b"bundle").await.unwrap();
+
+        assert_eq!(store.put_file("abc", &spooled).await.unwrap(), 6);
+        assert!(!spooled.exists());
+        assert_eq!(read_all(store.get("abc").await.unwrap()).await, b"bundle");
+    }
+
+    #[tokio::test]
+    async fn test_fs_store_list_ignores_temp_files() {
+        let temp = TempDir::new().unwrap();
+        let store = FsBlobStore::new(temp.path().to_path_buf());
+        fs::write(temp.path().join(".tmp-123"), b"partial")
+            .await
+            .unwrap();
+        store.put("b", Box::pin(&b"b"[..])).await.unwrap();
+        store.put("a", Box::pin(&b"a"[..])).await.unwrap();
+
+        assert_eq!(store.list().await.unwrap(), vec!["a", "b"]);
+    }
+
+    #[tokio::test]
+    async fn test_fs_store_rejects_non_digest_keys() {
+        let temp = TempDir::new().unwrap();
+        let store = FsBlobStore::new(temp.path().join("store"));
+        fs::create_dir(temp.path().join("store")).await.unwrap();
+        fs::write(temp.path().join("x.tar"), b"outside")
+            .await
+            .unwrap();
+
+        for key in ["../x", "", "ABC", "a/b"] {
+            let err = store.get(key).await.err().unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput, "{}", key);
+        }
+        let err = store.put("../x", Box::pin(&b"x"[..])).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(
+            fs::read(temp.path().join("x.tar")).await.unwrap(),
+            b"outside"
+        );
+    }
+}
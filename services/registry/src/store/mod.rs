@@ -0,0 +1,73 @@
+use std::{io, path::Path, pin::Pin, sync::Arc};
+
+use tokio::{fs::File, io::AsyncRead};
+
+use crate::config::StorageConfig;
+
+mod fs;
+mod object;
+
+pub use fs::FsBlobStore;
+pub use object::ObjectBlobStore;
+
+pub type BlobReader = Pin<Box<dyn AsyncRead + Send>>;
+
+/// Longest key accepted, well above the hex digest of any hash in use.
+const MAX_KEY_LEN: usize = 128;
+
+/// Content addressed storage for function bundles, keyed by digest.
+///
+/// Missing blobs are reported as `io::ErrorKind::NotFound`. Keys must be lowercase hex
+/// digests, anything else is rejected with `io::ErrorKind::InvalidInput` as it would end
+/// up in a path or object name.
+#[allow(clippy::double_must_use)]
+#[tonic::async_trait]
+pub trait BlobStore: Send + Sync {
+    /// Stores everything `reader` yields under `key`, replacing any existing blob.
+    /// Returns the number of bytes written.
+    async fn put(&self, key: &str, reader: BlobReader) -> io::Result<u64>;
+
+    /// Moves a fully written local file into the store under `key`.
+    /// Stores that share a filesystem with `path` can rename instead of copying.
+    async fn put_file(&self, key: &str, path: &Path) -> io::Result<u64> {
+        let file = File::open(path).await?;
+        self.put(key, Box::pin(file)).await
+    }
+
+    async fn get(&self, key: &str) -> io::Result<BlobReader>;
+
+    async fn exists(&self, key: &str) -> io::Result<bool>;
+
+    async fn delete(&self, key: &str) -> io::Result<()>;
+
+    async fn list(&self) -> io::Result<Vec<String>>;
+}
+
+/// Rejects keys that are not digests, e.g. `../x`.
+pub fn validate_key(key: &str) -> io::Result<()> {
+    let is_digest = !key.is_empty()
+        && key.len() <= MAX_KEY_LEN
+        && key.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'));
+    if !is_digest {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid blob key: {:?}", key),
+        ));
+    }
+    Ok(())
+}
+
+pub fn from_config(config: &StorageConfig) -> io::Result<Arc<dyn BlobStore>> {
+    match config {
+        StorageConfig::Filesystem { root } => Ok(Arc::new(FsBlobStore::new(root.clone()))),
+        StorageConfig::S3 {
+            bucket,
+            endpoint,
+            region,
+        } => Ok(Arc::new(ObjectBlobStore::s3(
+            bucket,
+            endpoint.as_deref(),
+            region.as_deref(),
+        )?)),
+    }
+}
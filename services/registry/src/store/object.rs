@@ -0,0 +1,270 @@
+use std::{io, sync::Arc};
+
+use object_store::{
+    ObjectStore, aws::AmazonS3Builder, buffered::BufWriter, path::Path as ObjectPath,
+};
+use tokio::io::AsyncWriteExt;
+use tokio_stream::StreamExt;
+use tokio_util::io::StreamReader;
+use tracing::debug;
+
+use super::{BlobReader, BlobStore, validate_key};
+
+const BLOB_EXTENSION: &str = ".tar";
+
+/// Stores blobs as `<key>.tar` objects in any `object_store` backend, S3 compatible ones in
+/// particular, so several registry replicas can share one bucket.
+pub struct ObjectBlobStore {
+    store: Arc<dyn ObjectStore>,
+}
+
+impl ObjectBlobStore {
+    pub fn new(store: Arc<dyn ObjectStore>) -> Self {
+        Self { store }
+    }
+
+    /// Credentials are read from the usual `AWS_*` environment variables.
+    pub fn s3(bucket: &str, endpoint: Option<&str>, region: Option<&str>) -> io::Result<Self> {
+        Self::s3_with_builder(AmazonS3Builder::from_env(), bucket, endpoint, region)
+    }
+
+    fn s3_with_builder(
+        builder: AmazonS3Builder,
+        bucket: &str,
+        endpoint: Option<&str>,
+        region: Option<&str>,
+    ) -> io::Result<Self> {
+        let mut builder = builder.with_bucket_name(bucket);
+
+        if let Some(endpoint) = endpoint {
+            // Self hosted stores like MinIO are addressed by path and often without TLS.
+            builder = builder
+                .with_endpoint(endpoint)
+                .with_allow_http(endpoint.starts_with("http://"))
+                .with_virtual_hosted_style_request(false);
+        }
+
+        if let Some(region) = region {
+            builder = builder.with_region(region);
+        }
+
+        let store = builder.build().map_err(to_io_error)?;
+        Ok(Self::new(Arc::new(store)))
+    }
+}
+
+fn location(key: &str) -> io::Result<ObjectPath> {
+    validate_key(key)?;
+    Ok(ObjectPath::from(format!("{}{}", key, BLOB_EXTENSION)))
+}
+
+fn to_io_error(err: object_store::Error) -> io::Error {
+    match err {
+        object_store::Error::NotFound { .. } => io::Error::new(io::ErrorKind::NotFound, err),
+        err => io::Error::other(err),
+    }
+}
+
+#[tonic::async_trait]
+impl BlobStore for ObjectBlobStore {
+    async fn put(&self, key: &str, mut reader: BlobReader) -> io::Result<u64> {
+        // Small blobs go up in one request, larger ones as a multipart upload.
+        let mut writer = BufWriter::new(self.store.clone(), location(key)?);
+        let size = match tokio::io::copy(&mut reader, &mut writer).await {
+            Ok(size) => size,
+            Err(err) => {
+                let _ = writer.abort().await;
+                return Err(err);
+            }
+        };
+        writer.shutdown().await?;
+
+        debug!(key = %key, size_bytes = size, "Stored blob in object store");
+        Ok(size)
+    }
+
+    async fn get(&self, key: &str) -> io::Result<BlobReader> {
+        let result = self.store.get(&location(key)?).await.map_err(to_io_error)?;
+        let stream = result.into_stream().map(|chunk| chunk.map_err(to_io_error));
+        Ok(Box::pin(StreamReader::new(stream)))
+    }
+
+    async fn exists(&self, key: &str) -> io::Result<bool> {
+        match self.store.head(&location(key)?).await {
+            Ok(_) => Ok(true),
+            Err(object_store::Error::NotFound { .. }) => Ok(false),
+            Err(err) => Err(to_io_error(err)),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> io::Result<()> {
+        // Object stores treat deleting a missing object as success, match the filesystem.
+        if !self.exists(key).await? {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("blob not found: {}", key),
+            ));
+        }
+        self.store
+            .delete(&location(key)?)
+            .await
+            .map_err(to_io_error)
+    }
+
+    async fn list(&self) -> io::Result<Vec<String>> {
+        let mut keys = vec![];
+        let mut objects = self.store.list(None);
+        while let Some(meta) = objects.next().await {
+            let meta = meta.map_err(to_io_error)?;
+            if let Some(key) = meta.location.as_ref().strip_suffix(BLOB_EXTENSION)
+                && validate_key(key).is_ok()
+            {
+                keys.push(key.to_string());
+            }
+        }
+        keys.sort();
+        Ok(keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        collections::BTreeMap,
+        sync::{Arc, Mutex},
+    };
+
+    use axum::{
+        Router,
+        body::Bytes,
+        extract::{Path, State},
+        http::{StatusCode, header},
+        response::{IntoResponse, Response},
+        routing::get,
+    };
+    use tokio::io::AsyncReadExt;
+
+    type Objects = Arc<Mutex<BTreeMap<String, Vec<u8>>>>;
+
+    const LAST_MODIFIED: &str = "Thu, 01 Jan 2026 00:00:00 GMT";
+
+    /// Minimal MinIO style stand-in: path addressed PUT/GET/HEAD/DELETE and ListObjectsV2.
+    async fn start_s3_stand_in() -> String {
+        async fn put(
+            State(objects): State<Objects>,
+            Path((_, key)): Path<(String, String)>,
+            body: Bytes,
+        ) -> Response {
+            objects.lock().unwrap().insert(key, body.to_vec());
+            (StatusCode::OK, [(header::ETAG, "\"etag\"")]).into_response()
+        }
+
+        async fn get_object(
+            State(objects): State<Objects>,
+            Path((_, key)): Path<(String, String)>,
+        ) -> Response {
+            match objects.lock().unwrap().get(&key) {
+                Some(data) => (
+                    StatusCode::OK,
+                    [
+                        (header::LAST_MODIFIED, LAST_MODIFIED),
+                        (header::ETAG, "\"etag\""),
+                    ],
+                    data.clone(),
+                )
+                    .into_response(),
+                None => StatusCode::NOT_FOUND.into_response(),
+            }
+        }
+
+        async fn delete(
+            State(objects): State<Objects>,
+            Path((_, key)): Path<(String, String)>,
+        ) -> StatusCode {
+            objects.lock().unwrap().remove(&key);
+            StatusCode::NO_CONTENT
+        }
+
+        async fn list(State(objects): State<Objects>) -> Response {
+            let contents: String = objects
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(key, data)| {
+                    format!(
+                        "<Contents><Key>{}</Key><Size>{}</Size><LastModified>2026-01-01T00:00:00Z</LastModified></Contents>",
+                        key,
+                        data.len()
+                    )
+                })
+                .collect();
+            let body = format!(
+                "<ListBucketResult><IsTruncated>false</IsTruncated>{}</ListBucketResult>",
+                contents
+            );
+            (
+                StatusCode::OK,
+                [(header::CONTENT_TYPE, "application/xml")],
+                body,
+            )
+                .into_response()
+        }
+
+        let app = Router::new()
+            .route("/{bucket}", get(list))
+            .route("/{bucket}/{*key}", get(get_object).put(put).delete(delete))
+            .with_state(Objects::default());
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        format!("http://{}", addr)
+    }
+
+    async fn s3_store() -> ObjectBlobStore {
+        let endpoint = start_s3_stand_in().await;
+        let builder = AmazonS3Builder::new()
+            .with_access_key_id("minioadmin")
+            .with_secret_access_key("minioadmin");
+        ObjectBlobStore::s3_with_builder(builder, "registry", Some(&endpoint), Some("us-east-1"))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_s3_store_roundtrip() {
+        let store = s3_store().await;
+
+        let size = store.put("abc", Box::pin(&b"hello"[..])).await.unwrap();
+        assert_eq!(size, 5);
+        assert!(store.exists("abc").await.unwrap());
+
+        let mut data = vec![];
+        store
+            .get("abc")
+            .await
+            .unwrap()
+            .read_to_end(&mut data)
+            .await
+            .unwrap();
+        assert_eq!(data, b"hello");
+
+        store.put("def", Box::pin(&b"world"[..])).await.unwrap();
+        assert_eq!(store.list().await.unwrap(), vec!["abc", "def"]);
+
+        store.delete("abc").await.unwrap();
+        assert!(!store.exists("abc").await.unwrap());
+        assert_eq!(store.list().await.unwrap(), vec!["def"]);
+    }
+
+    #[tokio::test]
+    async fn test_s3_store_reports_missing_blobs_as_not_found() {
+        let store = s3_store().await;
+
+        let err = store.get("ffff").await.err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        let err = store.delete("ffff").await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+}
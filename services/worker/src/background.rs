@@ -1,11 +1,18 @@
-use std::{sync::Arc, time::Duration};
+use std::{collections::HashMap, sync::Arc, time::Duration};
 
-use anyhow::{Ok, Result};
-use tokio::time::{Instant, sleep};
+use anyhow::Result;
+use proto::api::controlplane::ProvisionedFunction;
+use tokio::time::sleep;
 use tokio_util::sync::CancellationToken;
-use tracing::info;
+use tracing::{info, warn};
 
-use crate::worker::function_invocations::FunctionInvocations;
+use crate::{
+    client::controlplane_client::{ControlPlaneClient, with_secrets},
+    worker::{
+        function_invocations::{FunctionInvocations, PoolKey},
+        organizer::NativeWorker,
+    },
+};
 
 pub struct BackgroundConfig {
     pub time: Duration,
@@ -15,15 +22,31 @@ pub struct BackgroundConfig {
 pub struct BackgroundJob {
     config: BackgroundConfig,
     cancel: CancellationToken,
+    function_worker: Arc<NativeWorker>,
     function_invocations: Arc<FunctionInvocations>,
+    provisioner: Arc<Provisioner>,
+}
+
+/// Keeps functions with provisioned concurrency running, as configured in the control plane.
+pub struct Provisioner {
+    function_worker: Arc<NativeWorker>,
+    function_invocations: Arc<FunctionInvocations>,
+    controlplane_client: ControlPlaneClient,
 }
 
 impl BackgroundJob {
-    pub fn new(config: BackgroundConfig, function_invocations: &Arc<FunctionInvocations>) -> Self {
+    pub fn new(
+        config: BackgroundConfig,
+        function_worker: &Arc<NativeWorker>,
+        function_invocations: &Arc<FunctionInvocations>,
+        provisioner: Provisioner,
+    ) -> Self {
         Self {
             config,
             cancel: CancellationToken::new(),
+            function_worker: function_worker.clone(),
             function_invocations: function_invocations.clone(),
+            provisioner: Arc::new(provisioner),
         }
     }
 
@@ -33,14 +56,21 @@ impl BackgroundJob {
         let time = self.config.time;
         let resource_ttl = self.config.resource_ttl;
         let function = self.function_invocations.clone();
+        let worker = self.function_worker.clone();
+        let provisioner = self.provisioner.clone();
 
         tokio::spawn(async move {
             while !cancel.is_cancelled() {
+                // Provisioned functions are started right away, not only after the first sleep.
+                if let Err(err) = provisioner.sync().await {
+                    warn!("Something when worng while provisioning: {:?}", err);
+                }
                 sleep(time).await;
-                for instance_id in function.keys().await {
-                    if let Err(err) = execute(&instance_id, resource_ttl, &function).await {
-                        tracing::error!("Something when worng with {}: {:?}", instance_id, err);
-                    }
+                if let Err(err) = function.scale_down(resource_ttl).await {
+                    tracing::error!("Something when worng while scaling down: {:?}", err);
+                }
+                if let Err(err) = worker.check_health().await {
+                    warn!(error = ?err, "Failed to check instance health");
                 }
             }
         });
@@ -52,21 +82,47 @@ impl BackgroundJob {
     }
 }
 
-pub async fn execute(
-    instance_id: &str,
-    resource_ttl: Duration,
-    function: &FunctionInvocations,
-) -> Result<()> {
-    if let Some(inv) = function.peek(instance_id).await {
-        let expired = {
-            let inv = inv.lock().await;
-            Instant::now() - inv.last_accessed > resource_ttl
+impl Provisioner {
+    pub fn new(
+        function_worker: &Arc<NativeWorker>,
+        function_invocations: &Arc<FunctionInvocations>,
+        controlplane_client: ControlPlaneClient,
+    ) -> Self {
+        Self {
+            function_worker: function_worker.clone(),
+            function_invocations: function_invocations.clone(),
+            controlplane_client,
+        }
+    }
+
+    /// Exempts provisioned instances from scaling down and starts the missing ones.
+    async fn sync(&self) -> Result<()> {
+        let functions = self.controlplane_client.list_provisioned().await?;
+
+        let key = |function: &ProvisionedFunction| PoolKey {
+            name: function.name.clone(),
+            digest: function.digest.clone(),
         };
+        self.function_invocations.set_provisioned(
+            functions
+                .iter()
+                .map(|f| (key(f), f.provisioned_concurrency as usize))
+                .collect::<HashMap<_, _>>(),
+        );
 
-        if expired {
-            function.delete(instance_id).await?;
+        for function in functions {
+            if let Err(err) = self
+                .function_worker
+                .prewarm(
+                    &key(&function),
+                    function.provisioned_concurrency as usize,
+                    &with_secrets(function.config, function.secrets),
+                )
+                .await
+            {
+                warn!(name = %function.name, error = ?err, "Failed to start provisioned instances");
+            }
         }
+        Ok(())
     }
-
-    Ok(())
 }
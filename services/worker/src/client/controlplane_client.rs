@@ -1,10 +1,28 @@
+use std::collections::HashMap;
+
 use anyhow::{Ok, Result};
 use proto::api::controlplane::{
-    GetDigestByNameRequest, control_plane_service_client::ControlPlaneServiceClient,
+    FunctionConfig, ListProvisionedFunctionsRequest, ProvisionedFunction, ResolveFunctionRequest,
+    ResolveFunctionResponse, worker_control_plane_service_client::WorkerControlPlaneServiceClient,
 };
 use tonic::Request;
 use tracing::{debug, instrument, warn};
 
+/// Config to start instances with, secrets become environment variables like the
+/// function's own and win over them.
+///
+/// The result holds secret values, so it must never end up in a log or span.
+pub fn with_secrets(
+    config: Option<FunctionConfig>,
+    secrets: HashMap<String, String>,
+) -> FunctionConfig {
+    let mut config = config.unwrap_or_default();
+    config.env.extend(secrets);
+    config
+}
+
+/// Talks to the control plane's internal address, the only one serving secrets.
+#[derive(Clone)]
 pub struct ControlPlaneClient {
     pub addr: String,
 }
@@ -17,27 +35,65 @@ impl ControlPlaneClient {
 }
 
 impl ControlPlaneClient {
-    #[instrument(skip(self), fields(addr = %self.addr))]
-    pub async fn get_digest(&self, key: String) -> Result<String> {
+    /// Resolves `key` to the function's name, digest, config and secrets, `routing_key`
+    /// keeps weighted aliases sticky per caller.
+    #[instrument(skip(self, routing_key), fields(addr = %self.addr))]
+    pub async fn resolve(
+        &self,
+        key: String,
+        routing_key: Option<&str>,
+    ) -> Result<ResolveFunctionResponse> {
         debug!(key = %key, "Fetching digest from control plane");
-        let mut client = ControlPlaneServiceClient::connect(self.addr.clone())
+        let mut client = telemetry::connect(self.addr.clone())
             .await
+            .map(WorkerControlPlaneServiceClient::new)
             .map_err(|e| {
                 warn!(error = %e, "Failed to connect to control plane");
                 e
             })?;
 
         let response = client
-            .get_digest_by_name(Request::new(GetDigestByNameRequest { key: key.clone() }))
+            .resolve_function(Request::new(ResolveFunctionRequest {
+                key: key.clone(),
+                routing_key: routing_key.unwrap_or_default().to_string(),
+            }))
             .await
             .map_err(|e| {
-                warn!(key = %key, error = %e, "Failed to get digest by name");
+                warn!(key = %key, error = %e, "Failed to resolve function");
                 e
             })?
             .into_inner();
 
         debug!(key = %key, digest = %response.digest, "Successfully retrieved digest");
 
-        Ok(response.digest)
+        Ok(response)
+    }
+
+    /// Functions that should always have instances running.
+    #[instrument(skip(self), fields(addr = %self.addr))]
+    pub async fn list_provisioned(&self) -> Result<Vec<ProvisionedFunction>> {
+        let mut client = telemetry::connect(self.addr.clone())
+            .await
+            .map(WorkerControlPlaneServiceClient::new)
+            .map_err(|e| {
+                warn!(error = %e, "Failed to connect to control plane");
+                e
+            })?;
+
+        let response = client
+            .list_provisioned_functions(Request::new(ListProvisionedFunctionsRequest {}))
+            .await
+            .map_err(|e| {
+                warn!(error = %e, "Failed to list provisioned functions");
+                e
+            })?
+            .into_inner();
+
+        debug!(
+            count = response.functions.len(),
+            "Successfully listed provisioned functions"
+        );
+
+        Ok(response.functions)
     }
 }
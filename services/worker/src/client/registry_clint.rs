@@ -1,12 +1,13 @@
 use std::path::{Path, PathBuf};
 
-use anyhow::{Ok, Result};
+use anyhow::{Ok, Result, bail};
 use proto::api::registry::{RegistryPullRequest, registry_service_client::RegistryServiceClient};
 use std::io::Cursor;
-use tokio::fs::create_dir;
+use tokio::fs::{create_dir, remove_dir_all, rename};
 use tokio_tar::Archive;
 use tonic::Request;
 use tracing::{debug, info, instrument, warn};
+use uuid::Uuid;
 
 use crate::path::get_dir_path;
 
@@ -36,7 +37,7 @@ impl RegistryClient {
         let data = self.fetch_digest(digest).await?;
 
         debug!(digest = %digest, size_bytes = data.len(), "Archive downloaded, extracting");
-        self.extract_archive(&data, &dir_path).await?;
+        self.extract_archive(&data, digest, &dir_path).await?;
 
         info!(digest = %digest, path = ?dir_path, "Archive extracted successfully");
         Ok(dir_path)
@@ -44,8 +45,9 @@ impl RegistryClient {
 
     #[instrument(skip(self), fields(addr = %self.addr))]
     async fn fetch_digest(&self, digest: &str) -> Result<Vec<u8>> {
-        let mut client = RegistryServiceClient::connect(self.addr.clone())
+        let mut client = telemetry::connect(self.addr.clone())
             .await
+            .map(RegistryServiceClient::new)
             .map_err(|e| {
                 warn!(error = %e, "Failed to connect to registry");
                 e
@@ -71,21 +73,139 @@ impl RegistryClient {
         Ok(data)
     }
 
+    /// Unpacks `data` into `dir_path` only if its content hashes to `digest` and no
+    /// entry could land outside of it.
+    /// Extraction goes through a staging folder so a failed or corrupt bundle is never
+    /// picked up as cached.
     #[instrument(skip(self, data))]
-    async fn extract_archive(&self, data: &[u8], dir_path: &Path) -> Result<()> {
-        create_dir(dir_path).await.map_err(|e| {
-            warn!(path = ?dir_path, error = %e, "Failed to create directory");
+    async fn extract_archive(&self, data: &[u8], digest: &str, dir_path: &Path) -> Result<()> {
+        let inspection = bundle::inspect(Archive::new(Cursor::new(data))).await?;
+        if !inspection.violations.is_empty() {
+            let offenders = inspection
+                .violations
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ");
+            warn!(offenders = %offenders, "Bundle has unsafe entries");
+            bail!("unsafe entries in bundle: {}", offenders);
+        }
+        if inspection.digest != digest {
+            warn!(expected = %digest, actual = %inspection.digest, "Bundle digest mismatch");
+            bail!(
+                "bundle digest mismatch: expected {}, content hashes to {}",
+                digest,
+                inspection.digest
+            );
+        }
+
+        let staging_path = dir_path.with_extension(format!("partial-{}", Uuid::new_v4()));
+        create_dir(&staging_path).await.map_err(|e| {
+            warn!(path = ?staging_path, error = %e, "Failed to create directory");
             e
         })?;
 
-        let cursor = Cursor::new(&data);
-        let mut archive = Archive::new(cursor);
+        let mut archive = Archive::new(Cursor::new(data));
+        if let Err(e) = archive.unpack(&staging_path).await {
+            warn!(path = ?staging_path, error = %e, "Failed to extract archive");
+            let _ = remove_dir_all(&staging_path).await;
+            return Err(e.into());
+        }
 
-        archive.unpack(&dir_path).await.map_err(|e| {
-            warn!(path = ?dir_path, error = %e, "Failed to extract archive");
-            e
-        })?;
+        if let Err(e) = rename(&staging_path, dir_path).await {
+            let _ = remove_dir_all(&staging_path).await;
+            // Someone else extracted the same digest first, theirs is just as good.
+            if !dir_path.exists() {
+                warn!(path = ?dir_path, error = %e, "Failed to move extracted archive into place");
+                return Err(e.into());
+            }
+        }
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    async fn archive() -> Vec<u8> {
+        let mut builder = tokio_tar::Builder::new(vec![]);
+        let mut header = tokio_tar::Header::new_gnu();
+        header.set_size(7);
+        header.set_mode(0o755);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "bootstrap", &b"echo hi"[..])
+            .await
+            .unwrap();
+        builder.into_inner().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_extract_archive_verifies_digest() {
+        let temp = TempDir::new().unwrap();
+        let client = RegistryClient::new("http://[::1]:1".to_string());
+        let data = archive().await;
+        let digest = bundle::content_digest(Archive::new(&data[..]))
+            .await
+            .unwrap();
+        let dir_path = temp.path().join(&digest);
+
+        client
+            .extract_archive(&data, &digest, &dir_path)
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read(dir_path.join("bootstrap")).unwrap(),
+            b"echo hi"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_extract_archive_refuses_unsafe_entries() {
+        let temp = TempDir::new().unwrap();
+        let client = RegistryClient::new("http://[::1]:1".to_string());
+
+        let mut builder = tokio_tar::Builder::new(vec![]);
+        let mut header = tokio_tar::Header::new_gnu();
+        header.set_entry_type(tokio_tar::EntryType::Link);
+        header.set_size(0);
+        header.set_link_name("../../etc/shadow").unwrap();
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "bootstrap", &[][..])
+            .await
+            .unwrap();
+        let data = builder.into_inner().await.unwrap();
+        let digest = bundle::content_digest(Archive::new(&data[..]))
+            .await
+            .unwrap();
+
+        let err = client
+            .extract_archive(&data, &digest, &temp.path().join(&digest))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "unsafe entries in bundle: bootstrap: hard link points outside the bundle"
+        );
+        assert_eq!(std::fs::read_dir(temp.path()).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_extract_archive_refuses_mismatch() {
+        let temp = TempDir::new().unwrap();
+        let client = RegistryClient::new("http://[::1]:1".to_string());
+        let data = archive().await;
+        let dir_path = temp.path().join("other");
+
+        let err = client
+            .extract_archive(&data, "other", &dir_path)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("digest mismatch"));
+        assert_eq!(std::fs::read_dir(temp.path()).unwrap().count(), 0);
+    }
+}
@@ -1,6 +1,6 @@
 use std::{net::SocketAddr, time::Duration};
 
-use crate::background::BackgroundConfig;
+use crate::{background::BackgroundConfig, worker::function_invocations::PoolConfig};
 
 #[derive(Debug, PartialEq)]
 pub enum Environment {
@@ -10,10 +10,16 @@ pub enum Environment {
 
 pub struct ServerConfig {
     pub addr: SocketAddr,
+    /// Serves the Prometheus metrics.
+    pub metrics_addr: SocketAddr,
     pub controlplane_clinet: String,
     pub registry_clinet: String,
     pub env: Environment,
     pub background_config: BackgroundConfig,
+    pub pool_config: PoolConfig,
+    pub invoke_timeout: Duration,
+    /// Output kept on disk per function before the oldest lines are dropped.
+    pub log_buffer_bytes: u64,
 }
 
 impl ServerConfig {
@@ -22,6 +28,10 @@ impl ServerConfig {
             .unwrap_or_else(|_| "[::1]:50003".to_string())
             .parse()
             .expect("Invalid server address");
+        let metrics_addr = std::env::var("METRICS_ADDR")
+            .unwrap_or_else(|_| "[::1]:9003".to_string())
+            .parse()
+            .expect("Invalid metrics address");
 
         let env = match cfg!(debug_assertions) {
             true => Environment::Development,
@@ -29,7 +39,7 @@ impl ServerConfig {
         };
 
         let controlplane_clinet = std::env::var("CONTROLPLANE_CLINET")
-            .unwrap_or_else(|_| "http://localhost:50002".to_string())
+            .unwrap_or_else(|_| "http://localhost:50012".to_string())
             .parse()
             .expect("Invalid controlplane address");
 
@@ -53,12 +63,33 @@ impl ServerConfig {
             .map(Duration::from_secs)
             .unwrap_or(Duration::from_secs(30));
 
+        let defaults = PoolConfig::default();
+        let pool_config = PoolConfig {
+            min_instances: env_usize("POOL_MIN_INSTANCES", defaults.min_instances),
+            max_instances: env_usize("POOL_MAX_INSTANCES", defaults.max_instances).max(1),
+            max_concurrency: env_usize("POOL_INSTANCE_CONCURRENCY", defaults.max_concurrency)
+                .max(1),
+        };
+
+        let invoke_timeout = Duration::from_secs(env_usize("INVOKE_TIMEOUT", 30) as u64);
+        let log_buffer_bytes = env_usize("LOG_BUFFER_BYTES", 1024 * 1024) as u64;
+
         Self {
             addr,
+            metrics_addr,
             controlplane_clinet,
             registry_clinet,
             env,
             background_config: BackgroundConfig { time, resource_ttl },
+            pool_config,
+            invoke_timeout,
+            log_buffer_bytes,
         }
     }
 }
+
+fn env_usize(name: &str, default: usize) -> usize {
+    std::env::var(name)
+        .map(|s| s.parse().unwrap_or_else(|_| panic!("Invalid {}", name)))
+        .unwrap_or(default)
+}
@@ -1,5 +1,6 @@
 mod client;
 mod config;
+mod metrics;
 mod path;
 mod server;
 mod worker;
@@ -20,23 +21,17 @@ use crate::client::registry_clint::RegistryClient;
 use crate::config::Environment;
 use crate::server::WorkerServer;
 use crate::worker::function_invocations::FunctionInvocations;
+use crate::worker::logs::LogStore;
 use crate::worker::organizer::{Config, NativeWorker};
 use tokio::signal;
-use tracing_subscriber::prelude::__tracing_subscriber_SubscriberExt;
-use tracing_subscriber::util::SubscriberInitExt;
 
 mod background;
 
-use background::BackgroundJob;
+use background::{BackgroundJob, Provisioner};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    tracing_subscriber::registry()
-        .with(
-            tracing_subscriber::EnvFilter::try_from_default_env().unwrap_or_else(|_| "info".into()),
-        )
-        .with(tracing_subscriber::fmt::layer())
-        .init();
+    let _tracing = telemetry::init("noctiforge-worker")?;
 
     let _ =
         tracing::info_span!("app", name = "worker", version = env!("CARGO_PKG_VERSION")).entered();
@@ -53,29 +48,55 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         info!("Starting in Development mode");
     }
 
-    let function_invocations = Arc::new(FunctionInvocations::new(root_path.to_path_buf()));
+    telemetry::serve_metrics(config.metrics_addr)?;
+    info!("Metrics listening on {}", config.metrics_addr);
+
+    let function_invocations = Arc::new(FunctionInvocations::new(
+        root_path.to_path_buf(),
+        config.pool_config.clone(),
+    ));
+
+    let logs = Arc::new(LogStore::new(
+        root_path.join("logs"),
+        config.log_buffer_bytes,
+    )?);
 
     let registry_clinet = RegistryClient::new(config.registry_clinet);
     let controlplane_client = ControlPlaneClient::new(config.controlplane_clinet);
 
-    let function_worker = NativeWorker::new(
+    let function_worker = Arc::new(NativeWorker::new(
         &function_invocations,
+        &logs,
         registry_clinet,
         root_path,
         &*syscall,
         Config {
             is_dev: config.env == Environment::Development,
+            invoke_timeout: config.invoke_timeout,
         },
-    )?;
+    )?);
+
+    function_worker.reconcile().await?;
 
-    let mut background_server = BackgroundJob::new(config.background_config, &function_invocations);
-    let worker_server = WorkerServer::new(function_worker, controlplane_client);
+    let provisioner = Provisioner::new(
+        &function_worker,
+        &function_invocations,
+        controlplane_client.clone(),
+    );
+    let mut background_server = BackgroundJob::new(
+        config.background_config,
+        &function_worker,
+        &function_invocations,
+        provisioner,
+    );
+    let worker_server = WorkerServer::new(&function_worker, &logs, controlplane_client);
 
     info!("Worker listening on {}", config.addr);
     background_server.start().await;
 
     // Graceful shutdown with signal handling
     let server = Server::builder()
+        .trace_fn(telemetry::server_span)
         .add_service(WorkerServiceServer::new(worker_server))
         .serve(config.addr);
 
@@ -0,0 +1,111 @@
+//! Metrics the worker exports on `METRICS_ADDR`.
+
+use std::time::Duration;
+
+use metrics::{counter, gauge, histogram};
+
+use crate::server;
+
+const INVOCATIONS: &str = "noctiforge_worker_invocations_total";
+const INVOCATION_DURATION: &str = "noctiforge_worker_invocation_duration_seconds";
+const STARTS: &str = "noctiforge_worker_starts_total";
+const ACTIVE_INSTANCES: &str = "noctiforge_worker_active_instances";
+const REAPED_INSTANCES: &str = "noctiforge_worker_reaped_instances_total";
+
+/// How an invocation ended, `Problem` when the function reported a problem.
+#[derive(Debug, Clone, Copy)]
+pub enum Outcome {
+    Success,
+    Problem,
+    Error,
+}
+
+impl Outcome {
+    fn as_str(self) -> &'static str {
+        match self {
+            Outcome::Success => "success",
+            Outcome::Problem => "problem",
+            Outcome::Error => "error",
+        }
+    }
+}
+
+/// Whether an invocation got a running instance or had to wait for a new one.
+#[derive(Debug, Clone, Copy)]
+pub enum Start {
+    Cold,
+    Warm,
+}
+
+/// Why the background job stopped an instance.
+#[derive(Debug, Clone, Copy)]
+pub enum Reaped {
+    Idle,
+    Unhealthy,
+}
+
+/// Labelled by function name, `hello:prod` and `hello@3` both count as `hello`.
+pub fn invocation(action: &str, outcome: Outcome, elapsed: Duration) {
+    let function = server::function_name(action).to_string();
+    counter!(INVOCATIONS, "function" => function.clone(), "outcome" => outcome.as_str())
+        .increment(1);
+    histogram!(INVOCATION_DURATION, "function" => function).record(elapsed);
+}
+
+pub fn start(start: Start) {
+    let kind = match start {
+        Start::Cold => "cold",
+        Start::Warm => "warm",
+    };
+    counter!(STARTS, "kind" => kind).increment(1);
+}
+
+pub fn instances_added(count: usize) {
+    gauge!(ACTIVE_INSTANCES).increment(count as f64);
+}
+
+pub fn instances_removed(count: usize) {
+    gauge!(ACTIVE_INSTANCES).decrement(count as f64);
+}
+
+pub fn reaped(reason: Reaped, count: usize) {
+    let reason = match reason {
+        Reaped::Idle => "idle",
+        Reaped::Unhealthy => "unhealthy",
+    };
+    counter!(REAPED_INSTANCES, "reason" => reason).increment(count as u64);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invocations_are_labelled_by_function_name() {
+        let recorder = telemetry::builder().build_recorder();
+        let handle = recorder.handle();
+
+        metrics::with_local_recorder(&recorder, || {
+            invocation("hello:prod", Outcome::Success, Duration::from_millis(20));
+            invocation("hello@3", Outcome::Problem, Duration::from_millis(20));
+            start(Start::Cold);
+            instances_added(2);
+            instances_removed(1);
+            reaped(Reaped::Idle, 1);
+        });
+
+        let rendered = handle.render();
+        assert!(rendered.contains(
+            r#"noctiforge_worker_invocations_total{function="hello",outcome="success"} 1"#
+        ));
+        assert!(rendered.contains(
+            r#"noctiforge_worker_invocations_total{function="hello",outcome="problem"} 1"#
+        ));
+        assert!(rendered.contains(
+            r#"noctiforge_worker_invocation_duration_seconds_bucket{function="hello",le="0.025"} 2"#
+        ));
+        assert!(rendered.contains(r#"noctiforge_worker_starts_total{kind="cold"} 1"#));
+        assert!(rendered.contains("noctiforge_worker_active_instances 1"));
+        assert!(rendered.contains(r#"noctiforge_worker_reaped_instances_total{reason="idle"} 1"#));
+    }
+}
@@ -1,22 +1,47 @@
-use std::sync::Arc;
+use std::{pin::Pin, sync::Arc, time::Duration};
 
-use proto::api::worker::{ExecuteRequest, ExecuteResponse, worker_service_server::WorkerService};
-use tokio::sync::Mutex;
-use tonic::{Request, Response, Status};
+use proto::api::worker::{
+    ExecuteRequest, ExecuteResponse, GetLogsRequest, GetLogsResponse, LogEntry, PrewarmRequest,
+    PrewarmResponse, TailLogsRequest, execute_response, worker_service_server::WorkerService,
+};
+use tokio::time::Instant;
+use tokio_stream::{Stream, StreamExt};
+use tonic::{Request, Response, Status, metadata::MetadataMap};
 use tracing::{debug, info, instrument, warn};
 
-use crate::{client::controlplane_client::ControlPlaneClient, worker::organizer::NativeWorker};
+use crate::{
+    client::controlplane_client::{ControlPlaneClient, with_secrets},
+    metrics,
+    worker::{function_invocations::PoolKey, logs::LogStore, organizer::NativeWorker},
+};
+
+/// Metadata entry callers set to pin weighted alias resolution, e.g. to a user id.
+const ROUTING_KEY_METADATA: &str = "routing-key";
+
+/// Lines returned by `GetLogs` when the request sets no limit.
+const DEFAULT_LOG_LIMIT: usize = 100;
+
+/// Most lines `GetLogs` returns and `TailLogs` replays, whatever the request asks for.
+const MAX_LOG_LIMIT: usize = 10_000;
+
+type TailLogsStream = Pin<Box<dyn Stream<Item = Result<LogEntry, Status>> + Send + 'static>>;
 
 pub struct WorkerServer {
-    function_worker: Arc<Mutex<NativeWorker>>,
+    function_worker: Arc<NativeWorker>,
+    logs: Arc<LogStore>,
     controlplane_client: ControlPlaneClient,
 }
 
 impl WorkerServer {
-    pub fn new(function_worker: NativeWorker, controlplane_client: ControlPlaneClient) -> Self {
+    pub fn new(
+        function_worker: &Arc<NativeWorker>,
+        logs: &Arc<LogStore>,
+        controlplane_client: ControlPlaneClient,
+    ) -> Self {
         debug!("Creating WorkerServer");
         Self {
-            function_worker: Arc::new(Mutex::new(function_worker)),
+            function_worker: function_worker.clone(),
+            logs: logs.clone(),
             controlplane_client,
         }
     }
@@ -29,26 +54,53 @@ impl WorkerService for WorkerServer {
         &self,
         request: Request<ExecuteRequest>,
     ) -> Result<Response<ExecuteResponse>, Status> {
+        // Taken first, tonic counts the caller's deadline from when the request arrived.
+        let caller_deadline =
+            caller_timeout(request.metadata()).and_then(|t| Instant::now().checked_add(t));
         let req = request.into_inner();
 
         info!(action = %req.action, "Executing request");
 
         debug!(action = %req.action, "Fetching digest from control plane");
-        let digest = self
+        let resolved = self
             .controlplane_client
-            .get_digest(req.action.clone())
+            .resolve(
+                req.action.clone(),
+                req.metadata.get(ROUTING_KEY_METADATA).map(String::as_str),
+            )
             .await
             .map_err(|e| {
                 warn!(action = %req.action, error = %e, "Failed to communicate with control plane");
                 Status::internal(format!("Failed to commnicate with controlplane: {:?}", e))
             })?;
 
-        debug!(action = %req.action, digest = %digest, body_size = req.body.len(), "Executing function");
-        let output = {
-            let mut worker = self.function_worker.lock().await;
-            worker.execute(digest, req.body, req.metadata).await
-        }
-        .map_err(|e| {
+        debug!(action = %req.action, digest = %resolved.digest, body_size = req.body.len(), "Executing function");
+        let started = Instant::now();
+        let result = self
+            .function_worker
+            .execute(
+                PoolKey {
+                    name: resolved.name,
+                    digest: resolved.digest,
+                },
+                req.body,
+                req.metadata,
+                with_secrets(resolved.config, resolved.secrets),
+                caller_deadline,
+            )
+            .await;
+
+        let outcome = match &result {
+            Ok(ExecuteResponse {
+                outcome: Some(execute_response::Outcome::Problem(_)),
+                ..
+            }) => metrics::Outcome::Problem,
+            Ok(_) => metrics::Outcome::Success,
+            Err(_) => metrics::Outcome::Error,
+        };
+        metrics::invocation(&req.action, outcome, started.elapsed());
+
+        let output = result.map_err(|e| {
             warn!(action = %req.action, error = %e, "Execution failed");
             Status::internal(format!("Execution failed: {:?}", e))
         })?;
@@ -57,4 +109,143 @@ impl WorkerService for WorkerServer {
 
         Ok(Response::new(output))
     }
+
+    #[instrument(skip(self, request), fields(name = %request.get_ref().name, count = request.get_ref().count))]
+    async fn prewarm(
+        &self,
+        request: Request<PrewarmRequest>,
+    ) -> Result<Response<PrewarmResponse>, Status> {
+        let req = request.into_inner();
+
+        debug!(name = %req.name, "Fetching digest from control plane");
+        let resolved = self
+            .controlplane_client
+            .resolve(req.name.clone(), None)
+            .await
+            .map_err(|e| {
+                warn!(name = %req.name, error = %e, "Failed to communicate with control plane");
+                Status::internal(format!("Failed to commnicate with controlplane: {:?}", e))
+            })?;
+
+        let running = self
+            .function_worker
+            .prewarm(
+                &PoolKey {
+                    name: resolved.name,
+                    digest: resolved.digest,
+                },
+                req.count as usize,
+                &with_secrets(resolved.config, resolved.secrets),
+            )
+            .await
+            .map_err(|e| {
+                warn!(name = %req.name, error = %e, "Prewarming failed");
+                Status::internal(format!("Prewarming failed: {:?}", e))
+            })?;
+
+        info!(name = %req.name, running, "Prewarming completed successfully");
+
+        Ok(Response::new(PrewarmResponse {
+            running: running as u32,
+        }))
+    }
+
+    #[instrument(skip(self, request), fields(name = %request.get_ref().name))]
+    async fn get_logs(
+        &self,
+        request: Request<GetLogsRequest>,
+    ) -> Result<Response<GetLogsResponse>, Status> {
+        let req = request.into_inner();
+        let function = function_name(&req.name);
+
+        let limit = match req.limit {
+            0 => DEFAULT_LOG_LIMIT,
+            limit => (limit as usize).min(MAX_LOG_LIMIT),
+        };
+        let entries = self
+            .logs
+            .read(function, limit, &req.invocation_id)
+            .await
+            .map_err(|e| {
+                warn!(name = %req.name, error = ?e, "Failed to read logs");
+                Status::internal(format!("Failed to read logs: {:?}", e))
+            })?;
+
+        debug!(name = %req.name, count = entries.len(), "Logs read");
+        Ok(Response::new(GetLogsResponse { entries }))
+    }
+
+    type TailLogsStream = TailLogsStream;
+
+    #[instrument(skip(self, request), fields(name = %request.get_ref().name))]
+    async fn tail_logs(
+        &self,
+        request: Request<TailLogsRequest>,
+    ) -> Result<Response<Self::TailLogsStream>, Status> {
+        let req = request.into_inner();
+        let function = function_name(&req.name);
+
+        let entries = self
+            .logs
+            .tail(function, (req.backlog as usize).min(MAX_LOG_LIMIT))
+            .await
+            .map_err(|e| {
+                warn!(name = %req.name, error = ?e, "Failed to read logs");
+                Status::internal(format!("Failed to read logs: {:?}", e))
+            })?;
+
+        info!(name = %req.name, "Following logs");
+        Ok(Response::new(Box::pin(entries.map(Ok)) as TailLogsStream))
+    }
+}
+
+/// Name of the function `action` selects, `hello:prod` and `hello@3` are both `hello`.
+pub fn function_name(action: &str) -> &str {
+    action.split(['@', ':']).next().unwrap_or(action)
+}
+
+/// The caller's gRPC deadline, sent as `grpc-timeout` like `100m` or `5S`.
+fn caller_timeout(metadata: &MetadataMap) -> Option<Duration> {
+    let value = metadata.get("grpc-timeout")?.to_str().ok()?;
+    let (amount, unit) = value.split_at_checked(value.len().checked_sub(1)?)?;
+    // The gRPC spec caps the amount at 8 digits, which keeps hours from overflowing.
+    if amount.len() > 8 {
+        return None;
+    }
+    let amount: u64 = amount.parse().ok()?;
+    match unit {
+        "H" => Some(Duration::from_secs(amount * 60 * 60)),
+        "M" => Some(Duration::from_secs(amount * 60)),
+        "S" => Some(Duration::from_secs(amount)),
+        "m" => Some(Duration::from_millis(amount)),
+        "u" => Some(Duration::from_micros(amount)),
+        "n" => Some(Duration::from_nanos(amount)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timeout(value: &str) -> Option<Duration> {
+        let mut metadata = MetadataMap::new();
+        metadata.insert("grpc-timeout", value.parse().unwrap());
+        caller_timeout(&metadata)
+    }
+
+    #[test]
+    fn test_caller_timeout() {
+        assert_eq!(timeout("250m"), Some(Duration::from_millis(250)));
+        assert_eq!(timeout("2S"), Some(Duration::from_secs(2)));
+        assert_eq!(timeout("1H"), Some(Duration::from_secs(3600)));
+        assert_eq!(timeout("5x"), None);
+        assert_eq!(timeout("m"), None);
+        assert_eq!(
+            timeout("99999999H"),
+            Some(Duration::from_secs(99999999 * 3600))
+        );
+        assert_eq!(timeout("18446744073709551615H"), None);
+        assert_eq!(caller_timeout(&MetadataMap::new()), None);
+    }
 }
@@ -1,22 +1,46 @@
-use std::path::{Path, PathBuf};
+use std::{
+    collections::BTreeSet,
+    os::fd::OwnedFd,
+    path::{Path, PathBuf},
+};
 
-use crate::{
-    path::copy_dir_all,
-    worker::spec::{SysUserParms, get_spec},
+use crate::worker::{
+    function_invocations::{PoolKey, fingerprint},
+    network,
+    spec::{InstancePaths, SysUserParms, get_spec},
 };
 use anyhow::{Context, Result};
 use libcontainer::{
     container::{Container, ContainerStatus, builder::ContainerBuilder},
     syscall::syscall::SyscallType,
 };
+use nix::{sys::stat::Mode, unistd::mkfifo};
+use proto::api::{
+    controlplane::{FunctionConfig, NetworkConfig, NetworkMode},
+    worker::LogStream,
+};
 use tokio::{
     fs::{DirBuilder, File},
     io::{AsyncWriteExt, BufWriter},
 };
+use tracing::{debug, warn};
 use url::Url;
 
 const CONTAINER_STATE_FOLDER: &str = "state";
 const CONTAINER_RUN_FOLDER: &str = "run";
+/// FIFOs in the bundle the handler's output goes to. They outlive the worker, so a
+/// restarted one can read them again.
+const STDOUT_FIFO: &str = "stdout";
+const STDERR_FIFO: &str = "stderr";
+/// Bundle file naming the function, digest and config an instance runs, read back
+/// when it is adopted.
+const FUNCTION_FILE: &str = "function.json";
+
+/// Write ends of the FIFOs the handler's stdout and stderr go to.
+pub struct InstanceOutput {
+    pub stdout: OwnedFd,
+    pub stderr: OwnedFd,
+}
 
 // Trait for abstracting container operations - enables mocking
 #[cfg_attr(test, mockall::automock)]
@@ -26,6 +50,7 @@ pub trait ContainerOps {
         instance_id: String,
         root_path: PathBuf,
         rootfs: PathBuf,
+        output: InstanceOutput,
     ) -> Result<Box<dyn ContainerWrapper>>;
 
     fn start_container(&self, container: &mut dyn ContainerWrapper) -> Result<()>;
@@ -40,6 +65,10 @@ pub trait ContainerWrapper: Send + Sync {
     fn status(&self) -> ContainerStatus;
     fn start(&mut self) -> Result<()>;
     fn delete(&mut self) -> Result<()>;
+    /// The file holding the `oom_kill` counter of the container's cgroup, only found
+    /// while the container runs.
+    fn oom_kill_file(&self) -> Result<PathBuf>;
+    fn pid(&self) -> Option<i32>;
 }
 
 // Wrapper implementation for real Container
@@ -63,6 +92,65 @@ impl ContainerWrapper for RealContainerWrapper {
         self.0.delete(true)?;
         Ok(())
     }
+
+    fn oom_kill_file(&self) -> Result<PathBuf> {
+        let pid = self.0.pid().context("container has no process")?;
+        let cgroups = std::fs::read_to_string(format!("/proc/{}/cgroup", pid))?;
+        oom_kill_file(&cgroups).context("container has no memory cgroup")
+    }
+
+    fn pid(&self) -> Option<i32> {
+        self.0.pid().map(|pid| pid.as_raw())
+    }
+}
+
+/// The `oom_kill` counter of an instance's cgroup at some point. The counter covers the
+/// cgroup's whole life and only counts processes the kernel killed, not every time the
+/// limit was hit, so a kill shows up as an increase since an earlier reading.
+pub struct OomKills {
+    file: PathBuf,
+    count: u64,
+}
+
+impl OomKills {
+    /// Whether the kernel OOM killed a process of the instance since this reading. The
+    /// file stays readable after the instance died, until it is deleted.
+    pub fn increased(&self) -> Result<bool> {
+        Ok(read_oom_kills(&self.file)? > self.count)
+    }
+}
+
+/// Where the `oom_kill` counter lives for a process in `cgroups`, the contents of its
+/// `/proc/<pid>/cgroup`. cgroup v2 keeps it in `memory.events`, v1 in `memory.oom_control`.
+fn oom_kill_file(cgroups: &str) -> Option<PathBuf> {
+    cgroups.lines().find_map(|line| {
+        let mut fields = line.splitn(3, ':');
+        let (_, controllers, path) = (fields.next()?, fields.next()?, fields.next()?);
+        let path = path.trim_start_matches('/');
+        if controllers.is_empty() {
+            Some(Path::new("/sys/fs/cgroup").join(path).join("memory.events"))
+        } else if controllers.split(',').any(|c| c == "memory") {
+            Some(
+                Path::new("/sys/fs/cgroup/memory")
+                    .join(path)
+                    .join("memory.oom_control"),
+            )
+        } else {
+            None
+        }
+    })
+}
+
+fn read_oom_kills(file: &Path) -> Result<u64> {
+    let contents = std::fs::read_to_string(file)
+        .with_context(|| format!("failed to read {}", file.display()))?;
+    contents
+        .lines()
+        .find_map(|line| line.strip_prefix("oom_kill "))
+        .context("no oom_kill counter, the kernel may be too old")?
+        .trim()
+        .parse()
+        .context("invalid oom_kill counter")
 }
 
 // Real implementation using libcontainer
@@ -74,10 +162,13 @@ impl ContainerOps for LibcontainerOps {
         instance_id: String,
         root_path: PathBuf,
         rootfs: PathBuf,
+        output: InstanceOutput,
     ) -> Result<Box<dyn ContainerWrapper>> {
         let container = ContainerBuilder::new(instance_id, SyscallType::default())
             .with_root_path(root_path)
             .expect("invalid root path")
+            .with_stdout(output.stdout)
+            .with_stderr(output.stderr)
             .as_init(rootfs)
             .with_detach(true)
             // TODO: Should we set it to true. or can we set that to false?
@@ -103,18 +194,116 @@ pub struct ProccesContainer {
 
 impl ProccesContainer {
     pub async fn new(
-        digest: &str,
+        instance_id: &str,
+        key: &PoolKey,
         handle_bin: PathBuf,
         root_path: PathBuf,
         sys_user: &SysUserParms,
+        config: &FunctionConfig,
     ) -> Result<Self> {
-        Self::new_with_deps(digest, handle_bin, root_path, sys_user, &LibcontainerOps).await
+        Self::new_with_deps(
+            instance_id,
+            key,
+            handle_bin,
+            root_path,
+            sys_user,
+            config,
+            &LibcontainerOps,
+        )
+        .await
     }
 
     pub async fn load(root_path: &Path, instance_id: &str) -> Result<Self> {
         Self::load_with_deps(root_path, instance_id, &LibcontainerOps).await
     }
 
+    /// Instances with a state or bundle folder under `root_path`, running or not.
+    pub async fn instance_ids(root_path: &Path) -> Result<BTreeSet<String>> {
+        let mut ids = BTreeSet::new();
+        for folder in [CONTAINER_STATE_FOLDER, CONTAINER_RUN_FOLDER] {
+            let mut entries = match tokio::fs::read_dir(root_path.join(folder)).await {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e).context("Failed to list instances"),
+            };
+            while let Some(entry) = entries.next_entry().await? {
+                if entry.file_type().await?.is_dir() {
+                    ids.insert(entry.file_name().to_string_lossy().into_owned());
+                }
+            }
+        }
+        Ok(ids)
+    }
+
+    /// Loads an instance a previous worker left behind without starting it. Running
+    /// instances are returned to be adopted, anything else is removed.
+    pub async fn recover(root_path: &Path, instance_id: &str) -> Result<Option<Self>> {
+        Self::recover_with_deps(root_path, instance_id, &LibcontainerOps).await
+    }
+
+    async fn recover_with_deps(
+        root_path: &Path,
+        instance_id: &str,
+        ops: &impl ContainerOps,
+    ) -> Result<Option<Self>> {
+        let container =
+            match ops.load_container(root_path.join(CONTAINER_STATE_FOLDER).join(instance_id)) {
+                Ok(container) => container,
+                Err(err) => {
+                    // Crashed before the container was created, or its state is unreadable.
+                    debug!(instance_id, error = ?err, "No container state, removing folders");
+                    for folder in [CONTAINER_STATE_FOLDER, CONTAINER_RUN_FOLDER] {
+                        let path = root_path.join(folder).join(instance_id);
+                        if path.exists() {
+                            tokio::fs::remove_dir_all(&path)
+                                .await
+                                .with_context(|| format!("Failed to remove {}", path.display()))?;
+                        }
+                    }
+                    return Ok(None);
+                }
+            };
+
+        let mut proc = Self { container };
+        if proc.container.status() == ContainerStatus::Running {
+            return Ok(Some(proc));
+        }
+        proc.cleanup().await?;
+        Ok(None)
+    }
+
+    /// Reads the OOM kills of a running instance, without starting it like `load` does.
+    pub fn oom_kills(root_path: &Path, instance_id: &str) -> Result<OomKills> {
+        Self::oom_kills_with_deps(root_path, instance_id, &LibcontainerOps)
+    }
+
+    /// Current status of the instance's container, without starting it like `load` does.
+    pub fn status(root_path: &Path, instance_id: &str) -> Result<ContainerStatus> {
+        Self::status_with_deps(root_path, instance_id, &LibcontainerOps)
+    }
+
+    fn status_with_deps(
+        root_path: &Path,
+        instance_id: &str,
+        ops: &impl ContainerOps,
+    ) -> Result<ContainerStatus> {
+        Ok(ops
+            .load_container(root_path.join(CONTAINER_STATE_FOLDER).join(instance_id))?
+            .status())
+    }
+
+    fn oom_kills_with_deps(
+        root_path: &Path,
+        instance_id: &str,
+        ops: &impl ContainerOps,
+    ) -> Result<OomKills> {
+        let file = ops
+            .load_container(root_path.join(CONTAINER_STATE_FOLDER).join(instance_id))?
+            .oom_kill_file()?;
+        let count = read_oom_kills(&file)?;
+        Ok(OomKills { file, count })
+    }
+
     async fn load_with_deps(
         root_path: &Path,
         instance_id: &str,
@@ -130,37 +319,81 @@ impl ProccesContainer {
     }
 
     async fn new_with_deps(
-        digest: &str,
+        instance_id: &str,
+        key: &PoolKey,
         handle_bin: PathBuf,
         root_path: PathBuf,
         sys_user: &SysUserParms,
+        config: &FunctionConfig,
         ops: &impl ContainerOps,
     ) -> Result<Self> {
-        let instance_id = digest.to_string();
-
         let rootfs = Self::create_rootfs(
-            &instance_id,
+            instance_id,
             handle_bin,
             sys_user,
+            config,
             root_path.join(CONTAINER_RUN_FOLDER),
         )
         .await?;
+        let function = serde_json::json!({
+            "name": key.name,
+            "digest": key.digest,
+            "fingerprint": fingerprint(config),
+        });
+        tokio::fs::write(rootfs.join(FUNCTION_FILE), function.to_string()).await?;
+
+        // Opened for reading too, so the handler never writes into a FIFO without readers
+        // while no worker is around. It blocks once the FIFO is full instead.
+        let open_fifo = |name: &str| -> Result<OwnedFd> {
+            let fifo = std::fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(rootfs.join(name))
+                .with_context(|| format!("Failed to open {} of the instance", name))?;
+            Ok(fifo.into())
+        };
+        let output = InstanceOutput {
+            stdout: open_fifo(STDOUT_FIFO)?,
+            stderr: open_fifo(STDERR_FIFO)?,
+        };
 
         let mut container = ops.build_container(
-            instance_id.clone(),
+            instance_id.to_string(),
             root_path.join(CONTAINER_STATE_FOLDER),
             rootfs.clone(),
+            output,
         )?;
 
         ops.start_container(container.as_mut())?;
 
-        Ok(Self { container })
+        let mut proc = Self { container };
+        let network = config.network.clone().unwrap_or_default();
+        if network.mode() != NetworkMode::Host
+            && let Err(e) = proc.attach_network(&network).await
+        {
+            // Never hand out an instance without the isolation it asked for.
+            if let Err(cleanup) = proc.cleanup().await {
+                warn!(error = %cleanup, "Failed to clean up container without network");
+            }
+            return Err(e);
+        }
+
+        Ok(proc)
+    }
+
+    async fn attach_network(&self, network: &NetworkConfig) -> Result<()> {
+        let pid = self
+            .container
+            .pid()
+            .context("Container has no init process")?;
+        network::attach(network, pid, &self.container.bundle()).await
     }
 
     async fn create_rootfs(
         instance_id: &str,
         handle_bin: PathBuf,
         sys_user: &SysUserParms,
+        config: &FunctionConfig,
         run_path: PathBuf,
     ) -> Result<PathBuf> {
         let path = run_path.join(instance_id);
@@ -170,9 +403,23 @@ impl ProccesContainer {
         }
 
         // TODO: need to look at this and see if we should create the folder a head of time?
-        DirBuilder::new().recursive(true).create(&path).await?;
-
-        let spec = get_spec(sys_user)?;
+        // Private, the spec holds the function's secrets.
+        DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(&path)
+            .await?;
+
+        // Bind mount sources have to be absolute.
+        let run = std::path::absolute(path.join("run"))?;
+        let spec = get_spec(
+            sys_user,
+            config,
+            &InstancePaths {
+                app: std::path::absolute(handle_bin)?,
+                run: run.clone(),
+            },
+        )?;
 
         // Create Spec
         let file = File::create(path.join("config.json")).await?;
@@ -181,27 +428,59 @@ impl ProccesContainer {
         writer.write_all(&json_bytes).await?;
         writer.flush().await?;
 
+        // Only mount points, the rootfs is read-only and everything else is mounted.
         let rootfs_path = path.join("rootfs");
         DirBuilder::new().create(&rootfs_path).await?;
+        for mount_point in ["app", "run", "tmp"] {
+            DirBuilder::new()
+                .create(&rootfs_path.join(mount_point))
+                .await?;
+        }
+        DirBuilder::new().create(&run).await?;
+        for fifo in [STDOUT_FIFO, STDERR_FIFO] {
+            mkfifo(&path.join(fifo), Mode::S_IRUSR | Mode::S_IWUSR)?;
+        }
 
-        copy_dir_all(handle_bin, rootfs_path.join("app")).await?;
+        Ok(path)
+    }
 
-        DirBuilder::new().create(&rootfs_path.join("run")).await?;
+    /// Function and digest the instance was started from, and the fingerprint of its config.
+    pub async fn function(&self) -> Result<(PoolKey, String)> {
+        let path = self.container.bundle().join(FUNCTION_FILE);
+        let contents = tokio::fs::read_to_string(&path)
+            .await
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let function: serde_json::Value = serde_json::from_str(&contents)?;
+        let field = |name: &str| {
+            function[name]
+                .as_str()
+                .map(str::to_string)
+                .with_context(|| format!("{} misses {}", path.display(), name))
+        };
+        let key = PoolKey {
+            name: field("name")?,
+            digest: field("digest")?,
+        };
+        Ok((key, field("fingerprint")?))
+    }
 
-        Ok(path)
+    /// FIFO the handler's `stream` goes to.
+    pub fn output_path(&self, stream: LogStream) -> PathBuf {
+        self.container.bundle().join(match stream {
+            LogStream::Stdout => STDOUT_FIFO,
+            LogStream::Stderr => STDERR_FIFO,
+        })
     }
 
     pub fn get_url(&self) -> Result<Url> {
-        let sock_path = format!(
-            "unix://{}/rootfs/run/app.sock",
-            self.container.bundle().display()
-        );
+        let sock_path = format!("unix://{}/run/app.sock", self.container.bundle().display());
         let url = Url::parse(&sock_path)?;
         Ok(url)
     }
 
     pub async fn cleanup(&mut self) -> Result<()> {
         let path = self.container.bundle();
+        network::detach(&path).await?;
         self.container.delete()?;
         if path.exists() {
             tokio::fs::remove_dir_all(&path)
@@ -215,9 +494,17 @@ impl ProccesContainer {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
     use tempfile::TempDir;
     use tokio::fs;
 
+    fn test_key() -> PoolKey {
+        PoolKey {
+            name: "hello".to_string(),
+            digest: "sha256:abc".to_string(),
+        }
+    }
+
     // ==================== Mocked Container Tests ====================
 
     #[tokio::test]
@@ -238,7 +525,7 @@ mod tests {
 
         // Set up container expectations
         mock_ops.expect_build_container().times(1).returning(
-            move |_instance_id, _root_path, _rootfs| {
+            move |_instance_id, _root_path, _rootfs, _output| {
                 let mut mock = MockContainerWrapper::new();
                 mock.expect_bundle()
                     .return_const(PathBuf::from("/tmp/test_bundle"));
@@ -255,9 +542,11 @@ mod tests {
         let sys_user = SysUserParms { uid: 0, gid: 0 };
         let result = ProccesContainer::new_with_deps(
             "test_digest",
+            &test_key(),
             handle_bin,
             root_path,
             &sys_user,
+            &FunctionConfig::default(),
             &mock_ops,
         )
         .await;
@@ -285,7 +574,7 @@ mod tests {
         let url = container.get_url().unwrap();
         assert_eq!(url.scheme(), "unix");
         assert!(url.path().contains("test_container"));
-        assert!(url.path().contains("rootfs/run/app.sock"));
+        assert!(url.path().ends_with("test_container/run/app.sock"));
     }
 
     #[tokio::test]
@@ -306,9 +595,11 @@ mod tests {
 
         mock_ops
             .expect_build_container()
-            .withf(move |instance_id, _root, _rootfs| instance_id == &expected_instance_clone)
+            .withf(move |instance_id, _root, _rootfs, _output| {
+                instance_id == &expected_instance_clone
+            })
             .times(1)
-            .returning(|_, _, _| {
+            .returning(|_, _, _, _| {
                 let mut mock = MockContainerWrapper::new();
                 mock.expect_bundle()
                     .return_const(PathBuf::from("/tmp/test"));
@@ -323,9 +614,11 @@ mod tests {
         let sys_user = SysUserParms { uid: 0, gid: 0 };
         let result = ProccesContainer::new_with_deps(
             "test_digest_123",
+            &test_key(),
             handle_bin,
             root_path,
             &sys_user,
+            &FunctionConfig::default(),
             &mock_ops,
         )
         .await;
@@ -356,14 +649,72 @@ mod tests {
         mock_ops.expect_build_container().times(0);
 
         let sys_user = SysUserParms { uid: 0, gid: 0 };
-        let result =
-            ProccesContainer::new_with_deps("test", handle_bin, root_path, &sys_user, &mock_ops)
-                .await;
+        let result = ProccesContainer::new_with_deps(
+            "test",
+            &test_key(),
+            handle_bin,
+            root_path,
+            &sys_user,
+            &FunctionConfig::default(),
+            &mock_ops,
+        )
+        .await;
 
         // Should fail with "already exists" error
         assert!(result.is_err());
     }
 
+    #[tokio::test]
+    async fn test_create_rootfs_mounts_bundle_instead_of_copying() {
+        let temp = TempDir::new().unwrap();
+        let handle_bin = temp.path().join("bin");
+        let run_path = temp.path().join("root").join("run");
+        fs::create_dir_all(&handle_bin).await.unwrap();
+        fs::write(handle_bin.join("bootstrap"), b"test")
+            .await
+            .unwrap();
+
+        let path = ProccesContainer::create_rootfs(
+            "test",
+            handle_bin.clone(),
+            &SysUserParms { uid: 0, gid: 0 },
+            &FunctionConfig::default(),
+            run_path,
+        )
+        .await
+        .unwrap();
+
+        assert!(!path.join("rootfs/app/bootstrap").exists());
+        assert!(path.join("rootfs/tmp").is_dir());
+        assert!(path.join("run").is_dir());
+        assert!(
+            fs::metadata(path.join("stdout"))
+                .await
+                .unwrap()
+                .file_type()
+                .is_fifo()
+        );
+        assert!(
+            fs::metadata(path.join("stderr"))
+                .await
+                .unwrap()
+                .file_type()
+                .is_fifo()
+        );
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+
+        let spec: serde_json::Value =
+            serde_json::from_slice(&fs::read(path.join("config.json")).await.unwrap()).unwrap();
+        let app = spec["mounts"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|mount| mount["destination"] == "/app")
+            .unwrap();
+        assert_eq!(app["source"], handle_bin.to_str().unwrap());
+    }
+
     #[tokio::test]
     async fn test_try_from_with_running_container() {
         let temp = TempDir::new().unwrap();
@@ -436,4 +787,237 @@ mod tests {
         assert!(result.is_ok());
         assert!(!bundle_path.exists());
     }
+
+    #[test]
+    fn test_oom_kills_only_counts_new_kills() {
+        let temp = TempDir::new().unwrap();
+        let events = temp.path().join("memory.events");
+        std::fs::write(&events, "low 0\nhigh 0\nmax 7\noom 5\noom_kill 1\n").unwrap();
+
+        let mut mock_ops = MockContainerOps::new();
+        let file = events.clone();
+        mock_ops
+            .expect_load_container()
+            .withf(|path| path.ends_with("state/instance"))
+            .times(1)
+            .returning(move |_| {
+                let mut mock = MockContainerWrapper::new();
+                let file = file.clone();
+                mock.expect_oom_kill_file()
+                    .returning(move || Ok(file.clone()));
+                Ok(Box::new(mock))
+            });
+        mock_ops.expect_start_container().times(0);
+
+        let oom_kills =
+            ProccesContainer::oom_kills_with_deps(temp.path(), "instance", &mock_ops).unwrap();
+        // Earlier kills and limit hits without a kill don't count.
+        std::fs::write(&events, "low 0\nhigh 0\nmax 9\noom 6\noom_kill 1\n").unwrap();
+        assert!(!oom_kills.increased().unwrap());
+
+        std::fs::write(&events, "low 0\nhigh 0\nmax 9\noom 7\noom_kill 2\n").unwrap();
+        assert!(oom_kills.increased().unwrap());
+    }
+
+    #[test]
+    fn test_oom_kill_file() {
+        assert_eq!(
+            oom_kill_file("0::/system.slice/youki-fn.scope\n"),
+            Some(PathBuf::from(
+                "/sys/fs/cgroup/system.slice/youki-fn.scope/memory.events"
+            ))
+        );
+        assert_eq!(
+            oom_kill_file("5:cpu,cpuacct:/fn\n4:memory:/fn\n"),
+            Some(PathBuf::from("/sys/fs/cgroup/memory/fn/memory.oom_control"))
+        );
+        assert_eq!(oom_kill_file("5:cpu:/fn\n"), None);
+    }
+
+    #[test]
+    fn test_status_does_not_start_container() {
+        let temp = TempDir::new().unwrap();
+
+        let mut mock_ops = MockContainerOps::new();
+        mock_ops
+            .expect_load_container()
+            .withf(|path| path.ends_with("state/instance"))
+            .times(1)
+            .returning(|_| {
+                let mut mock = MockContainerWrapper::new();
+                mock.expect_status().return_const(ContainerStatus::Stopped);
+                Ok(Box::new(mock))
+            });
+        mock_ops.expect_start_container().times(0);
+
+        let status =
+            ProccesContainer::status_with_deps(temp.path(), "instance", &mock_ops).unwrap();
+        assert_eq!(status, ContainerStatus::Stopped);
+    }
+
+    #[tokio::test]
+    async fn test_network_failure_removes_container() {
+        let temp = TempDir::new().unwrap();
+        let handle_bin = temp.path().join("bin");
+        let root_path = temp.path().join("root");
+        fs::create_dir_all(&handle_bin).await.unwrap();
+        fs::write(handle_bin.join("app"), b"test").await.unwrap();
+
+        let mut mock_ops = MockContainerOps::new();
+        let bundle = root_path.join("run").join("test");
+        mock_ops
+            .expect_build_container()
+            .times(1)
+            .returning(move |_, _, _, _| {
+                let mut mock = MockContainerWrapper::new();
+                mock.expect_bundle().return_const(bundle.clone());
+                // No init process, so the network can't be attached.
+                mock.expect_pid().return_const(None);
+                mock.expect_delete().times(1).returning(|| Ok(()));
+                Ok(Box::new(mock))
+            });
+        mock_ops.expect_start_container().returning(|_| Ok(()));
+
+        let config = FunctionConfig {
+            network: Some(NetworkConfig {
+                mode: NetworkMode::None.into(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let result = ProccesContainer::new_with_deps(
+            "test",
+            &test_key(),
+            handle_bin,
+            root_path.clone(),
+            &SysUserParms { uid: 0, gid: 0 },
+            &config,
+            &mock_ops,
+        )
+        .await;
+
+        assert!(result.is_err());
+        assert!(!root_path.join("run").join("test").exists());
+    }
+
+    #[tokio::test]
+    async fn test_instance_ids_lists_state_and_run_folders() {
+        let temp = TempDir::new().unwrap();
+        fs::create_dir_all(temp.path().join("state/fn-a"))
+            .await
+            .unwrap();
+        fs::create_dir_all(temp.path().join("run/fn-a"))
+            .await
+            .unwrap();
+        fs::create_dir_all(temp.path().join("run/fn-b"))
+            .await
+            .unwrap();
+        fs::write(temp.path().join("state/not-an-instance"), b"")
+            .await
+            .unwrap();
+
+        let ids = ProccesContainer::instance_ids(temp.path()).await.unwrap();
+        assert_eq!(ids.into_iter().collect::<Vec<_>>(), ["fn-a", "fn-b"]);
+    }
+
+    #[tokio::test]
+    async fn test_recover_keeps_running_instance() {
+        let temp = TempDir::new().unwrap();
+        let mut mock_ops = MockContainerOps::new();
+        mock_ops.expect_load_container().times(1).returning(|_| {
+            let mut mock = MockContainerWrapper::new();
+            mock.expect_status().return_const(ContainerStatus::Running);
+            mock.expect_delete().times(0);
+            Ok(Box::new(mock))
+        });
+        mock_ops.expect_start_container().times(0);
+
+        let recovered = ProccesContainer::recover_with_deps(temp.path(), "fn-a", &mock_ops)
+            .await
+            .unwrap();
+        assert!(recovered.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_recover_removes_stopped_instance() {
+        let temp = TempDir::new().unwrap();
+        let bundle = temp.path().join("run").join("fn-a");
+        fs::create_dir_all(&bundle).await.unwrap();
+
+        let mut mock_ops = MockContainerOps::new();
+        let bundle_clone = bundle.clone();
+        mock_ops
+            .expect_load_container()
+            .times(1)
+            .returning(move |_| {
+                let mut mock = MockContainerWrapper::new();
+                mock.expect_status().return_const(ContainerStatus::Stopped);
+                mock.expect_bundle().return_const(bundle_clone.clone());
+                mock.expect_delete().times(1).returning(|| Ok(()));
+                Ok(Box::new(mock))
+            });
+        mock_ops.expect_start_container().times(0);
+
+        let recovered = ProccesContainer::recover_with_deps(temp.path(), "fn-a", &mock_ops)
+            .await
+            .unwrap();
+        assert!(recovered.is_none());
+        assert!(!bundle.exists());
+    }
+
+    #[tokio::test]
+    async fn test_recover_removes_folders_without_state() {
+        let temp = TempDir::new().unwrap();
+        let bundle = temp.path().join("run").join("fn-a");
+        fs::create_dir_all(&bundle).await.unwrap();
+
+        let mut mock_ops = MockContainerOps::new();
+        mock_ops
+            .expect_load_container()
+            .times(1)
+            .returning(|_| Err(anyhow::anyhow!("no state")));
+
+        let recovered = ProccesContainer::recover_with_deps(temp.path(), "fn-a", &mock_ops)
+            .await
+            .unwrap();
+        assert!(recovered.is_none());
+        assert!(!bundle.exists());
+    }
+
+    #[tokio::test]
+    async fn test_new_records_function_in_bundle() {
+        let temp = TempDir::new().unwrap();
+        let handle_bin = temp.path().join("bin");
+        let root_path = temp.path().join("root");
+        fs::create_dir_all(&handle_bin).await.unwrap();
+
+        let mut mock_ops = MockContainerOps::new();
+        let bundle = root_path.join("run").join("instance");
+        mock_ops
+            .expect_build_container()
+            .times(1)
+            .returning(move |_, _, _, _| {
+                let mut mock = MockContainerWrapper::new();
+                mock.expect_bundle().return_const(bundle.clone());
+                Ok(Box::new(mock))
+            });
+        mock_ops.expect_start_container().returning(|_| Ok(()));
+
+        let proc = ProccesContainer::new_with_deps(
+            "instance",
+            &test_key(),
+            handle_bin,
+            root_path,
+            &SysUserParms { uid: 0, gid: 0 },
+            &FunctionConfig::default(),
+            &mock_ops,
+        )
+        .await
+        .unwrap();
+        let config = FunctionConfig::default();
+        assert_eq!(
+            proc.function().await.unwrap(),
+            (test_key(), fingerprint(&config))
+        );
+    }
 }
@@ -1,102 +1,707 @@
 use anyhow::{Ok, Result};
-use std::{collections::HashMap, path::PathBuf, sync::Arc};
-use tokio::{sync::Mutex, time::Instant};
-use tracing::info;
+use prost::Message;
+use proto::api::controlplane::FunctionConfig;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeMap, HashMap, hash_map::Entry},
+    fmt,
+    path::PathBuf,
+    sync::{Arc, Mutex as StdMutex},
+    time::Duration,
+};
+use tokio::{
+    sync::{Mutex, Notify},
+    time::Instant,
+};
+use tracing::{info, warn};
 use url::Url;
 
-use crate::worker::container::ProccesContainer;
+use crate::{
+    metrics::{self, Reaped},
+    worker::container::ProccesContainer,
+};
+
+#[derive(Debug, Clone)]
+pub struct PoolConfig {
+    /// Idle instances kept warm per function when scaling down.
+    pub min_instances: usize,
+    pub max_instances: usize,
+    /// Invocations a single instance handles at once.
+    pub max_concurrency: usize,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            min_instances: 0,
+            max_instances: 4,
+            max_concurrency: 4,
+        }
+    }
+}
+
+/// Instances are shared by the invocations of one version of one function. Functions
+/// pushed with the same bundle still get their own, their env and secrets differ.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PoolKey {
+    pub name: String,
+    pub digest: String,
+}
+
+impl fmt::Display for PoolKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}@{}", self.name, self.digest)
+    }
+}
+
+/// Hash of everything in `config` an instance is started with, secrets included, which
+/// tells instances started before a config change apart. Timeout and provisioned
+/// concurrency only matter to the worker, changing them keeps instances warm.
+pub fn fingerprint(config: &FunctionConfig) -> String {
+    let started_with = FunctionConfig {
+        provisioned_concurrency: 0,
+        timeout_ms: 0,
+        env: HashMap::new(),
+        ..config.clone()
+    };
+    let mut hasher = Sha256::new();
+    hasher.update(started_with.encode_to_vec());
+    // Maps are encoded in iteration order, so the environment is hashed sorted.
+    for (key, value) in config.env.iter().collect::<BTreeMap<_, _>>() {
+        hasher.update(key);
+        hasher.update([0]);
+        hasher.update(value);
+        hasher.update([0]);
+    }
+    hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
 
 pub struct Invocation {
+    pub instance_id: String,
     pub url: Url,
     pub last_accessed: Instant,
+    pub in_flight: usize,
+}
+
+/// Point in time view of one instance.
+pub struct InstanceState {
+    pub key: PoolKey,
+    pub instance_id: String,
+    pub url: Url,
+    pub idle: bool,
+}
+
+/// Every running instance of one function, all started with the same config.
+pub struct FunctionPool {
+    fingerprint: String,
+    instances: StdMutex<Vec<Invocation>>,
+    released: Notify,
+}
+
+/// An invocation slot on one instance, given back when dropped.
+pub struct Lease {
+    pool: Arc<FunctionPool>,
+    pub instance_id: String,
+    pub url: Url,
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        {
+            let mut instances = self.pool.instances.lock().expect("pool poisoned");
+            if let Some(inv) = instances
+                .iter_mut()
+                .find(|inv| inv.instance_id == self.instance_id)
+            {
+                inv.in_flight -= 1;
+                inv.last_accessed = Instant::now();
+            }
+        }
+        self.pool.released.notify_waiters();
+    }
+}
+
+impl FunctionPool {
+    fn new(fingerprint: &str) -> Self {
+        Self {
+            fingerprint: fingerprint.to_string(),
+            instances: StdMutex::new(vec![]),
+            released: Notify::new(),
+        }
+    }
+
+    /// Takes a slot on the least busy instance that still has room.
+    pub fn try_acquire(self: &Arc<Self>, max_concurrency: usize) -> Option<Lease> {
+        let mut instances = self.instances.lock().expect("pool poisoned");
+        let inv = instances
+            .iter_mut()
+            .filter(|inv| inv.in_flight < max_concurrency)
+            .min_by_key(|inv| inv.in_flight)?;
+
+        inv.in_flight += 1;
+        inv.last_accessed = Instant::now();
+        Some(Lease {
+            pool: self.clone(),
+            instance_id: inv.instance_id.clone(),
+            url: inv.url.clone(),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.instances.lock().expect("pool poisoned").len()
+    }
+
+    /// Resolves once any lease on this pool is released. Create it before checking
+    /// for capacity so a release in between isn't missed.
+    pub fn released(&self) -> tokio::sync::futures::Notified<'_> {
+        self.released.notified()
+    }
+}
+
+#[derive(Default)]
+struct Pools {
+    active: HashMap<PoolKey, Arc<FunctionPool>>,
+    /// Pools replaced after a config change, their instances take no new invocations
+    /// and are stopped once idle.
+    draining: Vec<(PoolKey, Arc<FunctionPool>)>,
+}
+
+impl Pools {
+    /// The pool of `key` for instances started with `fingerprint`, created empty on first
+    /// use. A pool of instances started with another config is drained.
+    fn get(&mut self, key: &PoolKey, fingerprint: &str) -> Arc<FunctionPool> {
+        match self.active.entry(key.clone()) {
+            Entry::Occupied(mut entry) if entry.get().fingerprint != fingerprint => {
+                info!(function = %key, "Function config changed, draining its instances");
+                let old = entry.insert(Arc::new(FunctionPool::new(fingerprint)));
+                // Invocations waiting on the old pool go to the new one instead.
+                old.released.notify_waiters();
+                self.draining.push((key.clone(), old));
+                entry.get().clone()
+            }
+            Entry::Occupied(entry) => entry.get().clone(),
+            Entry::Vacant(entry) => entry
+                .insert(Arc::new(FunctionPool::new(fingerprint)))
+                .clone(),
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (&PoolKey, &Arc<FunctionPool>)> {
+        self.active
+            .iter()
+            .chain(self.draining.iter().map(|(key, pool)| (key, pool)))
+    }
 }
 
 pub struct FunctionInvocations {
     root_path: PathBuf,
-    functions: Arc<Mutex<HashMap<String, Arc<Mutex<Invocation>>>>>,
+    config: PoolConfig,
+    functions: Arc<Mutex<Pools>>,
+    /// Provisioned concurrency per function, kept running regardless of the ttl.
+    provisioned: StdMutex<HashMap<PoolKey, usize>>,
 }
 
 impl FunctionInvocations {
-    pub fn new(root_path: PathBuf) -> Self {
+    pub fn new(root_path: PathBuf, config: PoolConfig) -> Self {
         Self {
-            functions: Arc::new(Mutex::new(HashMap::new())),
+            functions: Arc::new(Mutex::new(Pools::default())),
+            provisioned: StdMutex::new(HashMap::new()),
             root_path,
+            config,
         }
     }
 }
 
 impl FunctionInvocations {
-    /// Get a process by instance_id
-    pub async fn get(&self, instance_id: &str) -> Option<Arc<Mutex<Invocation>>> {
-        self.get_internal(instance_id, true).await
+    pub fn config(&self) -> &PoolConfig {
+        &self.config
     }
 
-    pub async fn peek(&self, instance_id: &str) -> Option<Arc<Mutex<Invocation>>> {
-        self.get_internal(instance_id, false).await
+    /// The pool of `key` for instances started with the config `fingerprint` stands for,
+    /// created empty on first use. Instances started with another config are drained.
+    pub async fn pool(&self, key: &PoolKey, fingerprint: &str) -> Arc<FunctionPool> {
+        self.functions.lock().await.get(key, fingerprint)
     }
 
-    pub async fn get_internal(
-        &self,
-        instance_id: &str,
-        touch: bool,
-    ) -> Option<Arc<Mutex<Invocation>>> {
+    /// Replaces the provisioned concurrency of every function, functions missing from
+    /// `provisioned` fall back to `min_instances`.
+    pub fn set_provisioned(&self, provisioned: HashMap<PoolKey, usize>) {
+        *self.provisioned.lock().expect("provisioned poisoned") = provisioned;
+    }
+
+    #[cfg(test)]
+    pub async fn keys(&self) -> Vec<PoolKey> {
         let functions = self.functions.lock().await;
+        functions.iter().map(|(key, _)| key.clone()).collect()
+    }
 
-        let invocation = functions.get(instance_id)?.clone();
+    /// Registers a freshly started instance and hands out its first slot.
+    pub async fn insert(
+        &self,
+        key: &PoolKey,
+        fingerprint: &str,
+        instance_id: String,
+        url: Url,
+    ) -> Lease {
+        info!("inserting a new proccess with id {}", instance_id);
+        let pool = self
+            .register(key, fingerprint, instance_id.clone(), url.clone(), 1)
+            .await;
 
-        if touch {
-            let mut inv = invocation.lock().await;
-            inv.last_accessed = Instant::now();
+        Lease {
+            pool,
+            instance_id,
+            url,
         }
+    }
 
-        Some(invocation)
+    /// Registers an idle instance that was already running, e.g. one left by a previous
+    /// worker.
+    pub async fn adopt(&self, key: &PoolKey, fingerprint: &str, instance_id: String, url: Url) {
+        info!(instance_id = %instance_id, "Adopting running instance");
+        self.register(key, fingerprint, instance_id, url, 0).await;
     }
 
-    pub async fn keys(&self) -> Vec<String> {
+    async fn register(
+        &self,
+        key: &PoolKey,
+        fingerprint: &str,
+        instance_id: String,
+        url: Url,
+        in_flight: usize,
+    ) -> Arc<FunctionPool> {
+        let pool = {
+            let mut functions = self.functions.lock().await;
+            let pool = functions.get(key, fingerprint);
+            pool.instances
+                .lock()
+                .expect("pool poisoned")
+                .push(Invocation {
+                    instance_id,
+                    url,
+                    last_accessed: Instant::now(),
+                    in_flight,
+                });
+            pool
+        };
+        metrics::instances_added(1);
+        // Waiters may fit in the remaining concurrency of the new instance.
+        pool.released.notify_waiters();
+        pool
+    }
+
+    /// Every instance currently registered.
+    pub async fn instances(&self) -> Vec<InstanceState> {
         let functions = self.functions.lock().await;
-        functions.keys().cloned().collect()
+        functions
+            .iter()
+            .flat_map(|(key, pool)| {
+                let instances = pool.instances.lock().expect("pool poisoned");
+                instances
+                    .iter()
+                    .map(|inv| InstanceState {
+                        key: key.clone(),
+                        instance_id: inv.instance_id.clone(),
+                        url: inv.url.clone(),
+                        idle: inv.in_flight == 0,
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect()
     }
 
-    /// Insert a process (idempotent overwrite)
-    pub async fn insert(&self, instance_id: String, url: Url) -> Arc<Mutex<Invocation>> {
-        info!("inserting a new proccess with id {}", instance_id);
-        let new_invocation = Arc::new(Mutex::new(Invocation {
-            url,
-            last_accessed: Instant::now(),
-        }));
+    /// Removes instances idle for longer than `ttl`, keeping `min_instances` or the
+    /// provisioned concurrency per function, and returns their ids. Idle instances of
+    /// draining pools are removed right away. Empty pools are dropped.
+    pub async fn take_idle(&self, ttl: Duration) -> Vec<String> {
         let mut functions = self.functions.lock().await;
-        functions.insert(instance_id, new_invocation.clone());
-        new_invocation
+        let provisioned = self.provisioned.lock().expect("provisioned poisoned");
+        let now = Instant::now();
+        let mut removed = vec![];
+
+        functions.draining.retain(|(_, pool)| {
+            let mut instances = pool.instances.lock().expect("pool poisoned");
+            instances.retain(|inv| {
+                if inv.in_flight == 0 {
+                    removed.push(inv.instance_id.clone());
+                }
+                inv.in_flight > 0
+            });
+            !instances.is_empty()
+        });
+
+        functions.active.retain(|key, pool| {
+            let keep = provisioned
+                .get(key)
+                .map_or(self.config.min_instances, |&count| {
+                    count.max(self.config.min_instances)
+                });
+            let mut instances = pool.instances.lock().expect("pool poisoned");
+            // Longest idle first, so the most recently used stay warm.
+            instances.sort_by_key(|inv| inv.last_accessed);
+
+            let mut remaining = instances.len();
+            instances.retain(|inv| {
+                let idle = inv.in_flight == 0 && now - inv.last_accessed > ttl;
+                if idle && remaining > keep {
+                    remaining -= 1;
+                    removed.push(inv.instance_id.clone());
+                    false
+                } else {
+                    true
+                }
+            });
+            !instances.is_empty()
+        });
+
+        metrics::instances_removed(removed.len());
+        removed
     }
 
-    pub async fn delete(&self, instance_id: &str) -> Result<()> {
-        info!("deleting {}", instance_id);
+    /// Scales every function down to its warm minimum, stopping the idle containers.
+    pub async fn scale_down(&self, ttl: Duration) -> Result<()> {
+        let idle = self.take_idle(ttl).await;
+        metrics::reaped(Reaped::Idle, idle.len());
+        for instance_id in idle {
+            if let Err(err) = self.cleanup(&instance_id).await {
+                warn!(instance_id = %instance_id, error = ?err, "Failed to stop idle instance");
+            }
+        }
+        Ok(())
+    }
 
-        let removed = {
-            let mut functions = self.functions.lock().await;
-            functions.remove(instance_id)
-        };
+    /// Stops one instance right away, even while it still has invocations in flight.
+    pub async fn evict(&self, key: &PoolKey, instance_id: &str) -> Result<()> {
+        self.remove(key, instance_id, false).await;
+        self.cleanup(instance_id).await
+    }
 
-        if removed.is_none() {
-            return Ok(());
+    /// Evicts the instance unless it took an invocation since it was seen idle, and
+    /// returns whether it did.
+    pub async fn evict_idle(&self, key: &PoolKey, instance_id: &str) -> Result<bool> {
+        if !self.remove(key, instance_id, true).await {
+            return Ok(false);
         }
+        self.cleanup(instance_id).await?;
+        Ok(true)
+    }
 
+    /// Takes the instance out of its pool, only when idle if `only_idle`, and returns
+    /// whether it did.
+    async fn remove(&self, key: &PoolKey, instance_id: &str, only_idle: bool) -> bool {
+        let pools: Vec<Arc<FunctionPool>> = {
+            let functions = self.functions.lock().await;
+            functions
+                .iter()
+                .filter(|(pool_key, _)| *pool_key == key)
+                .map(|(_, pool)| pool.clone())
+                .collect()
+        };
+        let mut removed = false;
+        for pool in pools {
+            let mut instances = pool.instances.lock().expect("pool poisoned");
+            let before = instances.len();
+            instances
+                .retain(|inv| inv.instance_id != instance_id || (only_idle && inv.in_flight > 0));
+            metrics::instances_removed(before - instances.len());
+            removed |= instances.len() < before;
+            drop(instances);
+            // Waiters may start a new instance in the freed capacity.
+            pool.released.notify_waiters();
+        }
+        removed
+    }
+
+    async fn cleanup(&self, instance_id: &str) -> Result<()> {
+        info!("deleting {}", instance_id);
         let mut proc = ProccesContainer::load(&self.root_path, instance_id).await?;
         proc.cleanup().await?;
-
         Ok(())
     }
 
     pub async fn delete_all(&self) -> Result<()> {
-        let keys: Vec<String> = {
-            let functions = self.functions.lock().await;
-            functions.keys().cloned().collect()
+        let instance_ids: Vec<String> = {
+            let mut functions = self.functions.lock().await;
+            let pools = std::mem::take(&mut *functions);
+            pools
+                .iter()
+                .flat_map(|(_, pool)| {
+                    let instances = pool.instances.lock().expect("pool poisoned");
+                    instances
+                        .iter()
+                        .map(|inv| inv.instance_id.clone())
+                        .collect::<Vec<_>>()
+                })
+                .collect()
         };
+        metrics::instances_removed(instance_ids.len());
 
-        for key in keys {
-            self.delete(&key).await?;
+        for instance_id in instance_ids {
+            self.cleanup(&instance_id).await?;
         }
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invocations(min_instances: usize) -> FunctionInvocations {
+        FunctionInvocations::new(
+            PathBuf::from("/tmp/unused"),
+            PoolConfig {
+                min_instances,
+                max_instances: 2,
+                max_concurrency: 2,
+            },
+        )
+    }
+
+    fn key(name: &str) -> PoolKey {
+        PoolKey {
+            name: name.to_string(),
+            digest: "abc".to_string(),
+        }
+    }
+
+    fn url(id: &str) -> Url {
+        Url::parse(&format!("unix:///tmp/{}.sock", id)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_acquire_respects_instance_concurrency() {
+        let invocations = invocations(0);
+        let first = invocations
+            .insert(&key("fn"), "v1", "fn-a".to_string(), url("a"))
+            .await;
+        let pool = invocations.pool(&key("fn"), "v1").await;
+
+        let second = pool.try_acquire(2).unwrap();
+        assert_eq!(second.instance_id, "fn-a");
+        assert!(pool.try_acquire(2).is_none());
+
+        drop(first);
+        assert_eq!(pool.try_acquire(2).unwrap().url, url("a"));
+    }
+
+    #[tokio::test]
+    async fn test_acquire_prefers_least_busy_instance() {
+        let invocations = invocations(0);
+        let _a = invocations
+            .insert(&key("fn"), "v1", "fn-a".to_string(), url("a"))
+            .await;
+        let b = invocations
+            .insert(&key("fn"), "v1", "fn-b".to_string(), url("b"))
+            .await;
+        drop(b);
+
+        let pool = invocations.pool(&key("fn"), "v1").await;
+        assert_eq!(pool.len(), 2);
+        assert_eq!(pool.try_acquire(2).unwrap().instance_id, "fn-b");
+    }
+
+    #[tokio::test]
+    async fn test_take_idle_keeps_busy_and_minimum_instances() {
+        let invocations = invocations(1);
+        let busy = invocations
+            .insert(&key("fn"), "v1", "fn-a".to_string(), url("a"))
+            .await;
+        drop(
+            invocations
+                .insert(&key("fn"), "v1", "fn-b".to_string(), url("b"))
+                .await,
+        );
+        drop(
+            invocations
+                .insert(&key("other"), "v1", "other-a".to_string(), url("c"))
+                .await,
+        );
+
+        // `fn-a` is busy and `other-a` is the warm minimum of its function.
+        let removed = invocations.take_idle(Duration::ZERO).await;
+        assert_eq!(removed, vec!["fn-b"]);
+
+        drop(busy);
+        assert!(invocations.take_idle(Duration::ZERO).await.is_empty());
+        assert_eq!(invocations.keys().await.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_take_idle_drops_empty_pools() {
+        let invocations = invocations(0);
+        drop(
+            invocations
+                .insert(&key("fn"), "v1", "fn-a".to_string(), url("a"))
+                .await,
+        );
+
+        assert!(
+            invocations
+                .take_idle(Duration::from_secs(60))
+                .await
+                .is_empty()
+        );
+        assert_eq!(invocations.take_idle(Duration::ZERO).await, vec!["fn-a"]);
+        assert!(invocations.keys().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_take_idle_keeps_provisioned_instances() {
+        let invocations = invocations(0);
+        for id in ["fn-a", "fn-b", "fn-c"] {
+            drop(
+                invocations
+                    .insert(&key("fn"), "v1", id.to_string(), url(id))
+                    .await,
+            );
+        }
+        invocations.set_provisioned(HashMap::from([(key("fn"), 2)]));
+
+        assert_eq!(invocations.take_idle(Duration::ZERO).await, vec!["fn-a"]);
+        assert!(invocations.take_idle(Duration::ZERO).await.is_empty());
+
+        invocations.set_provisioned(HashMap::new());
+        assert_eq!(invocations.take_idle(Duration::ZERO).await.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_evict_removes_busy_instance() {
+        let invocations = invocations(0);
+        let lease = invocations
+            .insert(&key("fn"), "v1", "fn-a".to_string(), url("a"))
+            .await;
+        drop(
+            invocations
+                .insert(&key("fn"), "v1", "fn-b".to_string(), url("b"))
+                .await,
+        );
+
+        // There is no container behind the test instances to stop.
+        assert!(invocations.evict(&key("fn"), "fn-a").await.is_err());
+
+        let pool = invocations.pool(&key("fn"), "v1").await;
+        assert_eq!(pool.len(), 1);
+        drop(lease);
+        assert_eq!(pool.try_acquire(2).unwrap().instance_id, "fn-b");
+    }
+
+    #[tokio::test]
+    async fn test_evict_idle_keeps_instance_that_became_busy() {
+        let invocations = invocations(0);
+        let lease = invocations
+            .insert(&key("fn"), "v1", "fn-a".to_string(), url("a"))
+            .await;
+
+        assert!(!invocations.evict_idle(&key("fn"), "fn-a").await.unwrap());
+        let pool = invocations.pool(&key("fn"), "v1").await;
+        assert_eq!(pool.len(), 1);
+
+        drop(lease);
+        // There is no container behind the test instances to stop.
+        assert!(invocations.evict_idle(&key("fn"), "fn-a").await.is_err());
+        assert_eq!(pool.len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_adopted_instance_is_idle_and_reapable() {
+        let invocations = invocations(0);
+        invocations
+            .adopt(&key("fn"), "v1", "fn-a".to_string(), url("a"))
+            .await;
+
+        let pool = invocations.pool(&key("fn"), "v1").await;
+        let lease = pool.try_acquire(2).unwrap();
+        assert_eq!(lease.instance_id, "fn-a");
+        drop(lease);
+
+        assert_eq!(invocations.take_idle(Duration::ZERO).await, vec!["fn-a"]);
+    }
+
+    #[tokio::test]
+    async fn test_instances_reports_idle_state() {
+        let invocations = invocations(0);
+        let _busy = invocations
+            .insert(&key("fn"), "v1", "fn-a".to_string(), url("a"))
+            .await;
+        invocations
+            .adopt(&key("fn"), "v1", "fn-b".to_string(), url("b"))
+            .await;
+
+        let mut instances = invocations.instances().await;
+        instances.sort_by(|a, b| a.instance_id.cmp(&b.instance_id));
+        let states = instances
+            .iter()
+            .map(|i| (i.key.name.as_str(), i.instance_id.as_str(), i.idle))
+            .collect::<Vec<_>>();
+        assert_eq!(states, [("fn", "fn-a", false), ("fn", "fn-b", true)]);
+    }
+
+    #[tokio::test]
+    async fn test_functions_sharing_a_digest_get_their_own_instances() {
+        let invocations = invocations(0);
+        drop(
+            invocations
+                .insert(&key("fn"), "v1", "fn-a".to_string(), url("a"))
+                .await,
+        );
+
+        let other = invocations.pool(&key("other"), "v1").await;
+        assert!(other.try_acquire(2).is_none());
+        let pool = invocations.pool(&key("fn"), "v1").await;
+        assert_eq!(pool.try_acquire(2).unwrap().instance_id, "fn-a");
+    }
+
+    #[tokio::test]
+    async fn test_config_change_drains_old_instances() {
+        let invocations = invocations(1);
+        let busy = invocations
+            .insert(&key("fn"), "v1", "fn-a".to_string(), url("a"))
+            .await;
+        drop(
+            invocations
+                .insert(&key("fn"), "v1", "fn-b".to_string(), url("b"))
+                .await,
+        );
+
+        // The old instances take no new invocations once the config changed.
+        let pool = invocations.pool(&key("fn"), "v2").await;
+        assert!(pool.try_acquire(2).is_none());
+        assert_eq!(invocations.instances().await.len(), 2);
+
+        // Idle ones are stopped at once despite `min_instances`, busy ones when done.
+        assert_eq!(
+            invocations.take_idle(Duration::from_secs(60)).await,
+            vec!["fn-b"]
+        );
+        drop(busy);
+        assert_eq!(
+            invocations.take_idle(Duration::from_secs(60)).await,
+            vec!["fn-a"]
+        );
+        assert!(invocations.keys().await.is_empty());
+    }
+
+    #[test]
+    fn test_fingerprint_covers_what_instances_start_with() {
+        let config = |env: &[(&str, &str)], timeout_ms| FunctionConfig {
+            timeout_ms,
+            env: env
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            ..Default::default()
+        };
+        let base = fingerprint(&config(&[("A", "1"), ("B", "2")], 0));
+
+        assert_eq!(base, fingerprint(&config(&[("B", "2"), ("A", "1")], 0)));
+        assert_eq!(base, fingerprint(&config(&[("A", "1"), ("B", "2")], 500)));
+        assert_ne!(base, fingerprint(&config(&[("A", "1"), ("B", "3")], 0)));
+        assert_ne!(base, fingerprint(&config(&[("A", "1")], 0)));
+    }
+}
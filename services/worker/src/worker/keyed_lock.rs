@@ -0,0 +1,113 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard};
+
+/// One async lock per key, so work on the same key is serialized while different
+/// keys proceed in parallel. Entries are dropped again once nobody holds or waits on them.
+#[derive(Default)]
+pub struct KeyedLock {
+    locks: Mutex<HashMap<String, Arc<AsyncMutex<()>>>>,
+}
+
+pub struct KeyedGuard<'a> {
+    owner: &'a KeyedLock,
+    key: String,
+    guard: Option<OwnedMutexGuard<()>>,
+}
+
+impl KeyedLock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub async fn lock(&self, key: &str) -> KeyedGuard<'_> {
+        let mutex = {
+            let mut locks = self.locks.lock().expect("keyed lock poisoned");
+            locks.entry(key.to_string()).or_default().clone()
+        };
+
+        KeyedGuard {
+            owner: self,
+            key: key.to_string(),
+            guard: Some(mutex.lock_owned().await),
+        }
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.locks.lock().unwrap().len()
+    }
+}
+
+impl Drop for KeyedGuard<'_> {
+    fn drop(&mut self) {
+        drop(self.guard.take());
+
+        // Waiters clone the mutex under the map lock, so a single reference left
+        // here means nobody else is interested in the key.
+        let mut locks = self.owner.locks.lock().expect("keyed lock poisoned");
+        if locks
+            .get(&self.key)
+            .is_some_and(|mutex| Arc::strong_count(mutex) == 1)
+        {
+            locks.remove(&self.key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::time::timeout;
+
+    #[tokio::test]
+    async fn test_same_key_is_serialized() {
+        let locks = KeyedLock::new();
+        let guard = locks.lock("a").await;
+
+        assert!(
+            timeout(Duration::from_millis(20), locks.lock("a"))
+                .await
+                .is_err()
+        );
+
+        drop(guard);
+        let _guard = timeout(Duration::from_millis(20), locks.lock("a"))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_different_keys_run_in_parallel() {
+        let locks = KeyedLock::new();
+        let _a = locks.lock("a").await;
+
+        let _b = timeout(Duration::from_millis(20), locks.lock("b"))
+            .await
+            .unwrap();
+        assert_eq!(locks.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_released_keys_are_removed() {
+        let locks = Arc::new(KeyedLock::new());
+        let guard = locks.lock("a").await;
+
+        let waiter = {
+            let locks = locks.clone();
+            tokio::spawn(async move {
+                let _guard = locks.lock("a").await;
+            })
+        };
+        tokio::task::yield_now().await;
+
+        // The waiter still needs the entry after the first holder is done.
+        drop(guard);
+        waiter.await.unwrap();
+        assert_eq!(locks.len(), 0);
+    }
+}
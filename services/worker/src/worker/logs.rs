@@ -0,0 +1,461 @@
+use std::{
+    collections::{HashMap, VecDeque, hash_map::Entry},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex as StdMutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result};
+use proto::api::worker::{LogEntry, LogStream};
+use sha2::{Digest, Sha256};
+use tokio::{
+    fs::{File, OpenOptions},
+    io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::unix::pipe,
+    sync::{Mutex, broadcast},
+};
+use tokio_stream::{Stream, StreamExt, wrappers::BroadcastStream};
+use tracing::{debug, warn};
+
+/// Longer lines are split, so a handler can't grow the worker's memory without bound.
+const MAX_LINE_BYTES: u64 = 16 * 1024;
+/// New lines a slow follower may fall behind on before it starts missing some.
+const TAIL_CAPACITY: usize = 1024;
+
+struct Segment {
+    file: File,
+    len: u64,
+}
+
+/// Output of function instances, kept on disk in a bounded ring per function name so
+/// it outlives deploys.
+///
+/// Each function writes to a current segment which replaces the previous one once it
+/// holds half of `max_bytes`, so no function ever keeps more than `max_bytes`.
+pub struct LogStore {
+    dir: PathBuf,
+    max_bytes: u64,
+    /// Open current segments, also keeps followers from missing lines while they
+    /// read the backlog.
+    segments: Mutex<HashMap<String, Segment>>,
+    /// Invocations each instance is handling right now.
+    active: StdMutex<HashMap<String, Vec<String>>>,
+    tail: broadcast::Sender<(String, LogEntry)>,
+}
+
+/// Attributes an instance's output to one invocation until dropped.
+pub struct InvocationLogs {
+    store: Arc<LogStore>,
+    instance_id: String,
+    invocation_id: String,
+}
+
+impl Drop for InvocationLogs {
+    fn drop(&mut self) {
+        let mut active = self.store.active.lock().expect("active poisoned");
+        if let Some(invocations) = active.get_mut(&self.instance_id) {
+            if let Some(pos) = invocations.iter().position(|id| *id == self.invocation_id) {
+                invocations.swap_remove(pos);
+            }
+            if invocations.is_empty() {
+                active.remove(&self.instance_id);
+            }
+        }
+    }
+}
+
+impl LogStore {
+    pub fn new(dir: PathBuf, max_bytes: u64) -> Result<Self> {
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create log directory: {}", dir.display()))?;
+        Ok(Self {
+            dir,
+            max_bytes,
+            segments: Mutex::new(HashMap::new()),
+            active: StdMutex::new(HashMap::new()),
+            tail: broadcast::channel(TAIL_CAPACITY).0,
+        })
+    }
+
+    /// Tags the output of `instance_id` with `invocation_id` while the returned guard
+    /// lives. Lines of an instance handling several invocations at once stay untagged,
+    /// they can't be told apart.
+    pub fn begin_invocation(
+        self: &Arc<Self>,
+        instance_id: &str,
+        invocation_id: &str,
+    ) -> InvocationLogs {
+        self.active
+            .lock()
+            .expect("active poisoned")
+            .entry(instance_id.to_string())
+            .or_default()
+            .push(invocation_id.to_string());
+        InvocationLogs {
+            store: self.clone(),
+            instance_id: instance_id.to_string(),
+            invocation_id: invocation_id.to_string(),
+        }
+    }
+
+    /// Reads an instance's output FIFO in the background until the instance exits.
+    pub fn capture(
+        self: &Arc<Self>,
+        function: &str,
+        instance_id: &str,
+        stream: LogStream,
+        fifo: &Path,
+    ) -> Result<()> {
+        let output = pipe::OpenOptions::new()
+            .open_receiver(fifo)
+            .with_context(|| format!("Failed to open instance output: {}", fifo.display()))?;
+        let store = self.clone();
+        let function = function.to_string();
+        let instance_id = instance_id.to_string();
+        tokio::spawn(async move {
+            store
+                .read_output(&function, &instance_id, stream, output)
+                .await
+        });
+        Ok(())
+    }
+
+    async fn read_output(
+        &self,
+        function: &str,
+        instance_id: &str,
+        stream: LogStream,
+        output: impl AsyncRead + Unpin,
+    ) {
+        let mut reader = BufReader::new(output);
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            match (&mut reader)
+                .take(MAX_LINE_BYTES)
+                .read_until(b'\n', &mut line)
+                .await
+            {
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(err) => {
+                    warn!(instance_id, error = %err, "Failed to read instance output");
+                    break;
+                }
+            }
+            if line.last() == Some(&b'\n') {
+                line.pop();
+            }
+
+            let entry = LogEntry {
+                timestamp_ms: now_ms(),
+                instance_id: instance_id.to_string(),
+                invocation_id: self.invocation_of(instance_id),
+                stream: stream.into(),
+                line: String::from_utf8_lossy(&line).into_owned(),
+            };
+            // Keeps draining, a full pipe would block the handler.
+            if let Err(err) = self.append(function, entry).await {
+                warn!(instance_id, error = ?err, "Failed to store instance output");
+            }
+        }
+        debug!(instance_id, ?stream, "Instance output closed");
+    }
+
+    fn invocation_of(&self, instance_id: &str) -> String {
+        let active = self.active.lock().expect("active poisoned");
+        match active.get(instance_id).map(Vec::as_slice) {
+            Some([invocation_id]) => invocation_id.clone(),
+            _ => String::new(),
+        }
+    }
+
+    async fn append(&self, function: &str, entry: LogEntry) -> Result<()> {
+        let record = encode(&entry);
+        let mut segments = self.segments.lock().await;
+        let segment = match segments.entry(function.to_string()) {
+            Entry::Occupied(segment) => segment.into_mut(),
+            Entry::Vacant(vacant) => {
+                let file = open_segment(&self.current_path(function)).await?;
+                let len = file.metadata().await?.len();
+                vacant.insert(Segment { file, len })
+            }
+        };
+
+        if segment.len > 0 && segment.len + record.len() as u64 > self.max_bytes / 2 {
+            debug!(function, "Rotating log segment");
+            tokio::fs::rename(self.current_path(function), self.previous_path(function)).await?;
+            segment.file = open_segment(&self.current_path(function)).await?;
+            segment.len = 0;
+        }
+
+        segment.file.write_all(record.as_bytes()).await?;
+        // Readers open the file themselves and must see the line right away.
+        segment.file.flush().await?;
+        segment.len += record.len() as u64;
+
+        // Fails only without followers.
+        let _ = self.tail.send((function.to_string(), entry));
+        Ok(())
+    }
+
+    /// The newest `limit` lines of `function`, oldest first, only those of
+    /// `invocation_id` when it isn't empty.
+    pub async fn read(
+        &self,
+        function: &str,
+        limit: usize,
+        invocation_id: &str,
+    ) -> Result<Vec<LogEntry>> {
+        let _segments = self.segments.lock().await;
+        self.read_segments(function, limit, invocation_id).await
+    }
+
+    /// The newest `backlog` lines of `function` followed by every line written after.
+    pub async fn tail(
+        &self,
+        function: &str,
+        backlog: usize,
+    ) -> Result<impl Stream<Item = LogEntry> + Send + use<>> {
+        let segments = self.segments.lock().await;
+        // Subscribed under the lock, so no line is missed or sent twice.
+        let receiver = self.tail.subscribe();
+        let backlog = self.read_segments(function, backlog, "").await?;
+        drop(segments);
+
+        let function = function.to_string();
+        let follow = BroadcastStream::new(receiver).filter_map(move |entry| match entry {
+            Ok((id, entry)) if id == function => Some(entry),
+            Ok(_) => None,
+            Err(err) => {
+                warn!(function, error = %err, "Log follower fell behind");
+                None
+            }
+        });
+        Ok(tokio_stream::iter(backlog).chain(follow))
+    }
+
+    async fn read_segments(
+        &self,
+        function: &str,
+        limit: usize,
+        invocation_id: &str,
+    ) -> Result<Vec<LogEntry>> {
+        if limit == 0 {
+            return Ok(vec![]);
+        }
+        let mut entries = VecDeque::new();
+
+        for path in [self.previous_path(function), self.current_path(function)] {
+            let content = match tokio::fs::read(&path).await {
+                Ok(content) => content,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => {
+                    return Err(e)
+                        .with_context(|| format!("Failed to read logs: {}", path.display()));
+                }
+            };
+
+            let entries_in_segment = String::from_utf8_lossy(&content)
+                .split('\n')
+                .filter_map(decode)
+                .filter(|entry| invocation_id.is_empty() || entry.invocation_id == invocation_id)
+                .collect::<Vec<_>>();
+            for entry in entries_in_segment {
+                if entries.len() == limit {
+                    entries.pop_front();
+                }
+                entries.push_back(entry);
+            }
+        }
+        Ok(entries.into())
+    }
+
+    fn current_path(&self, function: &str) -> PathBuf {
+        self.dir.join(format!("{}.log", segment_name(function)))
+    }
+
+    fn previous_path(&self, function: &str) -> PathBuf {
+        self.dir.join(format!("{}.log.1", segment_name(function)))
+    }
+}
+
+async fn open_segment(path: &Path) -> Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await
+        .with_context(|| format!("Failed to open log segment: {}", path.display()))
+}
+
+/// Function names may hold any character but `@` and `:`, their hash is safe as a file name.
+fn segment_name(function: &str) -> String {
+    format!("{:x}", Sha256::digest(function.as_bytes()))
+}
+
+fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as i64)
+}
+
+/// One tab separated record per line, the output line itself goes last as it may
+/// contain tabs.
+fn encode(entry: &LogEntry) -> String {
+    format!(
+        "{}\t{}\t{}\t{}\t{}\n",
+        entry.timestamp_ms, entry.instance_id, entry.invocation_id, entry.stream, entry.line
+    )
+}
+
+fn decode(record: &str) -> Option<LogEntry> {
+    let mut fields = record.splitn(5, '\t');
+    Some(LogEntry {
+        timestamp_ms: fields.next()?.parse().ok()?,
+        instance_id: fields.next()?.to_string(),
+        invocation_id: fields.next()?.to_string(),
+        stream: fields.next()?.parse().ok()?,
+        line: fields.next()?.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn store(temp: &TempDir, max_bytes: u64) -> Arc<LogStore> {
+        Arc::new(LogStore::new(temp.path().join("logs"), max_bytes).unwrap())
+    }
+
+    fn lines(entries: &[LogEntry]) -> Vec<&str> {
+        entries.iter().map(|entry| entry.line.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_output_is_tagged_with_the_only_active_invocation() {
+        let temp = TempDir::new().unwrap();
+        let store = store(&temp, 1 << 20);
+
+        let first = store.begin_invocation("fn-a", "inv-1");
+        store
+            .read_output("fn", "fn-a", LogStream::Stdout, &b"one\ttab\n"[..])
+            .await;
+        let second = store.begin_invocation("fn-a", "inv-2");
+        store
+            .read_output("fn", "fn-a", LogStream::Stderr, &b"two\n"[..])
+            .await;
+        drop(first);
+        drop(second);
+        store
+            .read_output("fn", "fn-a", LogStream::Stdout, &b"three"[..])
+            .await;
+
+        let entries = store.read("fn", 10, "").await.unwrap();
+        assert_eq!(lines(&entries), ["one\ttab", "two", "three"]);
+        assert_eq!(entries[0].invocation_id, "inv-1");
+        assert_eq!(entries[1].invocation_id, "");
+        assert_eq!(entries[1].stream(), LogStream::Stderr);
+        assert_eq!(entries[2].instance_id, "fn-a");
+
+        let entries = store.read("fn", 10, "inv-1").await.unwrap();
+        assert_eq!(lines(&entries), ["one\ttab"]);
+        assert!(store.read("other", 10, "").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_logs_are_bounded_and_keep_the_newest_lines() {
+        let temp = TempDir::new().unwrap();
+        let store = store(&temp, 1024);
+
+        let output = (0..200)
+            .map(|i| format!("line {}\n", i))
+            .collect::<String>();
+        store
+            .read_output("fn", "fn-a", LogStream::Stdout, output.as_bytes())
+            .await;
+
+        let size = |path: PathBuf| std::fs::metadata(path).unwrap().len();
+        assert!(size(store.current_path("fn")) + size(store.previous_path("fn")) <= 1024);
+
+        let entries = store.read("fn", 3, "").await.unwrap();
+        assert_eq!(lines(&entries), ["line 197", "line 198", "line 199"]);
+    }
+
+    #[tokio::test]
+    async fn test_function_names_stay_inside_the_log_dir() {
+        let temp = TempDir::new().unwrap();
+        let store = store(&temp, 1 << 20);
+
+        store
+            .read_output("../../fn", "fn-a", LogStream::Stdout, &b"one\n"[..])
+            .await;
+
+        let files = std::fs::read_dir(temp.path().join("logs")).unwrap().count();
+        assert_eq!(files, 1);
+        assert_eq!(std::fs::read_dir(temp.path()).unwrap().count(), 1);
+        let entries = store.read("../../fn", 10, "").await.unwrap();
+        assert_eq!(lines(&entries), ["one"]);
+    }
+
+    #[tokio::test]
+    async fn test_long_lines_are_split() {
+        let temp = TempDir::new().unwrap();
+        let store = store(&temp, 1 << 20);
+
+        let output = "x".repeat(MAX_LINE_BYTES as usize + 10);
+        store
+            .read_output("fn", "fn-a", LogStream::Stdout, output.as_bytes())
+            .await;
+
+        let entries = store.read("fn", 10, "").await.unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].line, "x".repeat(10));
+    }
+
+    #[tokio::test]
+    async fn test_tail_sends_backlog_then_follows_the_function() {
+        let temp = TempDir::new().unwrap();
+        let store = store(&temp, 1 << 20);
+        store
+            .read_output("fn", "fn-a", LogStream::Stdout, &b"old\nbacklog\n"[..])
+            .await;
+
+        let tail = store.tail("fn", 1).await.unwrap();
+        tokio::pin!(tail);
+        store
+            .read_output("other", "other-a", LogStream::Stdout, &b"skipped\n"[..])
+            .await;
+        store
+            .read_output("fn", "fn-a", LogStream::Stdout, &b"new\n"[..])
+            .await;
+
+        assert_eq!(tail.next().await.unwrap().line, "backlog");
+        assert_eq!(tail.next().await.unwrap().line, "new");
+    }
+
+    #[tokio::test]
+    async fn test_capture_reads_instance_fifo() {
+        let temp = TempDir::new().unwrap();
+        let store = store(&temp, 1 << 20);
+        let fifo = temp.path().join("stdout");
+        nix::unistd::mkfifo(&fifo, nix::sys::stat::Mode::S_IRWXU).unwrap();
+
+        // Opened like the instance's end, for reading and writing.
+        let mut instance = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&fifo)
+            .unwrap();
+        std::io::Write::write_all(&mut instance, b"hello\n").unwrap();
+
+        let tail = store.tail("fn", 0).await.unwrap();
+        tokio::pin!(tail);
+        store
+            .capture("fn", "fn-a", LogStream::Stdout, &fifo)
+            .unwrap();
+        assert_eq!(tail.next().await.unwrap().line, "hello");
+        drop(instance);
+    }
+}
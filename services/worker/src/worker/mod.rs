@@ -1,4 +1,8 @@
 mod container;
 pub mod function_invocations;
+mod keyed_lock;
+pub mod logs;
+mod network;
 pub mod organizer;
+mod problem;
 pub mod spec;
@@ -0,0 +1,188 @@
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+    process::Stdio,
+};
+
+use anyhow::{Context, Result, bail};
+use nix::{
+    sys::signal::{Signal, kill},
+    unistd::Pid,
+};
+use proto::{
+    api::controlplane::{NetworkConfig, NetworkMode},
+    egress::EgressRule,
+};
+use tokio::{io::AsyncWriteExt, process::Command};
+use tracing::{debug, warn};
+
+/// Where the user mode networking helper of an instance writes its pid.
+const HELPER_PID_FILE: &str = "pasta.pid";
+
+/// Sets up the network namespace of a freshly started instance.
+///
+/// The namespace belongs to the container's user namespace, so it is configured from
+/// the host with `nsenter` and `pasta` joining both, the worker itself never enters it.
+pub async fn attach(network: &NetworkConfig, pid: i32, bundle: &Path) -> Result<()> {
+    match network.mode() {
+        NetworkMode::Host => Ok(()),
+        NetworkMode::None => {
+            debug!(pid, "Bringing up loopback");
+            run(nsenter(pid).args(["ip", "link", "set", "lo", "up"]), None).await
+        }
+        NetworkMode::Egress => {
+            let rules = network
+                .egress_allowlist
+                .iter()
+                .map(|entry| entry.parse())
+                .collect::<Result<Vec<EgressRule>, _>>()?;
+
+            // Filter first, so the instance is never reachable without it.
+            debug!(pid, rules = rules.len(), "Applying egress allowlist");
+            run(
+                nsenter(pid).args(["nft", "-f", "-"]),
+                Some(&ruleset(&rules)),
+            )
+            .await?;
+
+            debug!(pid, "Starting user mode networking");
+            run(
+                Command::new("pasta")
+                    .args(["--config-net", "--quiet"])
+                    // No ports forwarded into the instance, it is only reached over its socket.
+                    .args(["-t", "none", "-u", "none", "-T", "none", "-U", "none"])
+                    .arg("--pid")
+                    .arg(bundle.join(HELPER_PID_FILE))
+                    .arg("--userns")
+                    .arg(ns_path(pid, "user"))
+                    .arg("--netns")
+                    .arg(ns_path(pid, "net")),
+                None,
+            )
+            .await
+        }
+    }
+}
+
+/// Stops the networking helper of an instance, if it has one.
+pub async fn detach(bundle: &Path) -> Result<()> {
+    let pid_file = bundle.join(HELPER_PID_FILE);
+    let pid = match tokio::fs::read_to_string(&pid_file).await {
+        Ok(pid) => pid,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e).context("Failed to read networking helper pid"),
+    };
+    let pid: i32 = pid
+        .trim()
+        .parse()
+        .with_context(|| format!("Invalid networking helper pid: {}", pid.trim()))?;
+
+    if let Err(err) = kill(Pid::from_raw(pid), Signal::SIGTERM) {
+        // Exits by itself once the namespace is gone.
+        warn!(pid, error = %err, "Failed to stop networking helper");
+    }
+    Ok(())
+}
+
+fn ns_path(pid: i32, ns: &str) -> PathBuf {
+    PathBuf::from(format!("/proc/{}/ns/{}", pid, ns))
+}
+
+fn nsenter(pid: i32) -> Command {
+    let mut command = Command::new("nsenter");
+    command
+        .arg(format!("--target={}", pid))
+        .args(["--user", "--net", "--preserve-credentials"]);
+    command
+}
+
+async fn run(command: &mut Command, stdin: Option<&str>) -> Result<()> {
+    let mut child = command
+        .stdin(if stdin.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run {:?}", command.as_std().get_program()))?;
+
+    if let Some(input) = stdin
+        && let Some(mut pipe) = child.stdin.take()
+    {
+        pipe.write_all(input.as_bytes()).await?;
+    }
+
+    let output = child.wait_with_output().await?;
+    if !output.status.success() {
+        bail!(
+            "{:?} failed with {}: {}",
+            command.as_std().get_program(),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+/// nftables ruleset dropping all outgoing traffic except loopback and the allowlist.
+fn ruleset(rules: &[EgressRule]) -> String {
+    let mut ruleset = String::from(
+        "table inet noctiforge {\n\
+         \tchain output {\n\
+         \t\ttype filter hook output priority 0; policy drop;\n\
+         \t\toif \"lo\" accept\n\
+         \t\tct state established,related accept\n",
+    );
+    for rule in rules {
+        let family = if rule.addr.is_ipv4() { "ip" } else { "ip6" };
+        let destination = format!("{} daddr {}/{}", family, rule.addr, rule.prefix);
+        match rule.port {
+            Some(port) => {
+                for protocol in ["tcp", "udp"] {
+                    let _ = writeln!(
+                        ruleset,
+                        "\t\t{} {} dport {} accept",
+                        destination, protocol, port
+                    );
+                }
+            }
+            None => {
+                let _ = writeln!(ruleset, "\t\t{} accept", destination);
+            }
+        }
+    }
+    ruleset.push_str("\t}\n}\n");
+    ruleset
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(addr: &str, prefix: u8, port: Option<u16>) -> EgressRule {
+        EgressRule {
+            addr: addr.parse().unwrap(),
+            prefix,
+            port,
+        }
+    }
+
+    #[test]
+    fn test_ruleset_only_accepts_allowlist() {
+        let ruleset = ruleset(&[rule("10.0.0.0", 8, Some(443)), rule("::1", 128, None)]);
+
+        assert!(ruleset.contains("policy drop;"));
+        assert!(ruleset.contains("oif \"lo\" accept"));
+        assert!(ruleset.contains("ip daddr 10.0.0.0/8 tcp dport 443 accept"));
+        assert!(ruleset.contains("ip daddr 10.0.0.0/8 udp dport 443 accept"));
+        assert!(ruleset.contains("ip6 daddr ::1/128 accept"));
+    }
+
+    #[tokio::test]
+    async fn test_detach_without_helper() {
+        let temp = tempfile::TempDir::new().unwrap();
+        detach(temp.path()).await.unwrap();
+    }
+}
@@ -1,44 +1,79 @@
-use std::{collections::HashMap, path::PathBuf, sync::Arc, time::Duration};
+use std::{collections::HashMap, fmt, path::PathBuf, sync::Arc, time::Duration};
 
-use anyhow::{Ok, Result};
-use libcontainer::syscall::Syscall;
+use anyhow::{Ok, Result, bail};
+use futures::future::join_all;
+use libcontainer::{container::ContainerStatus, syscall::Syscall};
+use proto::api::controlplane::FunctionConfig;
 use proto::api::worker::ExecuteResponse;
 use proto::api::worker::ExecuteSuccess;
+use proto::api::worker::LogStream;
 use proto::api::worker::execute_response::Outcome;
-use tokio::time::sleep;
-use tonic::Request;
-use tracing::{debug, info, instrument, warn};
+use tokio::time::{Instant, sleep, sleep_until, timeout, timeout_at};
+use tokio_util::sync::CancellationToken;
+use tonic::{Code, Request};
+use tracing::{Instrument, debug, info, instrument, warn};
 use url::Url;
+use uuid::Uuid;
 
 use crate::{
     client::registry_clint::RegistryClient,
+    metrics::{self, Reaped, Start},
     worker::{
         container::{self},
-        function_invocations::FunctionInvocations,
+        function_invocations::{FunctionInvocations, InstanceState, Lease, PoolKey, fingerprint},
+        keyed_lock::KeyedLock,
+        logs::LogStore,
+        problem,
         spec::SysUserParms,
     },
 };
 use proto::api::action::{
-    InvokeRequest, function_runner_service_client::FunctionRunnerServiceClient,
+    HealthRequest, InvokeRequest, function_runner_service_client::FunctionRunnerServiceClient,
 };
 
 const SERVER_STARTUP_TIMEOUT_MS: u64 = 3000;
 const SERVER_STARTUP_RETRY_INTERVAL_MS: u64 = 10;
+/// Longest an idle handler may take to answer a health check.
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+/// Longest digest accepted, it names files and folders on the worker.
+const MAX_DIGEST_LEN: usize = 128;
+
+/// How close to its deadline a dropped invocation counts as timed out rather than cancelled.
+const CALLER_DEADLINE_SLACK: Duration = Duration::from_millis(50);
 
 pub struct Config {
     pub is_dev: bool,
+    /// Timeout of functions that don't configure their own.
+    pub invoke_timeout: Duration,
+}
+
+/// The handler could not be reached, so it never saw the request and it is safe to
+/// send it again.
+#[derive(Debug)]
+struct Undelivered;
+
+impl fmt::Display for Undelivered {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Function handler unreachable")
+    }
 }
 
+/// Runs invocations without a global lock: only container creation for the same
+/// function version is serialized, so concurrent cold starts of it share a container.
 pub struct NativeWorker {
     function_invocations: Arc<FunctionInvocations>,
+    cold_starts: KeyedLock,
+    logs: Arc<LogStore>,
     registry_service: RegistryClient,
     root_path: PathBuf,
     sysuser: SysUserParms,
+    invoke_timeout: Duration,
 }
 
 impl NativeWorker {
     pub fn new(
         function_invocations: &Arc<FunctionInvocations>,
+        logs: &Arc<LogStore>,
         registry_service: RegistryClient,
         root_path: PathBuf,
         syscall: &dyn Syscall,
@@ -47,49 +82,198 @@ impl NativeWorker {
         info!(is_dev = server_config.is_dev, "Creating NativeWorker");
         Ok(Self {
             function_invocations: function_invocations.clone(),
+            cold_starts: KeyedLock::new(),
+            logs: logs.clone(),
             registry_service,
             root_path,
             sysuser: SysUserParms {
                 uid: syscall.get_euid().as_raw(),
                 gid: syscall.get_egid().as_raw(),
             },
+            invoke_timeout: server_config.invoke_timeout,
         })
     }
 }
 
 impl NativeWorker {
-    #[instrument(name = "function_execute", level = "debug", skip(self, body), fields(digest = %digest, body_size = body.len()))]
+    /// Runs one invocation, giving up at the caller's deadline or after the function's
+    /// timeout, the worker default when it sets none.
+    ///
+    /// The call runs on its own task so a timeout still replaces the instance when
+    /// the caller's deadline drops this future first. An instance that can't be reached
+    /// is evicted and the request retried once on another one.
+    ///
+    /// The response carries a new invocation id, tagging what the function logged
+    /// while handling it.
+    #[instrument(name = "function_execute", level = "debug", skip(self, body, config), fields(function = %key, body_size = body.len()))]
     pub async fn execute(
-        &mut self,
-        digest: String,
+        self: &Arc<Self>,
+        key: PoolKey,
         body: Vec<u8>,
         metadata: HashMap<String, String>,
+        config: FunctionConfig,
+        caller_deadline: Option<Instant>,
     ) -> Result<ExecuteResponse> {
         debug!("Executing function");
+        validate_digest(&key.digest)?;
 
-        let uri = self.get_available_handler_uri(digest.clone()).await?;
+        let lease = self.acquire_instance(&key, &config).await?;
+        let invocation_id = Uuid::new_v4().to_string();
+        let timeout = match config.timeout_ms {
+            0 => self.invoke_timeout,
+            ms => Duration::from_millis(ms.into()),
+        };
+        // The caller can only shorten the function's own timeout, never extend it.
+        let function_deadline = Instant::now() + timeout;
+        let deadline =
+            caller_deadline.map_or(function_deadline, |caller| caller.min(function_deadline));
 
-        debug!(uri = %uri, "Connecting to function handler");
-        let mut client = FunctionRunnerServiceClient::connect(uri.to_string())
+        let cancel = CancellationToken::new();
+        let _cancel_on_drop = cancel.clone().drop_guard();
+        // Lets handler SDKs continue the trace, whatever the caller sent under these keys.
+        let mut metadata = metadata;
+        telemetry::inject(&mut metadata);
+        let request = InvokeRequest {
+            payload: body,
+            metadata,
+        };
+        let worker = self.clone();
+        let id = invocation_id.clone();
+        let mut response = tokio::spawn(
+            async move {
+                worker
+                    .invoke_with_retry(key, lease, id, request, config, deadline, cancel)
+                    .await
+            }
+            .in_current_span(),
+        )
+        .await??;
+        response.invocation_id = invocation_id;
+        Ok(response)
+    }
+
+    /// Tags the handler's output with `invocation_id` while it runs.
+    #[allow(clippy::too_many_arguments)]
+    async fn invoke_with_retry(
+        self: Arc<Self>,
+        key: PoolKey,
+        lease: Lease,
+        invocation_id: String,
+        request: InvokeRequest,
+        config: FunctionConfig,
+        deadline: Instant,
+        cancel: CancellationToken,
+    ) -> Result<ExecuteResponse> {
+        let logs = self
+            .logs
+            .begin_invocation(&lease.instance_id, &invocation_id);
+        let result = self
+            .clone()
+            .invoke(
+                key.clone(),
+                lease,
+                request.clone(),
+                config.clone(),
+                deadline,
+                cancel.clone(),
+            )
+            .await;
+        drop(logs);
+
+        let err = match result {
+            Err(err) if err.is::<Undelivered>() => err,
+            result => return result,
+        };
+        warn!(function = %key, error = %err, "Retrying invocation on another instance");
+        let lease = timeout_at(deadline, self.acquire_instance(&key, &config))
             .await
-            .map_err(|e| {
-                warn!(digest = %digest, error = %e, "Failed to connect to function handler");
-                e
-            })?;
-
-        let resp = client
-            .invoke(Request::new(InvokeRequest {
-                payload: body,
-                metadata,
-            }))
+            .map_err(|_| anyhow::anyhow!("No instance to retry on before the deadline"))??;
+        let _logs = self
+            .logs
+            .begin_invocation(&lease.instance_id, &invocation_id);
+        self.invoke(key, lease, request, config, deadline, cancel)
             .await
-            .map_err(|e| {
-                warn!(digest = %digest, error = %e, "Function invocation failed");
-                e
-            })?
-            .into_inner();
+    }
+
+    async fn invoke(
+        self: Arc<Self>,
+        key: PoolKey,
+        lease: Lease,
+        request: InvokeRequest,
+        config: FunctionConfig,
+        deadline: Instant,
+        cancel: CancellationToken,
+    ) -> Result<ExecuteResponse> {
+        let uri = lease.url.to_string();
+        let timeout = deadline.saturating_duration_since(Instant::now());
+
+        // Read up front, a failed invocation was only OOM killed when the count went up.
+        let oom_kills = container::ProccesContainer::oom_kills(&self.root_path, &lease.instance_id)
+            .inspect_err(|err| debug!(error = ?err, "Failed to read OOM kills of instance"))
+            .ok();
 
-        debug!(digest = %digest, "Function execution completed");
+        let mut request = Request::new(request);
+        // Lets the handler give up by itself before it gets killed.
+        request.set_timeout(timeout);
+
+        let call = async {
+            debug!(uri = %uri, "Connecting to function handler");
+            let mut client = telemetry::connect(uri.clone())
+                .await
+                .map(FunctionRunnerServiceClient::new)
+                .map_err(|e| {
+                    warn!(function = %key, error = %e, "Failed to connect to function handler");
+                    anyhow::Error::new(e).context(Undelivered)
+                })?;
+            Ok(client.invoke(request).await)
+        };
+
+        // Dropping `call` resets the stream, which is how the handler learns about
+        // timeouts and cancellations.
+        let resp = tokio::select! {
+            resp = call => match resp {
+                std::result::Result::Ok(Err(status)) if status.code() == Code::DeadlineExceeded => None,
+                std::result::Result::Ok(std::result::Result::Ok(resp)) => Some(resp.into_inner()),
+                std::result::Result::Ok(Err(status)) => {
+                    return self.invocation_failed(key, lease, config, oom_kills, status.into());
+                }
+                Err(e) if e.is::<Undelivered>() => {
+                    let instance_id = lease.instance_id.clone();
+                    drop(lease);
+                    if let Err(err) = self
+                        .function_invocations
+                        .evict(&key, &instance_id)
+                        .await
+                    {
+                        warn!(instance_id = %instance_id, error = ?err, "Failed to stop unreachable instance");
+                    }
+                    return Err(e);
+                }
+                Err(e) => return self.invocation_failed(key, lease, config, oom_kills, e),
+            },
+            _ = sleep_until(deadline) => None,
+            _ = cancel.cancelled() => {
+                if deadline.saturating_duration_since(Instant::now()) > CALLER_DEADLINE_SLACK {
+                    debug!(function = %key, "Invocation cancelled by the caller");
+                    bail!("Invocation cancelled by the caller");
+                }
+                // The caller's deadline is handled by tonic, which dropped the request
+                // just before our own timer would have fired.
+                None
+            }
+        };
+
+        let Some(resp) = resp else {
+            warn!(instance_id = %lease.instance_id, ?timeout, "Function timed out, replacing instance");
+            let problem = problem::timeout(&lease.instance_id, timeout);
+            self.replace_instance(key, lease, config);
+            return Ok(ExecuteResponse {
+                outcome: Some(Outcome::Problem(problem)),
+                ..Default::default()
+            });
+        };
+
+        debug!(function = %key, "Function execution completed");
         if let Some(r) = resp.result {
             let outcome = match r {
                 proto::api::action::invoke_result::Result::Success(e) => {
@@ -106,6 +290,7 @@ impl NativeWorker {
             };
             return Ok(proto::api::worker::ExecuteResponse {
                 outcome: Some(outcome),
+                ..Default::default()
             });
         }
 
@@ -116,43 +301,303 @@ impl NativeWorker {
                 instance: "".to_string(),
                 extensions: HashMap::new(),
             })),
+            ..Default::default()
         })
     }
 
-    async fn get_available_handler_uri(&mut self, digest: String) -> Result<Url> {
-        // TODO: This is a workaround i don't like as there could be a very low way that this
-        // fails.
-        let short_digest = &digest[..16];
+    /// Reports a handler killed for going over its memory limit as such, which shows up
+    /// here only as a dropped connection.
+    fn invocation_failed(
+        self: &Arc<Self>,
+        key: PoolKey,
+        lease: Lease,
+        config: FunctionConfig,
+        oom_kills: Option<container::OomKills>,
+        err: anyhow::Error,
+    ) -> Result<ExecuteResponse> {
+        match oom_kills.map(|oom_kills| oom_kills.increased()) {
+            Some(std::result::Result::Ok(true)) => {
+                warn!(instance_id = %lease.instance_id, error = %err, "Function ran out of memory, replacing instance");
+                let memory_mb = config.limits.unwrap_or_default().memory_mb;
+                let problem = problem::out_of_memory(&lease.instance_id, memory_mb);
+                self.replace_instance(key, lease, config);
+                Ok(ExecuteResponse {
+                    outcome: Some(Outcome::Problem(problem)),
+                    ..Default::default()
+                })
+            }
+            result => {
+                if let Some(Err(oom_err)) = result {
+                    debug!(error = ?oom_err, "Failed to read OOM kills of instance");
+                }
+                warn!(function = %key, error = %err, "Function invocation failed");
+                Err(err)
+            }
+        }
+    }
 
-        let url = if let Some(invocation) = self.function_invocations.get(short_digest).await {
-            info!("Loading existing function");
-            {
-                let inv = invocation.lock().await;
-                inv.url.clone()
+    /// Kills an instance that may still be stuck in a handler or is already dead and
+    /// starts a fresh one in its place, in the background so the failure is reported
+    /// right away.
+    fn replace_instance(self: &Arc<Self>, key: PoolKey, lease: Lease, config: FunctionConfig) {
+        let instance_id = lease.instance_id.clone();
+        drop(lease);
+
+        let worker = self.clone();
+        tokio::spawn(async move {
+            if let Err(err) = worker.function_invocations.evict(&key, &instance_id).await {
+                warn!(instance_id = %instance_id, error = ?err, "Failed to stop replaced instance");
             }
-        } else {
-            info!("Creating new function");
-            let dir_path = self.registry_service.get_tar_by_digest(&digest).await?;
-
-            let proc = Arc::new(
-                container::ProccesContainer::new(
-                    short_digest,
-                    dir_path,
-                    self.root_path.clone(),
-                    &self.sysuser,
-                )
-                .await?,
-            );
-
-            let url = proc.get_url()?;
-            self.function_invocations
-                .insert(short_digest.to_string(), url.clone())
+
+            let _cold_start = worker.cold_starts.lock(&key.to_string()).await;
+            let pool = worker
+                .function_invocations
+                .pool(&key, &fingerprint(&config))
                 .await;
-            url
+            if pool.len() < worker.function_invocations.config().max_instances
+                && let Err(err) = worker.start_instance(&key, &config).await
+            {
+                warn!(function = %key, error = ?err, "Failed to start replacement instance");
+            }
+        });
+    }
+
+    /// Starts instances of `key` until `count` are running, capped at `max_instances`,
+    /// and returns how many run afterwards.
+    #[instrument(skip(self, config), fields(function = %key))]
+    pub async fn prewarm(
+        &self,
+        key: &PoolKey,
+        count: usize,
+        config: &FunctionConfig,
+    ) -> Result<usize> {
+        validate_digest(&key.digest)?;
+        let fingerprint = fingerprint(config);
+        let target = count.min(self.function_invocations.config().max_instances);
+
+        let _cold_start = self.cold_starts.lock(&key.to_string()).await;
+        loop {
+            // Fetched every round, scaling down drops pools that were empty.
+            let running = self
+                .function_invocations
+                .pool(key, &fingerprint)
+                .await
+                .len();
+            if running >= target {
+                info!(running, "Function prewarmed");
+                return Ok(running);
+            }
+            // Dropping the lease right away leaves the instance idle and warm.
+            drop(self.start_instance(key, config).await?);
+        }
+    }
+
+    /// Takes a slot on a warm instance, starting a new one while the pool is below
+    /// `max_instances` and waiting for a free slot once it is full.
+    async fn acquire_instance(&self, key: &PoolKey, config: &FunctionConfig) -> Result<Lease> {
+        let pool_config = self.function_invocations.config();
+        let fingerprint = fingerprint(config);
+
+        loop {
+            let pool = self.function_invocations.pool(key, &fingerprint).await;
+            let released = pool.released();
+            tokio::pin!(released);
+            released.as_mut().enable();
+
+            if let Some(lease) = pool.try_acquire(pool_config.max_concurrency) {
+                debug!(instance_id = %lease.instance_id, "Using warm instance");
+                metrics::start(Start::Warm);
+                return Ok(lease);
+            }
+
+            if pool.len() < pool_config.max_instances {
+                let _cold_start = self.cold_starts.lock(&key.to_string()).await;
+
+                // Whoever held the lock before us may have scaled up already.
+                if let Some(lease) = pool.try_acquire(pool_config.max_concurrency) {
+                    metrics::start(Start::Warm);
+                    return Ok(lease);
+                }
+                if pool.len() < pool_config.max_instances {
+                    metrics::start(Start::Cold);
+                    return self.start_instance(key, config).await;
+                }
+                continue;
+            }
+
+            debug!(function = %key, "Pool is at capacity, waiting for a free slot");
+            released.await;
+        }
+    }
+
+    async fn start_instance(&self, key: &PoolKey, config: &FunctionConfig) -> Result<Lease> {
+        // Unrelated to the digest, so any number of instances of it can run side by side.
+        let instance_id = Uuid::new_v4().simple().to_string();
+        info!(instance_id = %instance_id, "Creating new function instance");
+        let dir_path = self.registry_service.get_tar_by_digest(&key.digest).await?;
+
+        let mut proc = container::ProccesContainer::new(
+            &instance_id,
+            key,
+            dir_path,
+            self.root_path.clone(),
+            &self.sysuser,
+            config,
+        )
+        .await?;
+
+        let url = proc.get_url()?;
+        // Captured before the handler is ready, so failed startups show up in the logs.
+        let ready = async {
+            self.capture_output(&key.name, &instance_id, &proc)?;
+            self.wait_for_server_ready(&url).await
+        };
+        if let Err(e) = ready.await {
+            // Not registered yet, so nothing else would ever clean it up.
+            if let Err(cleanup) = proc.cleanup().await {
+                warn!(error = %cleanup, "Failed to clean up container that never became ready");
+            }
+            return Err(e);
+        }
+
+        Ok(self
+            .function_invocations
+            .insert(key, &fingerprint(config), instance_id, url)
+            .await)
+    }
+
+    fn capture_output(
+        &self,
+        function: &str,
+        instance_id: &str,
+        proc: &container::ProccesContainer,
+    ) -> Result<()> {
+        for stream in [LogStream::Stdout, LogStream::Stderr] {
+            self.logs
+                .capture(function, instance_id, stream, &proc.output_path(stream))?;
+        }
+        Ok(())
+    }
+
+    /// Adopts the healthy instances a previous worker left running and removes the
+    /// rest, whose folders would otherwise keep new instances from starting.
+    #[instrument(skip(self))]
+    pub async fn reconcile(&self) -> Result<()> {
+        let (mut adopted, mut removed, mut failed) = (0, 0, 0);
+        let instance_ids = container::ProccesContainer::instance_ids(&self.root_path).await?;
+        // Probed all at once, each unresponsive one waits out the whole startup timeout.
+        let recovered = join_all(instance_ids.iter().map(|id| self.recover_instance(id))).await;
+        for (instance_id, result) in instance_ids.iter().zip(recovered) {
+            match result {
+                std::result::Result::Ok(true) => adopted += 1,
+                std::result::Result::Ok(false) => removed += 1,
+                Err(err) => {
+                    warn!(instance_id = %instance_id, error = ?err, "Failed to reconcile instance");
+                    failed += 1;
+                }
+            }
+        }
+        info!(
+            adopted,
+            removed, failed, "Reconciled instances of the previous worker"
+        );
+        Ok(())
+    }
+
+    /// Returns whether the instance was adopted, it is removed otherwise.
+    async fn recover_instance(&self, instance_id: &str) -> Result<bool> {
+        let Some(mut proc) =
+            container::ProccesContainer::recover(&self.root_path, instance_id).await?
+        else {
+            debug!(instance_id, "Removed stopped instance");
+            return Ok(false);
         };
 
-        self.wait_for_server_ready(&url).await?;
-        Ok(url)
+        let url = proc.get_url()?;
+        let function = proc
+            .function()
+            .await
+            .and_then(|function| validate_digest(&function.0.digest).map(|_| function));
+        let adopted = match function {
+            std::result::Result::Ok((key, fingerprint))
+                if self.wait_for_server_ready(&url).await.is_ok() =>
+            {
+                self.capture_output(&key.name, instance_id, &proc)?;
+                self.function_invocations
+                    .adopt(&key, &fingerprint, instance_id.to_string(), url)
+                    .await;
+                true
+            }
+            _ => false,
+        };
+        if !adopted {
+            debug!(instance_id, "Removing unresponsive instance");
+            proc.cleanup().await?;
+        }
+        Ok(adopted)
+    }
+
+    /// Evicts instances whose container stopped, and idle ones whose handler fails its
+    /// health check. Busy instances are only checked for their container, a handler
+    /// working on a long invocation may be slow to answer.
+    #[instrument(skip(self))]
+    pub async fn check_health(&self) -> Result<()> {
+        let instances = self.function_invocations.instances().await;
+        let probes = join_all(instances.iter().map(|instance| self.probe(instance))).await;
+
+        let mut evicted = 0;
+        for (instance, probe) in instances.iter().zip(probes) {
+            let Err(err) = probe else {
+                continue;
+            };
+
+            // Seen idle before probing, it may have taken an invocation since.
+            let result = match instance.idle {
+                true => {
+                    self.function_invocations
+                        .evict_idle(&instance.key, &instance.instance_id)
+                        .await
+                }
+                false => self
+                    .function_invocations
+                    .evict(&instance.key, &instance.instance_id)
+                    .await
+                    .map(|_| true),
+            };
+            match result {
+                std::result::Result::Ok(false) => {
+                    debug!(instance_id = %instance.instance_id, error = %err, "Keeping unhealthy instance that became busy");
+                    continue;
+                }
+                std::result::Result::Ok(true) => {
+                    warn!(instance_id = %instance.instance_id, error = %err, "Evicted unhealthy instance")
+                }
+                Err(stop_err) => {
+                    warn!(instance_id = %instance.instance_id, error = %err, stop_error = ?stop_err, "Evicted unhealthy instance but failed to stop it")
+                }
+            }
+            evicted += 1;
+        }
+
+        metrics::reaped(Reaped::Unhealthy, evicted);
+        if evicted > 0 {
+            info!(evicted, "Unhealthy instances evicted");
+        }
+        Ok(())
+    }
+
+    async fn probe(&self, instance: &InstanceState) -> Result<()> {
+        let status = container::ProccesContainer::status(&self.root_path, &instance.instance_id)?;
+        if status != ContainerStatus::Running {
+            bail!("Container is {}", status);
+        }
+        if !instance.idle {
+            return Ok(());
+        }
+
+        timeout(HEALTH_CHECK_TIMEOUT, check_handler(instance.url.as_str()))
+            .await
+            .map_err(|_| anyhow::anyhow!("Health check timed out"))?
     }
 
     async fn wait_for_server_ready(&self, url: &Url) -> Result<()> {
@@ -163,7 +608,7 @@ impl NativeWorker {
         debug!(max_attempts, uri, "Waiting for server to be ready");
 
         for attempt in 1..=max_attempts {
-            match FunctionRunnerServiceClient::connect(uri.clone()).await {
+            match check_handler(&uri).await {
                 std::result::Result::Ok(_) => {
                     debug!(attempts = attempt, "Server is ready");
                     return Ok(());
@@ -189,3 +634,123 @@ impl NativeWorker {
         unreachable!("Loop should have returned")
     }
 }
+
+/// Asks the handler whether it can serve. Handlers without the health RPC pass as soon
+/// as they answer at all.
+async fn check_handler(uri: &str) -> Result<()> {
+    let mut client = FunctionRunnerServiceClient::new(telemetry::connect(uri.to_string()).await?);
+    match client.health(HealthRequest {}).await {
+        std::result::Result::Ok(resp) if resp.get_ref().healthy => Ok(()),
+        std::result::Result::Ok(_) => bail!("Handler reported itself unhealthy"),
+        Err(status) if status.code() == Code::Unimplemented => Ok(()),
+        Err(status) => Err(status.into()),
+    }
+}
+
+/// Checks a digest from outside the worker before it is used. Any format works, bare hex
+/// or `algorithm:hex` alike, as long as it is a plain file name.
+pub fn validate_digest(digest: &str) -> Result<()> {
+    let valid = !digest.is_empty()
+        && digest.len() <= MAX_DIGEST_LEN
+        && !digest.starts_with('.')
+        && digest
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, ':' | '.' | '_' | '-'));
+    if !valid {
+        bail!("Invalid digest: {:?}", digest);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proto::api::action::{
+        HealthResponse, InvokeResult,
+        function_runner_service_server::{FunctionRunnerService, FunctionRunnerServiceServer},
+    };
+    use tempfile::TempDir;
+    use tokio::net::UnixListener;
+    use tokio_stream::wrappers::UnixListenerStream;
+    use tonic::{Response, Status};
+
+    /// Handler answering health checks with the given state, or without the RPC.
+    struct Handler(Option<bool>);
+
+    #[tonic::async_trait]
+    impl FunctionRunnerService for Handler {
+        async fn invoke(
+            &self,
+            _request: Request<InvokeRequest>,
+        ) -> std::result::Result<Response<InvokeResult>, Status> {
+            Err(Status::unimplemented("invoke"))
+        }
+
+        async fn health(
+            &self,
+            _request: Request<HealthRequest>,
+        ) -> std::result::Result<Response<HealthResponse>, Status> {
+            match self.0 {
+                Some(healthy) => std::result::Result::Ok(Response::new(HealthResponse { healthy })),
+                None => Err(Status::unimplemented("health")),
+            }
+        }
+    }
+
+    fn serve(temp: &TempDir, handler: Handler) -> String {
+        let path = temp.path().join("app.sock");
+        let incoming = UnixListenerStream::new(UnixListener::bind(&path).unwrap());
+        tokio::spawn(
+            tonic::transport::Server::builder()
+                .add_service(FunctionRunnerServiceServer::new(handler))
+                .serve_with_incoming(incoming),
+        );
+        format!("unix://{}", path.display())
+    }
+
+    #[tokio::test]
+    async fn test_check_handler() {
+        let temp = TempDir::new().unwrap();
+        check_handler(&serve(&temp, Handler(Some(true))))
+            .await
+            .unwrap();
+
+        let temp = TempDir::new().unwrap();
+        let err = check_handler(&serve(&temp, Handler(Some(false))))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("unhealthy"));
+
+        let temp = TempDir::new().unwrap();
+        check_handler(&serve(&temp, Handler(None))).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_check_handler_without_server() {
+        let temp = TempDir::new().unwrap();
+        let uri = format!("unix://{}", temp.path().join("app.sock").display());
+        assert!(check_handler(&uri).await.is_err());
+    }
+
+    #[test]
+    fn test_validate_digest() {
+        for digest in [
+            "a3f1",
+            "sha256:9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08",
+            "v1.2_build-3",
+        ] {
+            validate_digest(digest).unwrap();
+        }
+        for digest in [
+            "",
+            "..",
+            "../etc",
+            "a/b",
+            ".hidden",
+            "a b",
+            &"a".repeat(129),
+        ] {
+            assert!(validate_digest(digest).is_err(), "{}", digest);
+        }
+    }
+}
@@ -0,0 +1,31 @@
+use std::{collections::HashMap, time::Duration};
+
+use proto::api::worker::ProblemDetails;
+
+/// The invocation ran past its deadline and the instance was replaced.
+pub const TIMEOUT: &str = "urn:noctiforge:problem:timeout";
+
+/// The handler was OOM killed for going over the function's memory limit.
+pub const OUT_OF_MEMORY: &str = "urn:noctiforge:problem:out-of-memory";
+
+pub fn timeout(instance_id: &str, timeout: Duration) -> ProblemDetails {
+    ProblemDetails {
+        r#type: TIMEOUT.to_string(),
+        detail: format!("function did not respond within {:?}", timeout),
+        instance: format!("urn:noctiforge:instance:{}", instance_id),
+        extensions: HashMap::from([("timeout_ms".to_string(), timeout.as_millis().to_string())]),
+    }
+}
+
+pub fn out_of_memory(instance_id: &str, memory_mb: u64) -> ProblemDetails {
+    let detail = match memory_mb {
+        0 => "function ran out of memory".to_string(),
+        mb => format!("function exceeded its memory limit of {}MB", mb),
+    };
+    ProblemDetails {
+        r#type: OUT_OF_MEMORY.to_string(),
+        detail,
+        instance: format!("urn:noctiforge:instance:{}", instance_id),
+        extensions: HashMap::from([("memory_mb".to_string(), memory_mb.to_string())]),
+    }
+}
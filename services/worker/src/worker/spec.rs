@@ -1,10 +1,293 @@
-use std::path::{Path, PathBuf};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use libcontainer::oci_spec::runtime::{
-    LinuxBuilder, LinuxIdMappingBuilder, LinuxNamespace, LinuxNamespaceBuilder, LinuxNamespaceType,
-    Mount, ProcessBuilder, RootBuilder, Spec,
+    Arch, Capability, Linux, LinuxBlockIoBuilder, LinuxBuilder, LinuxCapabilitiesBuilder,
+    LinuxCpuBuilder, LinuxIdMappingBuilder, LinuxMemoryBuilder, LinuxNamespace,
+    LinuxNamespaceBuilder, LinuxNamespaceType, LinuxPidsBuilder, LinuxSeccomp, LinuxSeccompAction,
+    LinuxSeccompArgBuilder, LinuxSeccompBuilder, LinuxSeccompOperator, LinuxSyscall,
+    LinuxSyscallBuilder, Mount, MountBuilder, ProcessBuilder, RootBuilder, Spec,
 };
+use proto::api::controlplane::{FunctionConfig, NetworkMode, ResourceLimits, SecurityConfig};
+
+/// Scheduling period the CPU quota is spread over.
+const CPU_PERIOD_US: u64 = 100_000;
+
+const DEFAULT_PATH: &str = "/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin";
+
+/// Size of `/tmp` when the function doesn't set one.
+const DEFAULT_TMP_SIZE_MB: u32 = 64;
+
+const EPERM: u32 = 1;
+const ENOSYS: u32 = 38;
+
+/// `CLONE_NEWNS | CLONE_NEWCGROUP | CLONE_NEWUTS | CLONE_NEWIPC | CLONE_NEWUSER |
+/// CLONE_NEWPID | CLONE_NEWNET`, `clone` is only allowed without any of them.
+const CLONE_NAMESPACE_FLAGS: u64 = 0x7e02_0000;
+
+/// Host files hidden from the instance.
+const MASKED_PATHS: &[&str] = &[
+    "/proc/acpi",
+    "/proc/asound",
+    "/proc/interrupts",
+    "/proc/kcore",
+    "/proc/keys",
+    "/proc/latency_stats",
+    "/proc/sched_debug",
+    "/proc/scsi",
+    "/proc/timer_list",
+    "/proc/timer_stats",
+    "/sys/devices/virtual/powercap",
+    "/sys/firmware",
+];
+
+const READONLY_PATHS: &[&str] = &[
+    "/proc/bus",
+    "/proc/fs",
+    "/proc/irq",
+    "/proc/sys",
+    "/proc/sysrq-trigger",
+];
+
+/// Syscalls allowed by the default seccomp profile, everything else fails with `EPERM`.
+///
+/// Enough for the usual language runtimes, it leaves out anything that mounts, loads
+/// code into the kernel, changes namespaces, traces other processes or needs a
+/// capability the instance doesn't have anyway.
+const SECCOMP_ALLOWLIST: &[&str] = &[
+    "accept",
+    "accept4",
+    "access",
+    "alarm",
+    "arch_prctl",
+    "bind",
+    "brk",
+    "capget",
+    "capset",
+    "chdir",
+    "chmod",
+    "chown",
+    "clock_getres",
+    "clock_gettime",
+    "clock_nanosleep",
+    "close",
+    "close_range",
+    "connect",
+    "copy_file_range",
+    "creat",
+    "dup",
+    "dup2",
+    "dup3",
+    "epoll_create",
+    "epoll_create1",
+    "epoll_ctl",
+    "epoll_pwait",
+    "epoll_pwait2",
+    "epoll_wait",
+    "eventfd",
+    "eventfd2",
+    "execve",
+    "execveat",
+    "exit",
+    "exit_group",
+    "faccessat",
+    "faccessat2",
+    "fadvise64",
+    "fallocate",
+    "fchdir",
+    "fchmod",
+    "fchmodat",
+    "fchown",
+    "fchownat",
+    "fcntl",
+    "fdatasync",
+    "fgetxattr",
+    "flistxattr",
+    "flock",
+    "fork",
+    "fstat",
+    "fstatfs",
+    "fsync",
+    "ftruncate",
+    "futex",
+    "futex_waitv",
+    "getcpu",
+    "getcwd",
+    "getdents",
+    "getdents64",
+    "getegid",
+    "geteuid",
+    "getgid",
+    "getgroups",
+    "getitimer",
+    "getpeername",
+    "getpgid",
+    "getpgrp",
+    "getpid",
+    "getppid",
+    "getpriority",
+    "getrandom",
+    "getresgid",
+    "getresuid",
+    "getrlimit",
+    "get_robust_list",
+    "getrusage",
+    "getsid",
+    "getsockname",
+    "getsockopt",
+    "gettid",
+    "gettimeofday",
+    "getuid",
+    "getxattr",
+    "inotify_add_watch",
+    "inotify_init",
+    "inotify_init1",
+    "inotify_rm_watch",
+    "io_cancel",
+    "io_destroy",
+    "io_getevents",
+    "io_setup",
+    "io_submit",
+    "ioctl",
+    "kill",
+    "lchown",
+    "lgetxattr",
+    "link",
+    "linkat",
+    "listen",
+    "listxattr",
+    "llistxattr",
+    "lseek",
+    "lstat",
+    "madvise",
+    "membarrier",
+    "memfd_create",
+    "mincore",
+    "mkdir",
+    "mkdirat",
+    "mlock",
+    "mlock2",
+    "mmap",
+    "mprotect",
+    "mremap",
+    "msync",
+    "munlock",
+    "munmap",
+    "nanosleep",
+    "newfstatat",
+    "open",
+    "openat",
+    "openat2",
+    "pause",
+    "pipe",
+    "pipe2",
+    "poll",
+    "ppoll",
+    "prctl",
+    "pread64",
+    "preadv",
+    "preadv2",
+    "prlimit64",
+    "pselect6",
+    "pwrite64",
+    "pwritev",
+    "pwritev2",
+    "read",
+    "readahead",
+    "readlink",
+    "readlinkat",
+    "readv",
+    "recvfrom",
+    "recvmmsg",
+    "recvmsg",
+    "rename",
+    "renameat",
+    "renameat2",
+    "restart_syscall",
+    "rmdir",
+    "rseq",
+    "rt_sigaction",
+    "rt_sigpending",
+    "rt_sigprocmask",
+    "rt_sigqueueinfo",
+    "rt_sigreturn",
+    "rt_sigsuspend",
+    "rt_sigtimedwait",
+    "rt_tgsigqueueinfo",
+    "sched_getaffinity",
+    "sched_getattr",
+    "sched_getparam",
+    "sched_get_priority_max",
+    "sched_get_priority_min",
+    "sched_getscheduler",
+    "sched_setaffinity",
+    "sched_yield",
+    "select",
+    "sendfile",
+    "sendmmsg",
+    "sendmsg",
+    "sendto",
+    "setgid",
+    "setgroups",
+    "setitimer",
+    "setpgid",
+    "setpriority",
+    "setregid",
+    "setresgid",
+    "setresuid",
+    "setreuid",
+    "setrlimit",
+    "set_robust_list",
+    "setsid",
+    "setsockopt",
+    "set_tid_address",
+    "setuid",
+    "shutdown",
+    "sigaltstack",
+    "signalfd",
+    "signalfd4",
+    "socket",
+    "socketpair",
+    "splice",
+    "stat",
+    "statfs",
+    "statx",
+    "symlink",
+    "symlinkat",
+    "sync",
+    "sync_file_range",
+    "syncfs",
+    "sysinfo",
+    "tee",
+    "tgkill",
+    "time",
+    "timer_create",
+    "timer_delete",
+    "timer_getoverrun",
+    "timer_gettime",
+    "timer_settime",
+    "timerfd_create",
+    "timerfd_gettime",
+    "timerfd_settime",
+    "tkill",
+    "truncate",
+    "umask",
+    "uname",
+    "unlink",
+    "unlinkat",
+    "utime",
+    "utimensat",
+    "utimes",
+    "vfork",
+    "wait4",
+    "waitid",
+    "write",
+    "writev",
+];
 
 #[derive(Clone)]
 pub struct SysUserParms {
@@ -12,11 +295,28 @@ pub struct SysUserParms {
     pub gid: u32,
 }
 
-pub fn get_spec(sys_user: &SysUserParms) -> Result<Spec> {
-    let namespaces = build_rootless_namespaces()?;
-    let linux = build_linux_config(sys_user, namespaces)?;
-    let mounts = build_rootless_mounts();
-    let process = build_process()?;
+/// Host directories mounted into an instance.
+pub struct InstancePaths {
+    /// Extracted function bundle, shared read-only by every instance of the digest.
+    pub app: PathBuf,
+    /// Per instance directory backing `/run`, where the handler creates its socket.
+    pub run: PathBuf,
+}
+
+pub fn get_spec(
+    sys_user: &SysUserParms,
+    config: &FunctionConfig,
+    paths: &InstancePaths,
+) -> Result<Spec> {
+    let network = config.network.clone().unwrap_or_default();
+    let namespaces = build_rootless_namespaces(network.mode())?;
+    let security = config.security.clone().unwrap_or_default();
+    let mut linux = build_linux_config(sys_user, namespaces)?;
+    apply_resource_limits(&mut linux, &config.limits.unwrap_or_default())?;
+    apply_linux_hardening(&mut linux, &security)?;
+    let mut mounts = build_rootless_mounts();
+    mounts.extend(build_instance_mounts(paths, config.tmp_size_mb)?);
+    let process = build_process(&security, &config.env)?;
     let root = build_root()?;
 
     let mut spec = Spec::default();
@@ -28,24 +328,27 @@ pub fn get_spec(sys_user: &SysUserParms) -> Result<Spec> {
     Ok(spec)
 }
 
-fn build_rootless_namespaces() -> Result<Vec<LinuxNamespace>> {
-    let mut namespaces = filter_default_namespaces();
+fn build_rootless_namespaces(network: NetworkMode) -> Result<Vec<LinuxNamespace>> {
+    let mut namespaces = filter_default_namespaces(network);
     namespaces.push(create_user_namespace()?);
     Ok(namespaces)
 }
 
-fn filter_default_namespaces() -> Vec<LinuxNamespace> {
+fn filter_default_namespaces(network: NetworkMode) -> Vec<LinuxNamespace> {
     libcontainer::oci_spec::runtime::get_default_namespaces()
         .into_iter()
-        .filter(|ns| !is_excluded_namespace(ns))
+        .filter(|ns| !is_excluded_namespace(ns, network))
         .collect()
 }
 
-fn is_excluded_namespace(ns: &LinuxNamespace) -> bool {
-    matches!(
-        ns.typ(),
-        LinuxNamespaceType::Network | LinuxNamespaceType::User
-    )
+/// The host network mode shares the worker's network stack, every other mode gets
+/// its own namespace that `network::attach` sets up once the container runs.
+fn is_excluded_namespace(ns: &LinuxNamespace, network: NetworkMode) -> bool {
+    match ns.typ() {
+        LinuxNamespaceType::User => true,
+        LinuxNamespaceType::Network => network == NetworkMode::Host,
+        _ => false,
+    }
 }
 
 fn create_user_namespace() -> Result<LinuxNamespace> {
@@ -55,10 +358,7 @@ fn create_user_namespace() -> Result<LinuxNamespace> {
         .map_err(Into::into)
 }
 
-fn build_linux_config(
-    sys_user: &SysUserParms,
-    namespaces: Vec<LinuxNamespace>,
-) -> Result<libcontainer::oci_spec::runtime::Linux> {
+fn build_linux_config(sys_user: &SysUserParms, namespaces: Vec<LinuxNamespace>) -> Result<Linux> {
     LinuxBuilder::default()
         .namespaces(namespaces)
         .uid_mappings(vec![create_id_mapping(sys_user.uid)?])
@@ -67,6 +367,147 @@ fn build_linux_config(
         .map_err(Into::into)
 }
 
+/// Adds the function's cgroup limits to the default resources, unset ones stay unlimited.
+fn apply_resource_limits(linux: &mut Linux, limits: &ResourceLimits) -> Result<()> {
+    let mut resources = linux.resources().clone().unwrap_or_default();
+    if limits.memory_mb > 0 {
+        let bytes = limits
+            .memory_mb
+            .checked_mul(1024 * 1024)
+            .and_then(|bytes| i64::try_from(bytes).ok())
+            .with_context(|| format!("memory limit too large: {} MB", limits.memory_mb))?;
+        resources.set_memory(Some(
+            LinuxMemoryBuilder::default()
+                .limit(bytes)
+                // Equal to the limit, so the function can't get around it by swapping.
+                .swap(bytes)
+                .build()?,
+        ));
+    }
+    if limits.cpu_millis > 0 || limits.cpu_shares > 0 {
+        let mut cpu = LinuxCpuBuilder::default();
+        if limits.cpu_millis > 0 {
+            cpu = cpu
+                .quota(limits.cpu_millis as i64 * CPU_PERIOD_US as i64 / 1000)
+                .period(CPU_PERIOD_US);
+        }
+        if limits.cpu_shares > 0 {
+            cpu = cpu.shares(limits.cpu_shares);
+        }
+        resources.set_cpu(Some(cpu.build()?));
+    }
+    if limits.pids_limit > 0 {
+        resources.set_pids(Some(
+            LinuxPidsBuilder::default()
+                .limit(limits.pids_limit as i64)
+                .build()?,
+        ));
+    }
+    if limits.io_weight > 0 {
+        resources.set_block_io(Some(
+            LinuxBlockIoBuilder::default()
+                .weight(limits.io_weight as u16)
+                .build()?,
+        ));
+    }
+
+    linux.set_resources(Some(resources));
+    Ok(())
+}
+
+/// Masks host paths under `/proc` and filters syscalls, unless the function opted out.
+fn apply_linux_hardening(linux: &mut Linux, security: &SecurityConfig) -> Result<()> {
+    if security.unmask_proc {
+        linux.set_masked_paths(Some(vec![]));
+        linux.set_readonly_paths(Some(vec![]));
+    } else {
+        linux.set_masked_paths(Some(MASKED_PATHS.iter().map(|p| p.to_string()).collect()));
+        linux.set_readonly_paths(Some(READONLY_PATHS.iter().map(|p| p.to_string()).collect()));
+    }
+
+    if !security.seccomp_unconfined {
+        linux.set_seccomp(Some(build_seccomp(&security.extra_syscalls)?));
+    }
+    Ok(())
+}
+
+fn build_seccomp(extra_syscalls: &[String]) -> Result<LinuxSeccomp> {
+    let allowed = SECCOMP_ALLOWLIST
+        .iter()
+        .map(|name| name.to_string())
+        .chain(
+            extra_syscalls
+                .iter()
+                .filter(|name| !SECCOMP_ALLOWLIST.contains(&name.as_str()))
+                .cloned(),
+        )
+        .collect::<Vec<_>>();
+
+    let mut syscalls = vec![allow(allowed)?];
+    if !extra_syscalls.iter().any(|name| name == "clone") {
+        // Threads and processes, but no new namespaces.
+        syscalls.push(
+            LinuxSyscallBuilder::default()
+                .names(vec!["clone".to_string()])
+                .action(LinuxSeccompAction::ScmpActAllow)
+                .args(vec![
+                    LinuxSeccompArgBuilder::default()
+                        .index(0_usize)
+                        .value(0_u64)
+                        .value_two(CLONE_NAMESPACE_FLAGS)
+                        .op(LinuxSeccompOperator::ScmpCmpMaskedEq)
+                        .build()?,
+                ])
+                .build()?,
+        );
+    }
+    if !extra_syscalls.iter().any(|name| name == "clone3") {
+        // Its flags live in memory seccomp can't look at, `ENOSYS` makes libcs fall
+        // back to the filtered `clone`.
+        syscalls.push(
+            LinuxSyscallBuilder::default()
+                .names(vec!["clone3".to_string()])
+                .action(LinuxSeccompAction::ScmpActErrno)
+                .errno_ret(ENOSYS)
+                .build()?,
+        );
+    }
+
+    LinuxSeccompBuilder::default()
+        .default_action(LinuxSeccompAction::ScmpActErrno)
+        .default_errno_ret(EPERM)
+        .architectures(vec![
+            Arch::ScmpArchX86_64,
+            Arch::ScmpArchX86,
+            Arch::ScmpArchX32,
+            Arch::ScmpArchAarch64,
+            Arch::ScmpArchArm,
+        ])
+        .syscalls(syscalls)
+        .build()
+        .map_err(Into::into)
+}
+
+fn allow(names: Vec<String>) -> Result<LinuxSyscall> {
+    LinuxSyscallBuilder::default()
+        .names(names)
+        .action(LinuxSeccompAction::ScmpActAllow)
+        .build()
+        .map_err(Into::into)
+}
+
+/// Parses capability names like `CAP_NET_BIND_SERVICE`.
+fn parse_capabilities(names: &[String]) -> Result<HashSet<Capability>> {
+    names
+        .iter()
+        .map(|name| {
+            let capability = name.strip_prefix("CAP_").unwrap_or(name);
+            Capability::from_str(capability)
+                .with_context(|| format!("Unknown capability: {}", name))
+        })
+        .collect()
+}
+
 fn create_id_mapping(host_id: u32) -> Result<libcontainer::oci_spec::runtime::LinuxIdMapping> {
     LinuxIdMappingBuilder::default()
         .host_id(host_id)
@@ -90,6 +531,45 @@ fn build_rootless_mounts() -> Vec<Mount> {
         .collect()
 }
 
+/// The rootfs is read-only, so these are the only places an instance can write to,
+/// and none of it outlives the instance.
+fn build_instance_mounts(paths: &InstancePaths, tmp_size_mb: u32) -> Result<Vec<Mount>> {
+    let tmp_size_mb = match tmp_size_mb {
+        0 => DEFAULT_TMP_SIZE_MB,
+        size => size,
+    };
+    Ok(vec![
+        bind_mount("/app", &paths.app, &["ro", "nosuid", "nodev"])?,
+        bind_mount("/run", &paths.run, &["rw", "nosuid", "nodev", "noexec"])?,
+        MountBuilder::default()
+            .destination("/tmp")
+            .typ("tmpfs")
+            .source("tmpfs")
+            .options(vec![
+                "nosuid".to_string(),
+                "nodev".to_string(),
+                "mode=1777".to_string(),
+                format!("size={}m", tmp_size_mb),
+            ])
+            .build()?,
+    ])
+}
+
+fn bind_mount(destination: &str, source: &Path, options: &[&str]) -> Result<Mount> {
+    let options = ["rbind"]
+        .iter()
+        .chain(options)
+        .map(|option| option.to_string())
+        .collect::<Vec<_>>();
+    MountBuilder::default()
+        .destination(destination)
+        .typ("bind")
+        .source(source)
+        .options(options)
+        .build()
+        .map_err(Into::into)
+}
+
 fn is_sys_mount(mount: &Mount) -> bool {
     mount.destination().eq(Path::new("/sys"))
 }
@@ -120,16 +600,378 @@ fn filter_mount_options(mount: &mut Mount) {
     mount.set_options(Some(filtered_options));
 }
 
-fn build_process() -> Result<libcontainer::oci_spec::runtime::Process> {
+/// Runs the handler with every capability dropped except the ones the function kept.
+fn build_process(
+    security: &SecurityConfig,
+    env: &HashMap<String, String>,
+) -> Result<libcontainer::oci_spec::runtime::Process> {
+    let capabilities = parse_capabilities(&security.capabilities)?;
     ProcessBuilder::default()
         .args(vec!["/app/bootstrap".to_string()])
+        .env(build_env(env))
+        .no_new_privileges(!security.allow_new_privileges)
+        .capabilities(
+            LinuxCapabilitiesBuilder::default()
+                .bounding(capabilities.clone())
+                .effective(capabilities.clone())
+                .permitted(capabilities.clone())
+                .inheritable(capabilities.clone())
+                .ambient(capabilities)
+                .build()?,
+        )
         .build()
         .map_err(Into::into)
 }
 
+/// The function's variables on top of a default `PATH`, sorted so the spec is stable.
+fn build_env(env: &HashMap<String, String>) -> Vec<String> {
+    let mut vars = BTreeMap::from([("PATH", DEFAULT_PATH)]);
+    vars.extend(
+        env.iter()
+            .map(|(key, value)| (key.as_str(), value.as_str())),
+    );
+    vars.into_iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect()
+}
+
 fn build_root() -> Result<libcontainer::oci_spec::runtime::Root> {
     RootBuilder::default()
-        .readonly(false)
+        .readonly(true)
         .build()
         .map_err(Into::into)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libcontainer::oci_spec::runtime::LinuxResources;
+
+    fn paths() -> InstancePaths {
+        InstancePaths {
+            app: PathBuf::from("/pkgs/abc"),
+            run: PathBuf::from("/instances/abc-1/run"),
+        }
+    }
+
+    fn resources(limits: ResourceLimits) -> LinuxResources {
+        let config = FunctionConfig {
+            limits: Some(limits),
+            ..Default::default()
+        };
+        let spec = get_spec(
+            &SysUserParms {
+                uid: 1000,
+                gid: 1000,
+            },
+            &config,
+            &paths(),
+        )
+        .unwrap();
+        spec.linux().as_ref().unwrap().resources().clone().unwrap()
+    }
+
+    fn namespaces(mode: NetworkMode) -> Vec<LinuxNamespaceType> {
+        let config = FunctionConfig {
+            network: Some(proto::api::controlplane::NetworkConfig {
+                mode: mode.into(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let spec = get_spec(
+            &SysUserParms {
+                uid: 1000,
+                gid: 1000,
+            },
+            &config,
+            &paths(),
+        )
+        .unwrap();
+        let linux = spec.linux().clone().unwrap();
+        linux
+            .namespaces()
+            .clone()
+            .unwrap()
+            .iter()
+            .map(|ns| ns.typ())
+            .collect()
+    }
+
+    #[test]
+    fn test_host_network_shares_network_namespace() {
+        let namespaces = namespaces(NetworkMode::Host);
+        assert!(!namespaces.contains(&LinuxNamespaceType::Network));
+        assert!(namespaces.contains(&LinuxNamespaceType::User));
+    }
+
+    #[test]
+    fn test_isolated_network_gets_own_namespace() {
+        for mode in [NetworkMode::None, NetworkMode::Egress] {
+            let namespaces = namespaces(mode);
+            assert!(namespaces.contains(&LinuxNamespaceType::Network));
+            assert_eq!(
+                namespaces
+                    .iter()
+                    .filter(|ns| **ns == LinuxNamespaceType::User)
+                    .count(),
+                1
+            );
+        }
+    }
+
+    fn spec_json(security: Option<SecurityConfig>) -> serde_json::Value {
+        let config = FunctionConfig {
+            security,
+            ..Default::default()
+        };
+        let spec = get_spec(
+            &SysUserParms {
+                uid: 1000,
+                gid: 1000,
+            },
+            &config,
+            &paths(),
+        )
+        .unwrap();
+        serde_json::to_value(&spec).unwrap()
+    }
+
+    fn syscall_rule<'a>(spec: &'a serde_json::Value, name: &str) -> Option<&'a serde_json::Value> {
+        spec["linux"]["seccomp"]["syscalls"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|rule| rule["names"].as_array().unwrap().iter().any(|n| n == name))
+    }
+
+    #[test]
+    fn test_spec_is_hardened_by_default() {
+        let spec = spec_json(None);
+
+        let process = &spec["process"];
+        assert_eq!(process["noNewPrivileges"], true);
+        for set in [
+            "bounding",
+            "effective",
+            "permitted",
+            "inheritable",
+            "ambient",
+        ] {
+            assert_eq!(
+                process["capabilities"][set],
+                serde_json::json!([]),
+                "{}",
+                set
+            );
+        }
+
+        let seccomp = &spec["linux"]["seccomp"];
+        assert_eq!(seccomp["defaultAction"], "SCMP_ACT_ERRNO");
+        assert_eq!(seccomp["defaultErrnoRet"], EPERM);
+        assert_eq!(
+            syscall_rule(&spec, "read").unwrap()["action"],
+            "SCMP_ACT_ALLOW"
+        );
+        for denied in ["mount", "unshare", "setns", "ptrace", "bpf", "init_module"] {
+            assert!(syscall_rule(&spec, denied).is_none(), "{}", denied);
+        }
+        let clone = syscall_rule(&spec, "clone").unwrap();
+        assert_eq!(clone["args"][0]["op"], "SCMP_CMP_MASKED_EQ");
+        assert_eq!(clone["args"][0]["valueTwo"], CLONE_NAMESPACE_FLAGS);
+        assert_eq!(syscall_rule(&spec, "clone3").unwrap()["errnoRet"], ENOSYS);
+
+        let masked = spec["linux"]["maskedPaths"].as_array().unwrap();
+        assert!(masked.contains(&serde_json::json!("/proc/kcore")));
+        let readonly = spec["linux"]["readonlyPaths"].as_array().unwrap();
+        assert!(readonly.contains(&serde_json::json!("/proc/sys")));
+    }
+
+    #[test]
+    fn test_spec_applies_security_opt_outs() {
+        let spec = spec_json(Some(SecurityConfig {
+            capabilities: vec!["CAP_NET_BIND_SERVICE".to_string()],
+            allow_new_privileges: true,
+            unmask_proc: true,
+            ..Default::default()
+        }));
+
+        let process = &spec["process"];
+        assert_eq!(process["noNewPrivileges"], false);
+        assert_eq!(
+            process["capabilities"]["bounding"],
+            serde_json::json!(["CAP_NET_BIND_SERVICE"])
+        );
+        assert_eq!(spec["linux"]["maskedPaths"], serde_json::json!([]));
+        assert_eq!(spec["linux"]["readonlyPaths"], serde_json::json!([]));
+        assert!(spec["linux"]["seccomp"].is_object());
+
+        let spec = spec_json(Some(SecurityConfig {
+            extra_syscalls: vec!["ptrace".to_string(), "clone3".to_string()],
+            ..Default::default()
+        }));
+        assert_eq!(
+            syscall_rule(&spec, "ptrace").unwrap()["action"],
+            "SCMP_ACT_ALLOW"
+        );
+        assert_eq!(
+            syscall_rule(&spec, "clone3").unwrap()["action"],
+            "SCMP_ACT_ALLOW"
+        );
+
+        let spec = spec_json(Some(SecurityConfig {
+            seccomp_unconfined: true,
+            ..Default::default()
+        }));
+        assert!(spec["linux"].get("seccomp").is_none());
+    }
+
+    #[test]
+    fn test_spec_rejects_unknown_capability() {
+        let config = FunctionConfig {
+            security: Some(SecurityConfig {
+                capabilities: vec!["CAP_MAKE_COFFEE".to_string()],
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert!(get_spec(&SysUserParms { uid: 0, gid: 0 }, &config, &paths()).is_err());
+    }
+
+    fn mount<'a>(spec: &'a serde_json::Value, destination: &str) -> &'a serde_json::Value {
+        spec["mounts"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|mount| mount["destination"] == destination)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_spec_mounts_bundle_into_readonly_rootfs() {
+        let spec = spec_json(None);
+        assert_eq!(spec["root"]["readonly"], true);
+
+        let app = mount(&spec, "/app");
+        assert_eq!(app["type"], "bind");
+        assert_eq!(app["source"], "/pkgs/abc");
+        assert!(app["options"].as_array().unwrap().contains(&"ro".into()));
+
+        let run = mount(&spec, "/run");
+        assert_eq!(run["source"], "/instances/abc-1/run");
+        assert!(run["options"].as_array().unwrap().contains(&"rw".into()));
+
+        let tmp = mount(&spec, "/tmp");
+        assert_eq!(tmp["type"], "tmpfs");
+        assert!(
+            tmp["options"]
+                .as_array()
+                .unwrap()
+                .contains(&"size=64m".into())
+        );
+    }
+
+    #[test]
+    fn test_spec_uses_function_tmp_size() {
+        let config = FunctionConfig {
+            tmp_size_mb: 512,
+            ..Default::default()
+        };
+        let spec = get_spec(&SysUserParms { uid: 0, gid: 0 }, &config, &paths()).unwrap();
+        let spec = serde_json::to_value(&spec).unwrap();
+        let tmp = mount(&spec, "/tmp");
+        assert!(
+            tmp["options"]
+                .as_array()
+                .unwrap()
+                .contains(&"size=512m".into())
+        );
+    }
+
+    #[test]
+    fn test_spec_injects_env() {
+        let config = FunctionConfig {
+            env: [
+                ("LOG_LEVEL".to_string(), "debug".to_string()),
+                ("API_KEY".to_string(), "s3cret".to_string()),
+                ("PATH".to_string(), "/app/bin".to_string()),
+            ]
+            .into(),
+            ..Default::default()
+        };
+        let spec = get_spec(&SysUserParms { uid: 0, gid: 0 }, &config, &paths()).unwrap();
+        let spec = serde_json::to_value(&spec).unwrap();
+
+        assert_eq!(
+            spec["process"]["env"],
+            serde_json::json!(["API_KEY=s3cret", "LOG_LEVEL=debug", "PATH=/app/bin"])
+        );
+
+        let spec = spec_json(None);
+        assert_eq!(
+            spec["process"]["env"],
+            serde_json::json!([format!("PATH={}", DEFAULT_PATH)])
+        );
+    }
+
+    #[test]
+    fn test_spec_without_limits_is_unlimited() {
+        let resources = resources(ResourceLimits::default());
+        assert!(resources.memory().is_none());
+        assert!(resources.cpu().is_none());
+        assert!(resources.pids().is_none());
+        assert!(resources.block_io().is_none());
+    }
+
+    #[test]
+    fn test_spec_renders_resource_limits() {
+        let resources = resources(ResourceLimits {
+            memory_mb: 128,
+            cpu_millis: 500,
+            cpu_shares: 512,
+            pids_limit: 64,
+            io_weight: 200,
+        });
+
+        let memory = resources.memory().as_ref().unwrap();
+        assert_eq!(memory.limit(), Some(128 * 1024 * 1024));
+        assert_eq!(memory.swap(), Some(128 * 1024 * 1024));
+
+        let cpu = resources.cpu().as_ref().unwrap();
+        assert_eq!(cpu.quota(), Some(50_000));
+        assert_eq!(cpu.period(), Some(100_000));
+        assert_eq!(cpu.shares(), Some(512));
+
+        assert_eq!(resources.pids().as_ref().unwrap().limit(), 64);
+        assert_eq!(resources.block_io().as_ref().unwrap().weight(), Some(200));
+    }
+
+    #[test]
+    fn test_spec_rejects_overflowing_memory_limit() {
+        let config = FunctionConfig {
+            limits: Some(ResourceLimits {
+                memory_mb: u64::MAX / 1024,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let user = SysUserParms {
+            uid: 1000,
+            gid: 1000,
+        };
+        assert!(get_spec(&user, &config, &paths()).is_err());
+    }
+
+    #[test]
+    fn test_spec_renders_only_set_limits() {
+        let resources = resources(ResourceLimits {
+            cpu_shares: 256,
+            ..Default::default()
+        });
+
+        assert!(resources.memory().is_none());
+        assert!(resources.pids().is_none());
+        let cpu = resources.cpu().as_ref().unwrap();
+        assert_eq!((cpu.quota(), cpu.shares()), (None, Some(256)));
+    }
+}